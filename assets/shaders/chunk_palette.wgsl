@@ -0,0 +1,98 @@
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+// Red is the particle type, green its variant, rows top to bottom, from Chunk::render_to_index_array
+@group(2) @binding(0) var cells: texture_2d<f32>;
+// Row 0 holds the color for each particle type, row 1 its glow color,
+// row 2 its heat (two bytes, biased by PALETTE_HEAT_BIAS), color variation and whether it is shaded at all
+@group(2) @binding(1) var palette: texture_2d<f32>;
+// Light reaching each particle, from Chunk::render_light_array
+@group(2) @binding(2) var light: texture_2d<f32>;
+@group(2) @binding(3) var light_sampler: sampler;
+// Light strength, color variation, edge shading and heat glow strength
+@group(2) @binding(4) var<uniform> shading: vec4<f32>;
+// World position of the chunk's bottom left cell
+@group(2) @binding(5) var<uniform> chunk_root: vec2<i32>;
+
+// Same as PALETTE_HEAT_BIAS, HEAT_GLOW_MIN_TEMP and HEAT_GLOW_MAX_TEMP in sandworld
+const PALETTE_HEAT_BIAS: i32 = 32768;
+const GLOW_MIN_TEMP: f32 = 300.0;
+const GLOW_MAX_TEMP: f32 = 1200.0;
+
+// Neighbors past the chunk's edge aren't in its texture, the edge cells stand in for them
+fn cell_type(pos: vec2<i32>) -> i32 {
+    let size = vec2<i32>(textureDimensions(cells));
+    let cell = textureLoad(cells, clamp(pos, vec2<i32>(0), size - 1), 0);
+    return i32(round(cell.r * 255.0));
+}
+
+fn heat_of(index: i32) -> i32 {
+    let row = textureLoad(palette, vec2<i32>(index, 2), 0);
+    return i32(round(row.r * 255.0)) + i32(round(row.g * 255.0)) * 256 - PALETTE_HEAT_BIAS;
+}
+
+// Same hash as sandworld's position_noise, from -1 to 1
+fn position_noise(pos: vec2<i32>) -> f32 {
+    var hash = (bitcast<u32>(pos.x) * 0x9E3779B1u) ^ (bitcast<u32>(pos.y) * 0x85EBCA77u);
+    hash ^= hash >> 15u;
+    hash *= 0x2C1B3C6Du;
+    hash ^= hash >> 12u;
+    return f32(hash & 0xFFFFu) / 32767.5 - 1.0;
+}
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(cells));
+    let cell_pos = clamp(vec2<i32>(mesh.uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    let cell = textureLoad(cells, cell_pos, 0);
+
+    let index = i32(round(cell.r * 255.0));
+    let variant = i32(round(cell.g * 255.0));
+    let base = textureLoad(palette, vec2<i32>(index, 0), 0);
+    let glow_color = textureLoad(palette, vec2<i32>(index, 1), 0);
+    let material = textureLoad(palette, vec2<i32>(index, 2), 0);
+    let lit = mix(vec3<f32>(1.0), textureSample(light, light_sampler, mesh.uv).rgb, shading.x);
+
+    // Air and debug overlays are drawn flat
+    if material.a < 0.5 {
+        return vec4<f32>(base.rgb * lit, base.a);
+    }
+
+    // Texture rows run top to bottom, so up in the world is down a row
+    let world_pos = chunk_root + vec2<i32>(cell_pos.x, size.y - 1 - cell_pos.y);
+    var brightness = 1.0;
+
+    // Stored variants 1-7 spread evenly over -1 to 1, otherwise it comes from the cell's position
+    var offset = position_noise(world_pos);
+    if variant != 0 {
+        offset = (f32(variant) - 4.0) / 3.0;
+    }
+    brightness += offset * material.b * shading.y;
+
+    // Same neighbor order and temperature sum as Chunk::caclulate_local_temp
+    let own_heat = heat_of(index);
+    var offsets = array<vec2<i32>, 8>(
+        vec2<i32>(0, -1), vec2<i32>(1, -1), vec2<i32>(1, 0), vec2<i32>(1, 1),
+        vec2<i32>(0, 1), vec2<i32>(-1, 1), vec2<i32>(-1, 0), vec2<i32>(-1, -1),
+    );
+    var temp = 0;
+    var exposed = 0.0;
+    for (var i = 0; i < 8; i++) {
+        let neighbor = cell_type(cell_pos + offsets[i]);
+        if neighbor == 0 {
+            temp += own_heat / 2;
+            // Only the four orthogonal neighbors count towards edge shading
+            if i % 2 == 0 {
+                exposed += 1.0;
+            }
+        }
+        else {
+            temp += heat_of(neighbor);
+        }
+    }
+    brightness -= shading.z * exposed / 4.0;
+
+    let glow = clamp((f32(temp) - GLOW_MIN_TEMP) / (GLOW_MAX_TEMP - GLOW_MIN_TEMP), 0.0, 1.0);
+    let color = mix(base.rgb * brightness, glow_color.rgb, glow * shading.w);
+
+    return vec4<f32>(color * lit, base.a);
+}
@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+use gridmath::GridBounds;
+use sandworld::*;
+
+// Updates waited through for an area to load before the benchmark gives up, well over what loading takes
+const MAX_LOAD_UPDATES: u32 = 100_000;
+
+// Update the world until everything in bounds has been generated and added to the simulation
+pub fn load_world(world: &mut World, bounds: GridBounds) {
+    let corners = [bounds.bottom_left(), bounds.top_left(), bounds.bottom_right(), bounds.top_right()];
+    for _ in 0..MAX_LOAD_UPDATES {
+        if corners.iter().all(|corner| world.contains(*corner)) {
+            return;
+        }
+        world.update(bounds, WorldUpdateOptions { budget: UpdateBudget::Chunks(0), ..Default::default() });
+        std::thread::sleep(Duration::from_micros(100));
+    }
+    panic!("{:?} did not load after {} updates", bounds, MAX_LOAD_UPDATES);
+}
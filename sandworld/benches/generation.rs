@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use gridmath::{GridBounds, GridVec};
+use sandworld::*;
+
+mod common;
+use common::load_world;
+
+const SEED: u32 = 1234;
+
+/*
+    Hides a generator's own fill_chunk so it goes through the default one, calling get_particle for every cell
+    This is how chunks were generated before generators could batch work per chunk
+*/
+struct PerCell<G>(G);
+
+impl<G: WorldGenerator> WorldGenerator for PerCell<G> {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        self.0.get_particle(world_pos)
+    }
+}
+
+// Same settings as the defaults in WorldGenSettings
+fn world_builder() -> WorldBuilder {
+    WorldBuilder::new(SEED, 5000., 1500., 500., 500., 400.)
+}
+
+fn biome_world() -> BiomeWorld {
+    BiomeWorld::new(SEED, Biome::default_table())
+        .with_biome_scale(6000., 0.08)
+        .with_terrain(5000., 1500., 0)
+        .with_caves(500., 0.09)
+}
+
+// Update a new world until the region around the origin has been generated and added to the simulation
+fn load_region(generator: &Arc<dyn WorldGenerator + Send + Sync>) -> World {
+    let mut world = World::new(generator.clone());
+    load_world(&mut world, GridBounds::new_from_extents(GridVec::new(0, 0), GridVec::new(1, 1)));
+    world
+}
+
+/*
+    Loading a region runs every chunk through the generator in LoadingRegion::start_load. Both generators
+    work their terrain height out per column, which fill_chunk does once per chunk column instead of once per cell
+*/
+fn region_generation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("region_generation");
+    group.sample_size(10);
+
+    let generators: [(&str, Arc<dyn WorldGenerator + Send + Sync>); 4] = [
+        ("world_builder/per_cell", Arc::new(PerCell(world_builder()))),
+        ("world_builder/fill_chunk", Arc::new(world_builder())),
+        ("biome_world/per_cell", Arc::new(PerCell(biome_world()))),
+        ("biome_world/fill_chunk", Arc::new(biome_world())),
+    ];
+    for (name, generator) in generators.iter() {
+        group.bench_function(*name, |bench| bench.iter(|| load_region(generator)));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, region_generation);
+criterion_main!(benches);
@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use criterion::{
+    black_box,
+    criterion_group,
+    criterion_main,
+    BatchSize,
+    Criterion
+};
+use gridmath::{gridline::GridLine, GridBounds, GridVec};
+use sandworld::*;
+
+mod common;
+use common::load_world;
+
+// Steps simulated per iteration of the chunk benchmarks, long enough for things to get moving
+const CHUNK_STEPS: usize = 16;
+
+// Benchmark name and a function building the chunk it starts from
+type Scene = (&'static str, fn() -> Chunk);
+
+fn fill(chunk: &mut Chunk, bounds: GridBounds, particle_type: ParticleType) {
+    for pos in bounds.iter() {
+        chunk.set_particle(pos.x as u8, pos.y as u8, Particle::new(particle_type));
+    }
+}
+
+fn rect(x: i32, y: i32, width: i32, height: i32) -> GridBounds {
+    GridBounds::new_from_extents(GridVec::new(x, y), GridVec::new(x + width, y + height))
+}
+
+fn falling_sand() -> Chunk {
+    let mut chunk = Chunk::new(GridVec::new(0, 0));
+    fill(&mut chunk, rect(0, 32, CHUNK_SIZE as i32, 24), ParticleType::Sand);
+    chunk
+}
+
+fn sloshing_water() -> Chunk {
+    let mut chunk = Chunk::new(GridVec::new(0, 0));
+    fill(&mut chunk, rect(0, 0, CHUNK_SIZE as i32, 4), ParticleType::Stone);
+    fill(&mut chunk, rect(0, 4, 24, 40), ParticleType::Water);
+    chunk
+}
+
+fn lava_and_water() -> Chunk {
+    let mut chunk = Chunk::new(GridVec::new(0, 0));
+    fill(&mut chunk, rect(0, 0, CHUNK_SIZE as i32, 4), ParticleType::Stone);
+    fill(&mut chunk, rect(0, 4, 32, 16), ParticleType::Lava);
+    fill(&mut chunk, rect(32, 4, 32, 32), ParticleType::Water);
+    chunk
+}
+
+fn idle_stone() -> Chunk {
+    let mut chunk = Chunk::new(GridVec::new(0, 0));
+    fill(&mut chunk, rect(0, 0, CHUNK_SIZE as i32, 48), ParticleType::Stone);
+    chunk
+}
+
+fn step_chunk(chunk: &mut Chunk, behaviors: &BehaviorRegistry, keep_dirty: bool) {
+    for _ in 0..CHUNK_STEPS {
+        if keep_dirty {
+            chunk.mark_self_dirty();
+        }
+        chunk.commit_updates();
+        chunk.update(behaviors);
+    }
+}
+
+fn chunk_update_benchmark(c: &mut Criterion) {
+    let behaviors = BehaviorRegistry::default();
+    let mut group = c.benchmark_group("chunk_update");
+
+    let scenes: [Scene; 3] = [
+        ("falling_sand", falling_sand),
+        ("sloshing_water", sloshing_water),
+        ("lava_and_water", lava_and_water),
+    ];
+    for (name, scene) in scenes {
+        group.bench_function(name, |bench| bench.iter_batched(
+            scene,
+            |mut chunk| step_chunk(&mut chunk, &behaviors, false),
+            BatchSize::SmallInput,
+        ));
+    }
+
+    // Settled stone never dirties itself, so keep it dirty to measure scanning a chunk where nothing moves,
+    // as happens when a neighbor keeps waking it
+    group.bench_function("idle_stone", |bench| bench.iter_batched(
+        idle_stone,
+        |mut chunk| step_chunk(&mut chunk, &behaviors, true),
+        BatchSize::SmallInput,
+    ));
+
+    group.finish();
+}
+
+fn compression_benchmark(c: &mut Criterion) {
+    let mut chunk = lava_and_water();
+    fill(&mut chunk, rect(8, 40, 48, 16), ParticleType::Sand);
+    let compressed = chunk.compress();
+
+    c.bench_function(
+        "chunk compress",
+        |bench| bench.iter(|| black_box(&chunk).compress())
+    );
+    c.bench_function(
+        "chunk decompress",
+        |bench| bench.iter(|| black_box(&compressed).decompress())
+    );
+}
+
+fn hills() -> Arc<dyn WorldGenerator + Send + Sync> {
+    let ground = Fill(Particle::new(ParticleType::Stone))
+        .masked(|pos: GridVec| (pos.x as f64 * 0.01).sin() * 48. - pos.y as f64, 0.);
+    let sand = Fill(Particle::new(ParticleType::Sand))
+        .masked(|pos: GridVec| (pos.x as f64 * 0.03).cos() * 24. + 8. - pos.y as f64, 0.);
+    Arc::new(ground.layer(sand))
+}
+
+fn region_load_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("region_load");
+    group.sample_size(10);
+
+    // Generation and the structure pass run on the thread pool, this is the time until the world has the region
+    let bounds = rect(0, 0, 1, 1);
+    group.bench_function("generate", |bench| bench.iter(|| {
+        let mut world = World::new(hills());
+        load_world(&mut world, bounds);
+        world
+    }));
+
+    group.finish();
+}
+
+fn loaded_world() -> (World, GridBounds) {
+    let bounds = rect(-(TRUE_REGION_SIZE as i32), -(TRUE_REGION_SIZE as i32), 2 * TRUE_REGION_SIZE as i32 - 1, 2 * TRUE_REGION_SIZE as i32 - 1);
+    let mut world = World::new(hills());
+    load_world(&mut world, bounds);
+    (world, bounds)
+}
+
+fn cast_ray_benchmark(c: &mut Criterion) {
+    let (world, bounds) = loaded_world();
+    let solid = particle_set![ParticleType::Stone, ParticleType::Sand];
+
+    // Straight down into the ground from high above it
+    let hit = GridLine::new(GridVec::new(0, bounds.top_right().y - 1), GridVec::new(0, bounds.bottom_left().y));
+    c.bench_function(
+        "world cast_ray (hit)",
+        |bench| bench.iter(|| world.cast_ray(black_box(&solid), black_box(hit)))
+    );
+
+    // Along the sky across both regions without touching anything
+    let top = bounds.top_right().y - 1;
+    let miss = GridLine::new(GridVec::new(bounds.bottom_left().x, top), GridVec::new(bounds.top_right().x - 1, top));
+    c.bench_function(
+        "world cast_ray (miss)",
+        |bench| bench.iter(|| world.cast_ray(black_box(&solid), black_box(miss)))
+    );
+}
+
+fn brush_benchmark(c: &mut Criterion) {
+    let (mut world, _) = loaded_world();
+    let center = GridVec::new(0, 0);
+
+    c.bench_function(
+        "brush place_circle",
+        |bench| bench.iter(|| world.place_circle(black_box(center), 16, Particle::new(ParticleType::Water), true))
+    );
+    c.bench_function(
+        "brush clear_circle",
+        |bench| bench.iter(|| world.clear_circle(black_box(center), 16))
+    );
+    c.bench_function(
+        "brush temp_change_circle",
+        |bench| bench.iter(|| world.temp_change_circle(black_box(center), 16, 0.5, 400))
+    );
+}
+
+criterion_group!(
+    benches,
+    chunk_update_benchmark,
+    compression_benchmark,
+    region_load_benchmark,
+    cast_ray_benchmark,
+    brush_benchmark,
+);
+criterion_main!(benches);
@@ -0,0 +1,296 @@
+use std::sync::Arc;
+
+use gridmath::GridVec;
+
+use crate::explosions::Explosion;
+use crate::particle::{calculate_local_temp, get_explosive_properties_for_type, Particle, ParticleType};
+
+/*
+    Actions a particle behavior can ask its chunk to carry out
+    All positions are chunk local, the same space as the position handed to the behavior,
+    offsets are relative to the particle being updated
+*/
+#[derive(Debug, Clone)]
+pub enum ChunkCommand {
+    // Place a particle at a position if that spot is currently Air
+    Add((GridVec, ParticleType, u16)),
+    // Replace the default movement rules for this update with these offsets
+    Move(Vec<GridVec>),
+    // As Move, but the particle is removed if none of the offsets were possible
+    MoveOrDestroy(Vec<GridVec>),
+    // Replace the particle with Air
+    Remove,
+    // Replace the particle with a new type and data
+    Mutate(ParticleType, u16),
+    // Trade places with whatever particle is at the offset
+    Swap(GridVec),
+    // Place a particle at an offset from this one if that spot is currently Air
+    Emit((GridVec, ParticleType, u16)),
+    // Expose the particle at the offset to a temperature, possibly causing it to melt or freeze
+    Heat((GridVec, i32)),
+    // Report something to the owner of the world, collected with World::take_particle_events
+    SpawnEvent(u32),
+    // Set off an explosion centered on the particle, carried out by the world after the update
+    Explode(Explosion),
+}
+
+/*
+    Event raised by a particle behavior with ChunkCommand::SpawnEvent
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticleEvent {
+    pub position: GridVec, // World position of the particle that raised the event
+    pub particle_type: ParticleType,
+    pub event_id: u32,
+}
+
+/*
+    Custom logic for a particle type, registered with World::set_particle_behavior
+    Updates run in parallel across chunks so implementations must be thread safe
+*/
+pub trait ParticleBehavior: Send + Sync {
+    // Called each time a particle of this type is updated, before temperature and movement
+    // neighbors are the 8 surrounding particle types, clockwise starting from directly above
+    fn update(&self, position: GridVec, particle: Particle, neighbors: &[ParticleType; 8]) -> Vec<ChunkCommand>;
+
+    // Called when a particle of this type is placed by a world edit or another behavior
+    fn on_place(&self, _position: GridVec, _particle: Particle) -> Vec<ChunkCommand> {
+        Vec::new()
+    }
+
+    // Called when a particle of this type is replaced by a world edit or another behavior
+    fn on_destroy(&self, _position: GridVec, _particle: Particle) -> Vec<ChunkCommand> {
+        Vec::new()
+    }
+}
+
+/*
+    Lookup from particle type to the behavior driving it, indexed by type so
+    the per particle lookup during updates stays cheap
+*/
+#[derive(Clone)]
+pub struct BehaviorRegistry {
+    behaviors: Vec<Option<Arc<dyn ParticleBehavior>>>,
+}
+
+impl BehaviorRegistry {
+    pub fn empty() -> Self {
+        BehaviorRegistry {
+            behaviors: vec![None; ParticleType::ALL.len()],
+        }
+    }
+
+    pub fn get(&self, particle_type: ParticleType) -> Option<&Arc<dyn ParticleBehavior>> {
+        self.behaviors[particle_type as usize].as_ref()
+    }
+
+    pub fn set(&mut self, particle_type: ParticleType, behavior: Arc<dyn ParticleBehavior>) {
+        self.behaviors[particle_type as usize] = Some(behavior);
+    }
+
+    pub fn remove(&mut self, particle_type: ParticleType) {
+        self.behaviors[particle_type as usize] = None;
+    }
+
+    // Restore the built in behavior for a type, if it has one
+    pub fn reset(&mut self, particle_type: ParticleType) {
+        self.behaviors[particle_type as usize] = get_builtin_behavior(particle_type);
+    }
+}
+
+impl Default for BehaviorRegistry {
+    fn default() -> Self {
+        let mut registry = BehaviorRegistry::empty();
+        for particle_type in ParticleType::ALL {
+            registry.reset(particle_type);
+        }
+        registry
+    }
+}
+
+fn get_builtin_behavior(particle_type: ParticleType) -> Option<Arc<dyn ParticleBehavior>> {
+    match particle_type {
+        ParticleType::Source => Some(Arc::new(SourceBehavior)),
+        ParticleType::LaserBeam => Some(Arc::new(LaserBeamBehavior)),
+        ParticleType::LaserEmitter => Some(Arc::new(LaserEmitterBehavior)),
+        ParticleType::Gunpowder | ParticleType::Steam => Some(Arc::new(ExplosiveBehavior)),
+        _ => None
+    }
+}
+
+struct SourceBehavior;
+struct LaserBeamBehavior;
+struct LaserEmitterBehavior;
+struct ExplosiveBehavior;
+
+fn laser_direction(direction: u8) -> GridVec {
+    match direction {
+        1 => GridVec::new(1, 0),
+        2 => GridVec::new(0, -1),
+        3 => GridVec::new(-1, 0),
+        _ => GridVec::new(0, 1),
+    }
+}
+
+impl ParticleBehavior for SourceBehavior {
+    fn update(&self, _position: GridVec, particle: Particle, neighbors: &[ParticleType; 8]) -> Vec<ChunkCommand> {
+        let data_val = particle.mode();
+        if data_val == 0 {
+            let mut new_val = 0;
+            for part in neighbors {
+                new_val = match  part {
+                    ParticleType::Water => 1,
+                    ParticleType::Lava => 2,
+                    ParticleType::Sand => 3,
+                    ParticleType::Gravel => 4,
+                    ParticleType::Steam => 5,
+                    _ => 0,
+                };
+                if new_val != 0 {
+                    break;
+                }
+            }
+            vec![ChunkCommand::Mutate(particle.particle_type, particle.with_mode(new_val).data())]
+        }
+        else {
+            let emit_type = match data_val {
+                1 => ParticleType::Water,
+                2 => ParticleType::Lava,
+                3 => ParticleType::Sand,
+                4 => ParticleType::Gravel,
+                5 => ParticleType::Steam,
+                _ => ParticleType::Air,
+            };
+
+            vec![
+                ChunkCommand::Emit((GridVec::new(-1, 0), emit_type, 0)),
+                ChunkCommand::Emit((GridVec::new(1, 0), emit_type, 0)),
+                ChunkCommand::Emit((GridVec::new(0, -1), emit_type, 0)),
+                ChunkCommand::Emit((GridVec::new(0, 1), emit_type, 0)),
+            ]
+        }
+    }
+}
+
+impl ParticleBehavior for LaserBeamBehavior {
+    fn update(&self, _position: GridVec, particle: Particle, _neighbors: &[ParticleType; 8]) -> Vec<ChunkCommand> {
+        vec![
+            ChunkCommand::MoveOrDestroy(vec![laser_direction(particle.direction())])
+        ]
+    }
+}
+
+impl ParticleBehavior for LaserEmitterBehavior {
+    fn update(&self, _position: GridVec, particle: Particle, _neighbors: &[ParticleType; 8]) -> Vec<ChunkCommand> {
+        let beam = Particle::new(ParticleType::LaserBeam).with_direction(particle.direction());
+        vec![
+            ChunkCommand::Emit((laser_direction(particle.direction()), beam.particle_type, beam.data())),
+        ]
+    }
+}
+
+// Detonates once hot enough, see get_explosive_properties_for_type
+impl ParticleBehavior for ExplosiveBehavior {
+    fn update(&self, _position: GridVec, particle: Particle, neighbors: &[ParticleType; 8]) -> Vec<ChunkCommand> {
+        let Some(properties) = get_explosive_properties_for_type(particle.particle_type) else {
+            return Vec::new();
+        };
+
+        if properties.min_pressure > 0 {
+            let pressure = neighbors.iter().filter(|neighbor| **neighbor == particle.particle_type).count();
+            if pressure < properties.min_pressure as usize || neighbors.contains(&ParticleType::Air) {
+                return Vec::new();
+            }
+        }
+
+        if calculate_local_temp(particle.particle_type, neighbors) < properties.ignite_temp {
+            return Vec::new();
+        }
+
+        match Explosion::for_type(particle.particle_type) {
+            Some(explosion) => vec![ChunkCommand::Remove, ChunkCommand::Explode(explosion)],
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::behavior::*;
+    use crate::chunk::Chunk;
+
+    struct RiseAndReport;
+
+    impl ParticleBehavior for RiseAndReport {
+        fn update(&self, _position: GridVec, _particle: Particle, _neighbors: &[ParticleType; 8]) -> Vec<ChunkCommand> {
+            vec![ChunkCommand::SpawnEvent(7), ChunkCommand::Swap(GridVec::new(0, 1))]
+        }
+
+        fn on_place(&self, _position: GridVec, _particle: Particle) -> Vec<ChunkCommand> {
+            vec![ChunkCommand::Emit((GridVec::new(1, 0), ParticleType::Stone, 0))]
+        }
+    }
+
+    #[test]
+    fn default_registry_has_builtins() {
+        let registry = BehaviorRegistry::default();
+        assert!(registry.get(ParticleType::Source).is_some());
+        assert!(registry.get(ParticleType::LaserEmitter).is_some());
+        assert!(registry.get(ParticleType::Sand).is_none());
+    }
+
+    #[test]
+    fn registered_behavior_drives_updates() {
+        let mut registry = BehaviorRegistry::default();
+        registry.set(ParticleType::Sand, Arc::new(RiseAndReport));
+
+        let mut chunk = Chunk::new(GridVec::new(1, 0));
+        chunk.set_particle(10, 10, Particle::new(ParticleType::Sand));
+        chunk.commit_updates();
+        chunk.update(&registry);
+
+        assert_eq!(chunk.get_particle(10, 10).particle_type, ParticleType::Air);
+        assert_eq!(chunk.get_particle(10, 11).particle_type, ParticleType::Sand);
+        assert_eq!(chunk.events.len(), 1);
+        assert_eq!(chunk.events[0].event_id, 7);
+        assert_eq!(chunk.events[0].position, GridVec::new(74, 10));
+    }
+
+    #[test]
+    fn heated_explosives_detonate() {
+        let registry = BehaviorRegistry::default();
+
+        let mut chunk = Chunk::new(GridVec::new(0, 1));
+        // Emitters stay put and never cool, unlike lava which can freeze or flow away before the gunpowder updates
+        chunk.set_particle(10, 10, Particle::new(ParticleType::Gunpowder));
+        chunk.set_particle(11, 10, Particle::new(ParticleType::LaserEmitter));
+        chunk.commit_updates();
+        chunk.update(&registry);
+
+        assert_eq!(chunk.get_particle(10, 10).particle_type, ParticleType::Air);
+        assert_eq!(chunk.explosions.len(), 1);
+        assert_eq!(chunk.explosions[0].0, GridVec::new(10, 74));
+
+        // Steam only goes off when it is packed in with no room to rise
+        let hot = [ParticleType::Lava, ParticleType::Air, ParticleType::Steam, ParticleType::Steam, ParticleType::Steam, ParticleType::Steam, ParticleType::Steam, ParticleType::Steam];
+        let steam = registry.get(ParticleType::Steam).unwrap();
+        assert!(steam.update(GridVec::new(0, 0), Particle::new(ParticleType::Steam), &hot).is_empty());
+
+        let confined = [ParticleType::Lava, ParticleType::Stone, ParticleType::Steam, ParticleType::Steam, ParticleType::Steam, ParticleType::Steam, ParticleType::Steam, ParticleType::Steam];
+        let commands = steam.update(GridVec::new(0, 0), Particle::new(ParticleType::Steam), &confined);
+        assert!(matches!(commands[..], [ChunkCommand::Remove, ChunkCommand::Explode(_)]));
+    }
+
+    #[test]
+    fn place_hook_runs_on_replace() {
+        let mut registry = BehaviorRegistry::default();
+        registry.set(ParticleType::Sand, Arc::new(RiseAndReport));
+
+        let mut chunk = Chunk::new(GridVec::new(0, 0));
+        chunk.replace_with_hooks(5, 5, Particle::new(ParticleType::Sand), Some(&registry), &mut rand::thread_rng());
+        assert_eq!(chunk.get_particle(6, 5).particle_type, ParticleType::Stone);
+
+        chunk.replace_with_hooks(20, 20, Particle::new(ParticleType::Sand), None, &mut rand::thread_rng());
+        assert_eq!(chunk.get_particle(21, 20).particle_type, ParticleType::Air);
+    }
+}
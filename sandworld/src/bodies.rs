@@ -0,0 +1,387 @@
+use gridmath::*;
+
+use crate::particle::*;
+use crate::particle_set;
+
+pub type BodyId = u64;
+
+// Particles a body can't pass through, anything else is pushed aside when it comes to rest
+pub static BODY_COLLIDES: ParticleSet = SOLID_MATS.union(POWDER_MATS).union(particle_set![
+    ParticleType::Source,
+    ParticleType::LaserEmitter,
+    ParticleType::Boundary,
+    ParticleType::RegionBoundary
+]);
+
+// Cells searched upwards for space when a settling body lands on something that moved under it
+const SETTLE_SEARCH_HEIGHT: i32 = 16;
+
+/*
+    Tuning for rigid bodies, set with World::set_body_options
+    Speeds are in cells per second
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct BodyOptions {
+    pub gravity: f32,
+    // Fraction of the speed into a surface kept as a bounce
+    pub restitution: f32,
+    pub friction: f32,
+    // Hitting something faster than this breaks the body apart
+    pub shatter_speed: f32,
+    // Bodies moving slower than this for rest_time seconds are written back into the grid
+    pub rest_speed: f32,
+    pub rest_time: f32,
+}
+
+impl Default for BodyOptions {
+    fn default() -> Self {
+        BodyOptions {
+            gravity: 100.,
+            restitution: 0.2,
+            friction: 0.6,
+            shatter_speed: 220.,
+            rest_speed: 4.,
+            rest_time: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BodyEvent {
+    // The body came to rest and its particles are back in the grid
+    Settled(BodyId),
+    // The body broke apart on impact, leaving loose particles behind
+    Shattered(BodyId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BodyStep {
+    Moving,
+    Resting,
+    Shattered,
+}
+
+/*
+    A group of particles lifted out of the grid that moves as one rigid piece
+    Cells keep the grid positions they were taken from, relative to the bottom left of the body,
+    and the body turns about its center of mass
+*/
+#[derive(Debug, Clone)]
+pub struct ParticleBody {
+    id: BodyId,
+    cells: Vec<(GridVec, Particle)>,
+    size: GridVec,
+    center_of_mass: [f32; 2], // Local, from the bottom left corner of the body
+    inertia: f32,
+    pub position: [f32; 2], // World position of the center of mass
+    pub rotation: f32, // Radians counterclockwise
+    pub velocity: [f32; 2],
+    pub angular_velocity: f32,
+    rest_timer: f32,
+}
+
+fn rotate(v: [f32; 2], angle: f32) -> [f32; 2] {
+    let (sin, cos) = angle.sin_cos();
+    [v[0] * cos - v[1] * sin, v[0] * sin + v[1] * cos]
+}
+
+fn cross(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+fn dot(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+// Broken down form of a material, for bodies that shatter
+pub fn get_shattered_type(particle_type: ParticleType) -> ParticleType {
+    match particle_type {
+        ParticleType::Stone => ParticleType::Gravel,
+        ParticleType::Glass => ParticleType::Sand,
+        ParticleType::Ice => ParticleType::Snow,
+        _ => particle_type,
+    }
+}
+
+impl ParticleBody {
+    // Cells are world positions, returns None if there are none
+    pub(crate) fn new(id: BodyId, cells: Vec<(GridVec, Particle)>) -> Option<Self> {
+        if cells.is_empty() {
+            return None;
+        }
+
+        let bounds = GridBounds::containing(&cells.iter().map(|(pos, _)| *pos).collect());
+        let root = bounds.bottom_left();
+        let cells: Vec<(GridVec, Particle)> = cells.into_iter().map(|(pos, particle)| (pos - root, particle)).collect();
+
+        let mass = cells.len() as f32;
+        let mut center_of_mass = [0., 0.];
+        for (local, _) in cells.iter() {
+            center_of_mass[0] += (local.x as f32 + 0.5) / mass;
+            center_of_mass[1] += (local.y as f32 + 0.5) / mass;
+        }
+
+        // Each cell is a unit square, so add its own inertia about its center
+        let mut inertia = 0.;
+        for (local, _) in cells.iter() {
+            let offset = [local.x as f32 + 0.5 - center_of_mass[0], local.y as f32 + 0.5 - center_of_mass[1]];
+            inertia += dot(offset, offset) + 1. / 6.;
+        }
+
+        Some(ParticleBody {
+            id,
+            cells,
+            size: GridVec::new(bounds.width() as i32, bounds.height() as i32),
+            center_of_mass,
+            inertia,
+            position: [root.x as f32 + center_of_mass[0], root.y as f32 + center_of_mass[1]],
+            rotation: 0.,
+            velocity: [0., 0.],
+            angular_velocity: 0.,
+            rest_timer: 0.,
+        })
+    }
+
+    pub fn id(&self) -> BodyId {
+        self.id
+    }
+
+    pub fn cells(&self) -> &[(GridVec, Particle)] {
+        &self.cells
+    }
+
+    // Size of the unrotated body in cells
+    pub fn get_size(&self) -> GridVec {
+        self.size
+    }
+
+    pub fn get_center_of_mass(&self) -> [f32; 2] {
+        self.center_of_mass
+    }
+
+    fn mass(&self) -> f32 {
+        self.cells.len() as f32
+    }
+
+    fn get_world_cell(&self, local: GridVec, position: [f32; 2], rotation: f32) -> GridVec {
+        let offset = rotate([local.x as f32 + 0.5 - self.center_of_mass[0], local.y as f32 + 0.5 - self.center_of_mass[1]], rotation);
+        GridVec::new((position[0] + offset[0]).floor() as i32, (position[1] + offset[1]).floor() as i32)
+    }
+
+    // Grid cells the body covers at its current position
+    pub fn get_world_cells(&self) -> Vec<(GridVec, Particle)> {
+        self.cells.iter().map(|(local, particle)| (self.get_world_cell(*local, self.position, self.rotation), *particle)).collect()
+    }
+
+    fn get_contacts(&self, position: [f32; 2], rotation: f32, collides: &impl Fn(GridVec) -> bool) -> Vec<GridVec> {
+        self.cells.iter()
+            .map(|(local, _)| self.get_world_cell(*local, position, rotation))
+            .filter(|pos| collides(*pos))
+            .collect()
+    }
+
+    // Velocity of a point on the body, given by its offset from the center of mass
+    fn get_point_velocity(&self, offset: [f32; 2]) -> [f32; 2] {
+        [self.velocity[0] - self.angular_velocity * offset[1], self.velocity[1] + self.angular_velocity * offset[0]]
+    }
+
+    fn apply_impulse(&mut self, offset: [f32; 2], impulse: [f32; 2]) {
+        self.velocity[0] += impulse[0] / self.mass();
+        self.velocity[1] += impulse[1] / self.mass();
+        self.angular_velocity += cross(offset, impulse) / self.inertia;
+    }
+
+    /*
+        Advance the body, stepping a cell or less at a time so it can't tunnel through thin walls
+        On contact the step is undone and an impulse applied at the middle of the overlapping cells
+    */
+    pub(crate) fn step(&mut self, dt: f32, options: &BodyOptions, collides: impl Fn(GridVec) -> bool) -> BodyStep {
+        self.velocity[1] -= options.gravity * dt;
+
+        let radius = (self.size.x.max(self.size.y) as f32) * 0.75;
+        let travel = dot(self.velocity, self.velocity).sqrt() * dt + self.angular_velocity.abs() * radius * dt;
+        let substeps = travel.ceil().max(1.) as u32;
+        let sub_dt = dt / substeps as f32;
+
+        for _ in 0..substeps {
+            let next_position = [self.position[0] + self.velocity[0] * sub_dt, self.position[1] + self.velocity[1] * sub_dt];
+            let next_rotation = self.rotation + self.angular_velocity * sub_dt;
+
+            let contacts = self.get_contacts(next_position, next_rotation, &collides);
+            if contacts.is_empty() {
+                self.position = next_position;
+                self.rotation = next_rotation;
+                continue;
+            }
+
+            let mut contact_point = [0., 0.];
+            for contact in contacts.iter() {
+                contact_point[0] += (contact.x as f32 + 0.5) / contacts.len() as f32;
+                contact_point[1] += (contact.y as f32 + 0.5) / contacts.len() as f32;
+            }
+            let offset = [contact_point[0] - self.position[0], contact_point[1] - self.position[1]];
+            let offset_length = dot(offset, offset).sqrt();
+            let normal = if offset_length > 0.001 { [-offset[0] / offset_length, -offset[1] / offset_length] } else { [0., 1.] };
+
+            let point_velocity = self.get_point_velocity(offset);
+            let normal_speed = dot(point_velocity, normal);
+            if normal_speed < 0. {
+                if -normal_speed > options.shatter_speed {
+                    return BodyStep::Shattered;
+                }
+
+                let normal_mass = 1. / self.mass() + cross(offset, normal).powi(2) / self.inertia;
+                let normal_impulse = -(1. + options.restitution) * normal_speed / normal_mass;
+                self.apply_impulse(offset, [normal[0] * normal_impulse, normal[1] * normal_impulse]);
+
+                let point_velocity = self.get_point_velocity(offset);
+                let tangent_velocity = [point_velocity[0] - normal[0] * dot(point_velocity, normal), point_velocity[1] - normal[1] * dot(point_velocity, normal)];
+                let tangent_speed = dot(tangent_velocity, tangent_velocity).sqrt();
+                if tangent_speed > 0.001 {
+                    let tangent = [tangent_velocity[0] / tangent_speed, tangent_velocity[1] / tangent_speed];
+                    let tangent_mass = 1. / self.mass() + cross(offset, tangent).powi(2) / self.inertia;
+                    let friction_impulse = (tangent_speed / tangent_mass).min(options.friction * normal_impulse);
+                    self.apply_impulse(offset, [-tangent[0] * friction_impulse, -tangent[1] * friction_impulse]);
+                }
+            }
+
+            // Something moved into the body where it already was, lift it clear
+            if !self.get_contacts(self.position, self.rotation, &collides).is_empty() {
+                self.position[1] += 1.;
+            }
+            break;
+        }
+
+        let speed = dot(self.velocity, self.velocity).sqrt() + self.angular_velocity.abs() * radius;
+        if speed < options.rest_speed {
+            self.rest_timer += dt;
+        }
+        else {
+            self.rest_timer = 0.;
+        }
+
+        if self.rest_timer >= options.rest_time { BodyStep::Resting } else { BodyStep::Moving }
+    }
+
+    /*
+        Where each particle goes when the body returns to the grid
+        Cells that land on something solid, or on another cell of the same body, look upwards for space
+        Gives None if any particle has nowhere to go, rather than losing it, so the body can stay out of the grid
+        until the space around it clears
+    */
+    pub(crate) fn get_settle_cells(&self, collides: impl Fn(GridVec) -> bool, shattered: bool) -> Option<Vec<(GridVec, Particle)>> {
+        let mut taken = std::collections::HashSet::new();
+        let mut settled = Vec::with_capacity(self.cells.len());
+
+        for (world_pos, particle) in self.get_world_cells() {
+            let particle = if shattered { Particle::new(get_shattered_type(particle.particle_type)) } else { particle };
+
+            let target = (0..SETTLE_SEARCH_HEIGHT)
+                .map(|lift| world_pos + GridVec::new(0, lift))
+                .find(|target| !collides(*target) && !taken.contains(target))?;
+            taken.insert(target);
+            settled.push((target, particle));
+        }
+
+        Some(settled)
+    }
+
+    // Colors of the unrotated body, rows from the top down to suit image formats
+    pub fn render_to_color_array(&self) -> Vec<u8> {
+        let mut colors = vec![0; (self.size.x * self.size.y * 4) as usize];
+        for (local, particle) in self.cells.iter() {
+            let index = (((self.size.y - 1 - local.y) * self.size.x + local.x) * 4) as usize;
+            colors[index..index + 4].copy_from_slice(&get_color_for_type(particle.particle_type));
+        }
+        colors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bodies::*;
+
+    fn floor_at(height: i32) -> impl Fn(GridVec) -> bool {
+        move |pos: GridVec| pos.y < height
+    }
+
+    fn block(root: GridVec, size: i32) -> Vec<(GridVec, Particle)> {
+        let mut cells = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                cells.push((root + GridVec::new(x, y), Particle::new(ParticleType::Stone)));
+            }
+        }
+        cells
+    }
+
+    #[test]
+    fn body_falls_and_comes_to_rest() {
+        let options = BodyOptions::default();
+        let mut body = ParticleBody::new(0, block(GridVec::new(10, 40), 4)).unwrap();
+        assert_eq!(body.get_size(), GridVec::new(4, 4));
+
+        let mut result = BodyStep::Moving;
+        for _ in 0..600 {
+            result = body.step(1. / 60., &options, floor_at(0));
+            if result != BodyStep::Moving {
+                break;
+            }
+        }
+
+        assert_eq!(result, BodyStep::Resting);
+        let settled = body.get_settle_cells(floor_at(0), false).unwrap();
+        assert_eq!(settled.len(), 16);
+        assert!(settled.iter().all(|(pos, particle)| pos.y >= 0 && pos.y < 8 && particle.particle_type == ParticleType::Stone));
+    }
+
+    #[test]
+    fn heavy_impact_shatters() {
+        let options = BodyOptions::default();
+        let mut body = ParticleBody::new(0, block(GridVec::new(0, 10), 3)).unwrap();
+        body.velocity = [0., -options.shatter_speed * 2.];
+
+        let mut result = BodyStep::Moving;
+        for _ in 0..10 {
+            result = body.step(1. / 60., &options, floor_at(0));
+            if result != BodyStep::Moving {
+                break;
+            }
+        }
+
+        assert_eq!(result, BodyStep::Shattered);
+        let pieces = body.get_settle_cells(floor_at(0), true).unwrap();
+        assert_eq!(pieces.len(), 9);
+        assert!(pieces.iter().all(|(_, particle)| particle.particle_type == ParticleType::Gravel));
+    }
+
+    #[test]
+    fn offset_landing_tips_over() {
+        let options = BodyOptions::default();
+        // An L shape whose heavy end hangs off a ledge
+        let mut cells = block(GridVec::new(0, 20), 2);
+        cells.extend(block(GridVec::new(2, 20), 2));
+        cells.extend(block(GridVec::new(2, 22), 2));
+        let mut body = ParticleBody::new(0, cells).unwrap();
+
+        let ledge = |pos: GridVec| pos.y < 0 || (pos.x < 1 && pos.y < 18);
+        for _ in 0..30 {
+            body.step(1. / 60., &options, ledge);
+        }
+
+        assert!(body.rotation.abs() > 0.01);
+    }
+
+    #[test]
+    fn crowded_bodies_wait_for_space() {
+        let body = ParticleBody::new(0, block(GridVec::new(0, 10), 3)).unwrap();
+
+        // Buried deeper than the search reaches, nothing is placed rather than some of it
+        assert!(body.get_settle_cells(floor_at(40), false).is_none());
+        assert!(body.get_settle_cells(floor_at(40), true).is_none());
+
+        let settled = body.get_settle_cells(floor_at(12), false).unwrap();
+        assert_eq!(settled.len(), 9);
+        assert!(settled.iter().all(|(pos, _)| pos.y >= 12));
+    }
+}
@@ -7,8 +7,15 @@ use gridmath::gridline::GridLine;
 use rand::{Rng, rngs::ThreadRng};
 use crate::collisions::HitInfo;
 use crate::region::REGION_SIZE;
+use crate::behavior::{BehaviorRegistry, ChunkCommand, ParticleEvent};
+use crate::explosions::Explosion;
+use crate::lighting::LightMap;
 use crate::{particle::*, particle_set, World, WorldGenerator};
 
+// Local temperatures drawn at full strength by render_temperature_array, anything between them fades out towards 0
+const OVERLAY_HOT_TEMP: i32 = 1024;
+const OVERLAY_COLD_TEMP: i32 = -64;
+
 #[derive(Debug)]
 pub struct Chunk {
     pub position: GridVec,
@@ -17,6 +24,28 @@ pub struct Chunk {
     pub(crate) dirty: RwLock<Option<GridBounds>>,
     pub(crate) update_this_frame: Option<GridBounds>,
     pub(crate) updated_last_frame: Option<GridBounds>,
+    pub(crate) events: Vec<ParticleEvent>,
+    pub(crate) explosions: Vec<(GridVec, Explosion)>, // World positions of explosions set off during the update
+    pub(crate) light: Option<Box<LightMap>>,
+    pub(crate) light_dirty: Option<GridBounds>, // Cells whose light needs recomputing, because of changes here or in a neighbor
+}
+
+// What the chunk needs to know about a particle after carrying out its behavior commands
+#[derive(Default)]
+pub(crate) struct CommandOutcome {
+    move_override: Option<Vec<GridVec>>,
+    destroy_if_not_moved: bool,
+    relocated: bool,
+}
+
+// Controls for Chunk::render_to_color_array, strengths of 0 disable that effect
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChunkRenderOptions {
+    pub draw_dirty: bool,
+    pub draw_borders: bool,
+    pub color_variation: f32,
+    pub edge_shading: f32,
+    pub heat_glow: f32,
 }
 
 #[derive(Clone)]
@@ -106,6 +135,16 @@ impl Drop for Neighbors {
     }
 }
 
+// Stable value from -1 to 1 for a world position, so per cell color variation doesn't flicker between redraws
+// Generators use it too, to dither between materials the same way every time a chunk is generated
+pub fn position_noise(pos: GridVec) -> f32 {
+    let mut hash = (pos.x as u32).wrapping_mul(0x9E3779B1) ^ (pos.y as u32).wrapping_mul(0x85EBCA77);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2C1B3C6D);
+    hash ^= hash >> 12;
+    (hash & 0xFFFF) as f32 / 32767.5 - 1.
+}
+
 impl CompressedChunk {
     pub fn decompress(&self) -> Chunk {
         let mut created = Chunk {
@@ -115,6 +154,10 @@ impl CompressedChunk {
             dirty: RwLock::new(None),
             update_this_frame: None,
             updated_last_frame: None,
+            events: Vec::new(),
+            explosions: Vec::new(),
+            light: None,
+            light_dirty: Some(Chunk::all_cells()),
         };
 
         match &self.particle_data {
@@ -160,6 +203,10 @@ impl Chunk {
             dirty: RwLock::new(None),
             update_this_frame: None,
             updated_last_frame: None,
+            events: Vec::new(),
+            explosions: Vec::new(),
+            light: None,
+            light_dirty: Some(Chunk::all_cells()),
         };
 
         return created;
@@ -173,16 +220,11 @@ impl Chunk {
 
     pub fn regenerate(&mut self, generator: &Arc<dyn WorldGenerator + Send + Sync>) {
         // println!("generating chunk {}", self.position);
-        for y in 0..CHUNK_SIZE {
-            for x in 0..CHUNK_SIZE {
-                let worldpos = GridVec::new(
-                    x as i32 + (CHUNK_SIZE as i32 * self.position.x),
-                     y as i32 + (CHUNK_SIZE as i32 * self.position.y));
-                
-                self.set_particle_sloppy(x, y, generator.get_particle(worldpos));
-                self.mark_self_dirty();
-            }
+        generator.fill_chunk(self.get_world_root(), &mut self.particles);
+        for particle in self.particles.iter_mut() {
+            particle.set_updated_this_frame(true);
         }
+        self.mark_self_dirty();
     }
 
     pub fn compress(&self) -> CompressedChunk {
@@ -265,6 +307,11 @@ impl Chunk {
         data
     }
     
+    // Every cell of a chunk, as inclusive local bounds
+    pub(crate) fn all_cells() -> GridBounds {
+        GridBounds::new_from_extents(GridVec::new(0, 0), GridVec::new(CHUNK_SIZE as i32 - 1, CHUNK_SIZE as i32 - 1))
+    }
+
     fn get_index_in_chunk(x: u8, y: u8) -> usize {
         return y as usize * CHUNK_SIZE as usize + x as usize;
     }
@@ -284,38 +331,16 @@ impl Chunk {
         return self.particles[Chunk::get_index_in_chunk(x, y)];
     }
 
-    pub fn render_to_color_array(&self, draw_dirty: bool, draw_borders: bool) -> Vec<u8> {
+    pub fn render_to_color_array(&self, options: &ChunkRenderOptions) -> Vec<u8> {
         let mut bytes = Vec::with_capacity(CHUNK_SIZE as usize * CHUNK_SIZE as usize * 4);
 
         for y in 0..CHUNK_SIZE {
             for x in 0..CHUNK_SIZE {
-                let part = self.get_particle(x, CHUNK_SIZE - y - 1).particle_type;
-                let mut color = get_color_for_type(part);
-
-                if draw_borders {
-                    if (x == 0 && self.position.x % REGION_SIZE as i32 == 0) 
-                    || (x == CHUNK_SIZE - 1 && (self.position.x + 1) % REGION_SIZE as i32 == 0) {
-                        color = get_color_for_type(ParticleType::RegionBoundary);
-                    } 
-                    else if x == 0 || x == CHUNK_SIZE - 1 {
-                        color = get_color_for_type(ParticleType::Boundary);
-                    }
-                    else if (y == CHUNK_SIZE - 1 && self.position.y % REGION_SIZE as i32 == 0) 
-                    || (y == 0 && (self.position.y + 1) % REGION_SIZE as i32 == 0) {
-                        color = get_color_for_type(ParticleType::RegionBoundary);
-                    } 
-                    else if y == 0 || y == CHUNK_SIZE - 1 {
-                        color = get_color_for_type(ParticleType::Boundary);
-                    }
-                }
-
-                if draw_dirty {
-                    if let Some(updated_bounds) = self.update_this_frame {
-                        if updated_bounds.is_boundary(GridVec::new(x as i32, (CHUNK_SIZE - y - 1) as i32)) {
-                            color = get_color_for_type(ParticleType::Dirty);
-                        }
-                    }
-                }
+                let local_y = CHUNK_SIZE - y - 1;
+                let color = match self.get_overlay_type(x, local_y, options) {
+                    Some(overlay) => get_color_for_type(overlay),
+                    None => self.shade_particle(x, local_y, self.get_particle(x, local_y), options),
+                };
 
                 bytes.push(color[0]);
                 bytes.push(color[1]);
@@ -327,6 +352,178 @@ impl Chunk {
         bytes
     }
 
+    /*
+        Heatmap of the local temperature each particle is simulated with, as RGBA rows ordered top to bottom
+        like render_to_color_array. Neutral cells are transparent, heat goes from red to yellow and cold is blue
+    */
+    pub fn render_temperature_array(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(CHUNK_SIZE as usize * CHUNK_SIZE as usize * 4);
+
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let temp = self.caclulate_local_temp(x as i16, (CHUNK_SIZE - y - 1) as i16);
+                let color = if temp >= 0 {
+                    let heat = (temp as f32 / OVERLAY_HOT_TEMP as f32).sqrt().min(1.);
+                    [0xff, (heat * 0xff as f32) as u8, 0x20, (heat * 0xc0 as f32) as u8]
+                }
+                else {
+                    let cold = (temp as f32 / OVERLAY_COLD_TEMP as f32).sqrt().min(1.);
+                    [0x30, 0x70, 0xff, (cold * 0xc0 as f32) as u8]
+                };
+                bytes.extend_from_slice(&color);
+            }
+        }
+
+        bytes
+    }
+
+    /*
+        Two bytes per particle, rows ordered top to bottom like render_to_color_array
+        The first is the particle type to look up in the palette from build_palette, or a debug overlay's type,
+        and the second its variant. Color variation, edge shading and heat glow are all worked out by the chunk
+        shader from this and the neighboring cells, so only draw_dirty and draw_borders are used from the options
+    */
+    pub fn render_to_index_array(&self, options: &ChunkRenderOptions) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(CHUNK_SIZE as usize * CHUNK_SIZE as usize * 2);
+        let draw_overlays = options.draw_dirty || options.draw_borders;
+
+        for y in 0..CHUNK_SIZE {
+            let local_y = CHUNK_SIZE - y - 1;
+            let row_start = Chunk::get_index_in_chunk(0, local_y);
+            for (x, part) in self.particles[row_start..row_start + CHUNK_SIZE as usize].iter().enumerate() {
+                let overlay = if draw_overlays { self.get_overlay_type(x as u8, local_y, options) } else { None };
+                match overlay {
+                    Some(overlay) => bytes.extend_from_slice(&[overlay as u8, 0]),
+                    None => bytes.extend_from_slice(&[part.particle_type as u8, part.variant()]),
+                }
+            }
+        }
+
+        bytes
+    }
+
+    pub fn get_light(&self) -> Option<&LightMap> {
+        self.light.as_deref()
+    }
+
+    /*
+        RGBA light reaching each particle, rows ordered top to bottom like render_to_color_array
+        Emissive particles are drawn fully lit, and nothing drops below the ambient level
+        Chunks that haven't been lit yet are left at the ambient level
+    */
+    pub fn render_light_array(&self, ambient: u8) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(CHUNK_SIZE as usize * CHUNK_SIZE as usize * 4);
+
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let local_y = CHUNK_SIZE - y - 1;
+                let light = match &self.light {
+                    Some(_) if get_emission_for_type(self.get_particle(x, local_y).particle_type) != [0, 0, 0] => [0xff; 3],
+                    Some(light_map) => light_map.get(x, local_y),
+                    None => [0; 3],
+                };
+
+                bytes.push(light[0].max(ambient));
+                bytes.push(light[1].max(ambient));
+                bytes.push(light[2].max(ambient));
+                bytes.push(0xff);
+            }
+        }
+
+        bytes
+    }
+
+    // Debug overlay drawn over a cell instead of its particle, if any
+    fn get_overlay_type(&self, x: u8, y: u8, options: &ChunkRenderOptions) -> Option<ParticleType> {
+        if options.draw_dirty {
+            if let Some(updated_bounds) = self.update_this_frame {
+                if updated_bounds.is_boundary(GridVec::new(x as i32, y as i32)) {
+                    return Some(ParticleType::Dirty);
+                }
+            }
+        }
+
+        if options.draw_borders {
+            let region_left = self.position.x.rem_euclid(REGION_SIZE as i32) == 0;
+            let region_right = (self.position.x + 1).rem_euclid(REGION_SIZE as i32) == 0;
+            let region_bottom = self.position.y.rem_euclid(REGION_SIZE as i32) == 0;
+            let region_top = (self.position.y + 1).rem_euclid(REGION_SIZE as i32) == 0;
+
+            if (x == 0 && region_left) || (x == CHUNK_SIZE - 1 && region_right) {
+                return Some(ParticleType::RegionBoundary);
+            }
+            else if x == 0 || x == CHUNK_SIZE - 1 {
+                return Some(ParticleType::Boundary);
+            }
+            else if (y == 0 && region_bottom) || (y == CHUNK_SIZE - 1 && region_top) {
+                return Some(ParticleType::RegionBoundary);
+            }
+            else if y == 0 || y == CHUNK_SIZE - 1 {
+                return Some(ParticleType::Boundary);
+            }
+        }
+
+        None
+    }
+
+    fn get_brightness(&self, x: u8, y: u8, part: Particle, options: &ChunkRenderOptions) -> f32 {
+        if part.particle_type == ParticleType::Air {
+            return 1.;
+        }
+
+        let mut brightness = 1.;
+
+        if options.color_variation > 0. {
+            let variation = get_color_variation_for_type(part.particle_type) * options.color_variation;
+            let offset = if part.variant() != 0 {
+                // Stored variants 1-7 spread evenly over -1 to 1
+                (part.variant() as f32 - 4.) / 3.
+            }
+            else {
+                let world_pos = self.get_world_root() + GridVec::new(x as i32, y as i32);
+                position_noise(world_pos)
+            };
+            brightness += offset * variation;
+        }
+
+        if options.edge_shading > 0. {
+            let (x, y) = (x as i16, y as i16);
+            let exposed = [(0, 1), (1, 0), (0, -1), (-1, 0)].iter()
+                .filter(|(dx, dy)| self.get_local_part(x + dx, y + dy) == ParticleType::Air)
+                .count();
+            brightness -= options.edge_shading * (exposed as f32 / 4.);
+        }
+
+        brightness
+    }
+
+    fn shade_particle(&self, x: u8, y: u8, part: Particle, options: &ChunkRenderOptions) -> [u8; 4] {
+        let base = get_color_for_type(part.particle_type);
+        if part.particle_type == ParticleType::Air {
+            return base;
+        }
+
+        let brightness = self.get_brightness(x, y, part, options);
+        let mut color = [0.; 3];
+        for i in 0..3 {
+            color[i] = base[i] as f32 * brightness;
+        }
+
+        if options.heat_glow > 0. {
+            let glow = get_heat_glow(self.caclulate_local_temp(x as i16, y as i16)) * options.heat_glow;
+            for i in 0..3 {
+                color[i] += (HEAT_GLOW_COLOR[i] as f32 - color[i]) * glow;
+            }
+        }
+
+        [
+            color[0].clamp(0., 255.) as u8,
+            color[1].clamp(0., 255.) as u8,
+            color[2].clamp(0., 255.) as u8,
+            base[3],
+        ]
+    }
+
     pub fn get_particle_mut(&mut self, x: u8, y: u8) -> &mut Particle {
         #[cfg(debug_assertions)] {
             if x >= CHUNK_SIZE {
@@ -680,14 +877,122 @@ impl Chunk {
         }
     }
 
+    fn set_local_temperature(&mut self, x: i16, y: i16, temp: i32, rng: &mut ThreadRng) {
+        if self.contains(x, y) {
+            self.try_state_change(x as u8, y as u8, temp, rng);
+        }
+        else if let Some(neighbor) = self.get_neighbor( Chunk::get_oob_direction(x, y) ) {
+            let dir = Chunk::get_oob_direction(x, y);
+            let adjusted_x = x - (dir.x as i16 * CHUNK_SIZE as i16);
+            let adjusted_y = y - (dir.y as i16 * CHUNK_SIZE as i16);
+            
+            unsafe {
+                (*neighbor).try_state_change(adjusted_x as u8, adjusted_y as u8, temp, rng);
+            }
+        }
+    }
+
+    // Replace a particle, giving the behaviors of the old and new types a chance to react if the type changed
+    // Commands from the hooks do not trigger further hooks, so behaviors can't set off an endless chain
+    // Passing None for the behaviors skips the hooks
+    pub(crate) fn replace_with_hooks(&mut self, x: i16, y: i16, val: Particle, hooks: Option<&BehaviorRegistry>, rng: &mut ThreadRng) -> Option<Particle> {
+        let old = self.get_test_particle(x, y)?;
+        self.set_local_part(x, y, val);
+
+        if let Some(behaviors) = hooks {
+            if old.particle_type != val.particle_type {
+                self.run_replace_hooks(x, y, old, val, behaviors, rng);
+            }
+        }
+
+        Some(old)
+    }
+
+    pub(crate) fn run_replace_hooks(&mut self, x: i16, y: i16, old: Particle, new: Particle, behaviors: &BehaviorRegistry, rng: &mut ThreadRng) {
+        let position = GridVec::new(x as i32, y as i32);
+
+        if let Some(behavior) = behaviors.get(old.particle_type) {
+            let commands = behavior.on_destroy(position, old);
+            self.apply_commands(x, y, old, commands, None, rng);
+        }
+        if let Some(behavior) = behaviors.get(new.particle_type) {
+            let commands = behavior.on_place(position, new);
+            self.apply_commands(x, y, new, commands, None, rng);
+        }
+    }
+
+    fn add_with_hooks(&mut self, x: i16, y: i16, val: Particle, hooks: Option<&BehaviorRegistry>, rng: &mut ThreadRng) {
+        if let Some(existing) = self.get_test_particle(x, y) {
+            if existing.particle_type == ParticleType::Air {
+                self.replace_with_hooks(x, y, val, hooks, rng);
+            }
+        }
+    }
+
+    // Carry out the commands a behavior returned for the particle at x, y
+    fn apply_commands(&mut self, x: i16, y: i16, particle: Particle, commands: Vec<ChunkCommand>, hooks: Option<&BehaviorRegistry>, rng: &mut ThreadRng) -> CommandOutcome {
+        let mut outcome = CommandOutcome::default();
+
+        for command in commands {
+            match command {
+                ChunkCommand::Add((position, particle_type, particle_data)) => {
+                    self.add_with_hooks(position.x as i16, position.y as i16, Particle::new_with_data(particle_type, particle_data), hooks, rng);
+                }
+                ChunkCommand::Emit((offset, particle_type, particle_data)) => {
+                    self.add_with_hooks(x + offset.x as i16, y + offset.y as i16, Particle::new_with_data(particle_type, particle_data), hooks, rng);
+                }
+                ChunkCommand::Move(movement) => outcome.move_override = Some(movement),
+                ChunkCommand::MoveOrDestroy(movement) => {
+                    outcome.move_override = Some(movement);
+                    outcome.destroy_if_not_moved = true;
+                },
+                ChunkCommand::Remove => {
+                    self.replace_with_hooks(x, y, Particle::new(ParticleType::Air), hooks, rng);
+                    outcome.relocated = true;
+                }
+                ChunkCommand::Mutate(particle_type, particle_data) => {
+                    self.replace_with_hooks(x, y, Particle::new_with_data(particle_type, particle_data), hooks, rng);
+                }
+                ChunkCommand::Swap(offset) => {
+                    let other_x = x + offset.x as i16;
+                    let other_y = y + offset.y as i16;
+                    if let (Some(own), Some(other)) = (self.get_test_particle(x, y), self.get_test_particle(other_x, other_y)) {
+                        self.set_local_part(other_x, other_y, own);
+                        self.set_local_part(x, y, other);
+                        outcome.relocated = true;
+                    }
+                }
+                ChunkCommand::Heat((offset, temperature)) => {
+                    self.set_local_temperature(x + offset.x as i16, y + offset.y as i16, temperature, rng);
+                }
+                ChunkCommand::SpawnEvent(event_id) => {
+                    self.events.push(ParticleEvent {
+                        position: self.get_world_root() + GridVec::new(x as i32, y as i32),
+                        particle_type: particle.particle_type,
+                        event_id,
+                    });
+                }
+                ChunkCommand::Explode(explosion) => {
+                    self.explosions.push((self.get_world_root() + GridVec::new(x as i32, y as i32), explosion));
+                }
+            }
+        }
+
+        outcome
+    }
+
     fn _is_border(x: u8, y: u8) -> bool {
         x == 0 || y == 0 || x == CHUNK_SIZE - 1  || y == CHUNK_SIZE - 1
     }
 
-    pub(crate) fn commit_updates(&mut self) {
+    // Start a step, the world calls this on each chunk before the checkerboard phases
+    pub fn commit_updates(&mut self) {
         self.update_this_frame = *self.dirty.read().unwrap();
         *self.dirty.write().unwrap() = None;
 
+        // Dirty bounds can spill past the chunk, get_relight_area clamps them back in
+        self.light_dirty = GridBounds::option_union(self.light_dirty, self.update_this_frame);
+
         if let Some(to_update) = GridBounds::option_union(self.update_this_frame, self.updated_last_frame) {
             for point in to_update.slide_iter() {
                 let x = point.x as u8;
@@ -785,13 +1090,8 @@ impl Chunk {
         return neighbors;
     }
     
-    fn caclulate_local_temp(&self, x: i16, y: i16) -> i32 {
-        let mut total = 0;
-        let own_temp = get_heat_for_type(self.get_local_part(x, y));
-        self.iterate_neighbor_parts(x, y, &mut |part_type: ParticleType| {
-            total += if part_type == ParticleType::Air { own_temp / 2 } else { get_heat_for_type(part_type) };
-        });
-        return total;
+    pub(crate) fn caclulate_local_temp(&self, x: i16, y: i16) -> i32 {
+        calculate_local_temp(self.get_local_part(x, y), &self.get_neighbors(x, y))
     }
 
     fn try_erode(&mut self, rng: &mut ThreadRng, x: i16, y: i16, vel: &GridVec) {
@@ -930,7 +1230,8 @@ impl Chunk {
         }
     }
 
-    pub(crate) fn update(&mut self) {      
+    // Simulate everything in the dirty area, run by the world once per step on alternating chunks so neighbors never update together
+    pub fn update(&mut self, behaviors: &BehaviorRegistry) {      
         let mut rng = rand::thread_rng();
 
         if let Some(to_update) = GridBounds::option_union(self.update_this_frame, self.updated_last_frame) {
@@ -943,22 +1244,15 @@ impl Chunk {
                 if !cur_part.updated_this_frame() {           
                     let neighbors = self.get_neighbors(x as i16, y as i16);
                     // Custom Logic
-                    let mut move_override = None;
-                    let mut destroy_if_not_moved = false;
-                    if let Some(update_fn) = get_update_fn_for_type(cur_part.particle_type) {
-                        let commands = update_fn(GridVec::new(x as i32, y as i32), cur_part, &neighbors);
-                        for command in commands {
-                            match command {
-                                ChunkCommand::Add((position, particle_type, particle_data)) => self.add_particle(position.x as i16, position.y as i16, Particle::new_with_data(particle_type, particle_data)), 
-                                ChunkCommand::Move(movement) => move_override = Some(movement),
-                                ChunkCommand::MoveOrDestroy(movement) => {
-                                    move_override = Some(movement);
-                                    destroy_if_not_moved = true;
-                                },
-                                ChunkCommand::Remove => self.set_particle(x, y, Particle::new(ParticleType::Air)),
-                                ChunkCommand::Mutate(particle_type, particle_data) => self.set_particle(x, y, Particle::new_with_data(particle_type, particle_data)),
-                            }
-                        }
+                    let mut outcome = CommandOutcome::default();
+                    if let Some(behavior) = behaviors.get(cur_part.particle_type) {
+                        let commands = behavior.update(GridVec::new(x as i32, y as i32), cur_part, &neighbors);
+                        outcome = self.apply_commands(x as i16, y as i16, cur_part, commands, Some(behaviors), &mut rng);
+                    }
+
+                    if outcome.relocated {
+                        // Particle is no longer here, nothing left to simulate for it
+                        continue;
                     }
                     
                     // Temperature
@@ -974,7 +1268,7 @@ impl Chunk {
                     }
 
                     // Movement
-                    let move_amount = self.particle_movement(x, y, &cur_part, &mut rng, move_override, &neighbors, local_temp);
+                    let move_amount = self.particle_movement(x, y, &cur_part, &mut rng, outcome.move_override, &neighbors, local_temp);
                     
                     // Erosion
                     if cur_part.particle_type == ParticleType::Water && move_amount.manhattan_length() > 1 {
@@ -985,8 +1279,8 @@ impl Chunk {
                     }
                     
                     // Tail custom logic 
-                    if destroy_if_not_moved && move_amount.manhattan_length() == 0 {
-                        self.set_particle(x, y, Particle::new(ParticleType::Air))
+                    if outcome.destroy_if_not_moved && move_amount.manhattan_length() == 0 {
+                        self.replace_with_hooks(x as i16, y as i16, Particle::new(ParticleType::Air), Some(behaviors), &mut rng);
                     }
                 }
             }
@@ -1033,4 +1327,101 @@ impl Chunk {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::chunk::*;
+
+    fn pixel(bytes: &[u8], x: u8, y: u8) -> [u8; 4] {
+        let index = ((CHUNK_SIZE - y - 1) as usize * CHUNK_SIZE as usize + x as usize) * 4;
+        [bytes[index], bytes[index + 1], bytes[index + 2], bytes[index + 3]]
+    }
+
+    #[test]
+    fn flat_render_matches_type_colors() {
+        let mut chunk = Chunk::new(GridVec::new(0, 0));
+        chunk.set_particle(3, 3, Particle::new(ParticleType::Stone));
+
+        let bytes = chunk.render_to_color_array(&ChunkRenderOptions::default());
+        assert_eq!(pixel(&bytes, 3, 3), get_color_for_type(ParticleType::Stone));
+        assert_eq!(pixel(&bytes, 4, 4), get_color_for_type(ParticleType::Air));
+    }
+
+    #[test]
+    fn temperature_render_shows_heat() {
+        let mut chunk = Chunk::new(GridVec::new(0, 0));
+        chunk.set_particle(10, 10, Particle::new(ParticleType::Lava));
+        chunk.set_particle(30, 30, Particle::new(ParticleType::Ice));
+
+        let bytes = chunk.render_temperature_array();
+        assert_eq!(pixel(&bytes, 50, 50)[3], 0);
+        assert!(pixel(&bytes, 11, 10)[3] > 0 && pixel(&bytes, 11, 10)[0] == 0xff);
+        assert!(pixel(&bytes, 31, 30)[3] > 0 && pixel(&bytes, 31, 30)[2] == 0xff);
+    }
+
+    #[test]
+    fn color_variation_is_stable() {
+        let mut chunk = Chunk::new(GridVec::new(0, 0));
+        for x in 0..CHUNK_SIZE {
+            chunk.set_particle(x, 0, Particle::new(ParticleType::Gravel));
+        }
+
+        let options = ChunkRenderOptions { color_variation: 1., ..Default::default() };
+        let first = chunk.render_to_color_array(&options);
+        assert_eq!(first, chunk.render_to_color_array(&options));
+        assert!((1..CHUNK_SIZE).any(|x| pixel(&first, x, 0) != pixel(&first, 0, 0)));
+    }
+
+    #[test]
+    fn exposed_edges_are_darker() {
+        let mut chunk = Chunk::new(GridVec::new(0, 0));
+        for x in 10..20 {
+            for y in 10..20 {
+                chunk.set_particle(x, y, Particle::new(ParticleType::Stone));
+            }
+        }
+
+        let bytes = chunk.render_to_color_array(&ChunkRenderOptions { edge_shading: 0.5, ..Default::default() });
+        assert!(pixel(&bytes, 10, 15)[0] < pixel(&bytes, 15, 15)[0]);
+    }
+
+    #[test]
+    fn index_render_matches_palette() {
+        let mut chunk = Chunk::new(GridVec::new(0, 0));
+        chunk.set_particle(3, 3, Particle::new(ParticleType::Water));
+        chunk.set_particle(5, 3, Particle::new(ParticleType::Stone).with_variant(6));
+
+        let bytes = chunk.render_to_index_array(&ChunkRenderOptions::default());
+        let index = ((CHUNK_SIZE - 4) as usize * CHUNK_SIZE as usize + 3) * 2;
+        assert_eq!(bytes.len(), CHUNK_SIZE as usize * CHUNK_SIZE as usize * 2);
+        assert_eq!(bytes[index..index + 2], [ParticleType::Water as u8, 0]);
+        assert_eq!(bytes[index + 4..index + 6], [ParticleType::Stone as u8, 6]);
+
+        let palette = build_palette();
+        let color_index = bytes[index] as usize * 4;
+        assert_eq!(palette[color_index..color_index + 4], get_color_for_type(ParticleType::Water));
+    }
+
+    #[test]
+    fn index_render_draws_overlays() {
+        let chunk = Chunk::new(GridVec::new(1, 1));
+        let bytes = chunk.render_to_index_array(&ChunkRenderOptions { draw_borders: true, ..Default::default() });
+        assert_eq!(bytes[0], ParticleType::Boundary as u8);
+        assert_eq!(bytes[(CHUNK_SIZE as usize * 2 + 2) * 2], ParticleType::Air as u8);
+    }
+
+    #[test]
+    fn palette_holds_heat_for_the_shader() {
+        let palette = build_palette();
+        let shading = |particle_type: ParticleType| {
+            let index = (PALETTE_WIDTH * 2 + particle_type as usize) * 4;
+            (palette[index] as i32 + palette[index + 1] as i32 * 256 - PALETTE_HEAT_BIAS, palette[index + 3])
+        };
+
+        assert_eq!(shading(ParticleType::Lava), (get_heat_for_type(ParticleType::Lava), 0xff));
+        assert_eq!(shading(ParticleType::Ice), (get_heat_for_type(ParticleType::Ice), 0xff));
+        // Air and overlays are drawn flat
+        assert_eq!(shading(ParticleType::Air).1, 0);
+        assert_eq!(shading(ParticleType::Boundary).1, 0);
+    }
+}
@@ -0,0 +1,244 @@
+use gridmath::*;
+
+use crate::particle::*;
+use crate::bodies::BODY_COLLIDES;
+
+/*
+    Tuning for ejecta, set with World::set_ejecta_options
+    Speeds are in cells per second
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct EjectaOptions {
+    pub gravity: f32,
+    // Live ejecta past this aren't spawned, the particles are left in the grid instead
+    pub max_ejecta: usize,
+    // Fraction of velocity lost per second while passing through a liquid
+    pub liquid_drag: f32,
+    // Anything entering a liquid faster than this throws some of it back out
+    pub splash_speed: f32,
+    // Seconds an ejecta that stopped somewhere it can't land yet is held for before it's dropped
+    pub park_time: f32,
+}
+
+impl Default for EjectaOptions {
+    fn default() -> Self {
+        EjectaOptions {
+            gravity: 100.,
+            max_ejecta: 4096,
+            liquid_drag: 4.,
+            splash_speed: 40.,
+            park_time: 60.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum EjectaStep {
+    Flying,
+    // Moved from open air into a liquid fast enough to splash it
+    Splashed(GridVec),
+    // Stopped in the first cell by running into the second
+    Landed(GridVec, GridVec),
+}
+
+/*
+    A single particle lifted out of the grid, flying free under gravity until it runs into something
+    Ejecta pass through air and sink through liquids, and stop against anything a body would collide with
+    Liquid ejecta also stop on reaching other liquids, so splashes rejoin the pool they land in
+*/
+#[derive(Debug, Clone)]
+pub struct Ejecta {
+    particle: Particle,
+    temperature: i32, // Local temperature where it was thrown from, given back to the grid when it lands
+    pub position: [f32; 2],
+    pub velocity: [f32; 2],
+    last_air: Option<GridVec>, // Where anything this pushes aside goes when it lands
+}
+
+// An ejecta that stopped where it couldn't be put back into the grid, held still until it can be
+#[derive(Debug, Clone)]
+pub(crate) struct ParkedEjecta {
+    pub ejecta: Ejecta,
+    pub at: GridVec,
+    pub hit: GridVec,
+    pub age: f32,
+    pub attempts: u32,
+}
+
+fn get_cell(position: [f32; 2]) -> GridVec {
+    GridVec::new(position[0].floor() as i32, position[1].floor() as i32)
+}
+
+impl Ejecta {
+    pub(crate) fn new(particle: Particle, temperature: i32, position: [f32; 2], velocity: [f32; 2]) -> Self {
+        Ejecta {
+            particle,
+            temperature,
+            position,
+            velocity,
+            last_air: Some(get_cell(position)),
+        }
+    }
+
+    pub fn particle(&self) -> Particle {
+        self.particle
+    }
+
+    pub fn temperature(&self) -> i32 {
+        self.temperature
+    }
+
+    pub fn get_cell(&self) -> GridVec {
+        get_cell(self.position)
+    }
+
+    pub(crate) fn get_last_air(&self) -> Option<GridVec> {
+        self.last_air
+    }
+
+    fn blocks(&self, particle_type: ParticleType) -> bool {
+        BODY_COLLIDES.test(particle_type) || (LIQUID_MATS.test(self.particle.particle_type) && LIQUID_MATS.test(particle_type))
+    }
+
+    // The blocking side neighbour between two diagonal cells, checking the one the path crosses into first
+    fn corner_block(&self, cell: GridVec, next_cell: GridVec, next_position: [f32; 2], get_type: impl Fn(GridVec) -> ParticleType) -> Option<GridVec> {
+        let crossing = |axis: usize, from: i32, to: i32| {
+            let boundary = from.max(to) as f32;
+            (boundary - self.position[axis]) / (next_position[axis] - self.position[axis])
+        };
+        let x_side = GridVec::new(next_cell.x, cell.y);
+        let y_side = GridVec::new(cell.x, next_cell.y);
+        let sides = if crossing(0, cell.x, next_cell.x) <= crossing(1, cell.y, next_cell.y) { [x_side, y_side] } else { [y_side, x_side] };
+
+        sides.into_iter().find(|side| self.blocks(get_type(*side)))
+    }
+
+    // Advance a cell or less at a time, so ejecta can't skip through thin walls
+    pub(crate) fn step(&mut self, dt: f32, options: &EjectaOptions, get_type: impl Fn(GridVec) -> ParticleType) -> EjectaStep {
+        self.velocity[1] -= options.gravity * dt;
+
+        if LIQUID_MATS.test(get_type(self.get_cell())) {
+            let keep = (-options.liquid_drag * dt).exp();
+            self.velocity[0] *= keep;
+            self.velocity[1] *= keep;
+        }
+
+        let speed = self.velocity[0].hypot(self.velocity[1]);
+        let substeps = (speed * dt).ceil().max(1.) as u32;
+        let sub_dt = dt / substeps as f32;
+
+        for _ in 0..substeps {
+            let cell = self.get_cell();
+            let next_position = [self.position[0] + self.velocity[0] * sub_dt, self.position[1] + self.velocity[1] * sub_dt];
+            let next_cell = get_cell(next_position);
+
+            if next_cell != cell {
+                // A step can still change both coordinates, which passes through a side neighbour on the way
+                if next_cell.x != cell.x && next_cell.y != cell.y {
+                    if let Some(hit) = self.corner_block(cell, next_cell, next_position, &get_type) {
+                        return EjectaStep::Landed(cell, hit);
+                    }
+                }
+
+                let next_type = get_type(next_cell);
+                if self.blocks(next_type) {
+                    return EjectaStep::Landed(cell, next_cell);
+                }
+
+                self.position = next_position;
+                if next_type == ParticleType::Air {
+                    self.last_air = Some(next_cell);
+                }
+                else if LIQUID_MATS.test(next_type) && get_type(cell) == ParticleType::Air && speed > options.splash_speed {
+                    return EjectaStep::Splashed(next_cell);
+                }
+            }
+            else {
+                self.position = next_position;
+            }
+        }
+
+        EjectaStep::Flying
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ejecta::*;
+
+    fn pool(surface: i32, floor: i32) -> impl Fn(GridVec) -> ParticleType {
+        move |pos: GridVec| {
+            if pos.y < floor { ParticleType::Stone }
+            else if pos.y < surface { ParticleType::Water }
+            else { ParticleType::Air }
+        }
+    }
+
+    fn run(ejecta: &mut Ejecta, world: impl Fn(GridVec) -> ParticleType) -> EjectaStep {
+        let options = EjectaOptions::default();
+        for _ in 0..600 {
+            let result = ejecta.step(1. / 60., &options, &world);
+            if let EjectaStep::Landed(..) = result {
+                return result;
+            }
+        }
+        EjectaStep::Flying
+    }
+
+    #[test]
+    fn falls_onto_floor() {
+        let mut ejecta = Ejecta::new(Particle::new(ParticleType::Sand), 0, [4.5, 30.5], [20., 0.]);
+        let result = run(&mut ejecta, pool(0, 0));
+
+        let EjectaStep::Landed(at, hit) = result else { panic!("never landed") };
+        assert_eq!(at.y, 0);
+        assert_eq!(hit, at + GridVec::new(0, -1));
+        assert!(at.x > 4);
+    }
+
+    #[test]
+    fn powder_sinks_through_liquid() {
+        let mut ejecta = Ejecta::new(Particle::new(ParticleType::Gravel), 0, [0.5, 30.5], [0., 0.]);
+        let result = run(&mut ejecta, pool(10, 0));
+
+        assert_eq!(result, EjectaStep::Landed(GridVec::new(0, 0), GridVec::new(0, -1)));
+        assert_eq!(ejecta.get_last_air(), Some(GridVec::new(0, 10)));
+    }
+
+    #[test]
+    fn liquid_stops_at_surface() {
+        let mut ejecta = Ejecta::new(Particle::new(ParticleType::Lava), 0, [0.5, 30.5], [0., 0.]);
+        let result = run(&mut ejecta, pool(10, 0));
+
+        assert_eq!(result, EjectaStep::Landed(GridVec::new(0, 10), GridVec::new(0, 9)));
+    }
+
+    #[test]
+    fn fast_entry_splashes() {
+        let options = EjectaOptions::default();
+        let mut ejecta = Ejecta::new(Particle::new(ParticleType::Stone), 0, [0.5, 11.5], [0., -options.splash_speed * 2.]);
+
+        let result = ejecta.step(1. / 30., &options, pool(10, 0));
+        assert_eq!(result, EjectaStep::Splashed(GridVec::new(0, 9)));
+    }
+
+    #[test]
+    fn cant_slip_between_diagonal_cells() {
+        // A one cell thick staircase, every wall cell only touching the next at a corner
+        let stairs = |pos: GridVec| if pos.x + pos.y == 1 { ParticleType::Stone } else { ParticleType::Air };
+        let options = EjectaOptions { gravity: 0., ..Default::default() };
+
+        // Heading straight through the corner between (0, 1) and (1, 0)
+        let mut ejecta = Ejecta::new(Particle::new(ParticleType::Sand), 0, [1.5, 1.5], [-30., -30.]);
+        let result = ejecta.step(1. / 30., &options, stairs);
+        let EjectaStep::Landed(at, hit) = result else { panic!("passed through the wall") };
+        assert_eq!(at, GridVec::new(1, 1));
+        assert_eq!(stairs(hit), ParticleType::Stone);
+
+        // Off center, the side it reaches first is the one it lands against
+        let mut ejecta = Ejecta::new(Particle::new(ParticleType::Sand), 0, [1.2, 1.3], [-30., -30.]);
+        assert_eq!(ejecta.step(1. / 60., &options, stairs), EjectaStep::Landed(GridVec::new(1, 1), GridVec::new(0, 1)));
+        let mut ejecta = Ejecta::new(Particle::new(ParticleType::Sand), 0, [1.3, 1.2], [-30., -30.]);
+        assert_eq!(ejecta.step(1. / 60., &options, stairs), EjectaStep::Landed(GridVec::new(1, 1), GridVec::new(1, 0)));
+    }
+}
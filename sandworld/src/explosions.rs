@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use gridmath::*;
+
+use crate::particle::*;
+
+/*
+    A blast set off with World::explode, or by explosive particles in the grid
+    Strength starts at power in the middle and drops to nothing at the radius, shaped by falloff
+    (1 is linear, higher values concentrate the blast towards the middle), and each particle it
+    passes through takes away its blast resistance from there outwards
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Explosion {
+    pub radius: i32,
+    pub power: f32,
+    pub falloff: f32,
+    // Temperature the blast exposes whatever is left standing to, 0 for none
+    pub heat: i32,
+    // Speed loose material is thrown at by the shockwave at full strength, in cells per second
+    pub throw_speed: f32,
+}
+
+impl Explosion {
+    pub fn new(radius: i32, power: f32) -> Self {
+        Explosion {
+            radius,
+            power,
+            falloff: 1.,
+            heat: 0,
+            throw_speed: 120.,
+        }
+    }
+
+    pub fn with_falloff(mut self, falloff: f32) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    pub fn with_heat(mut self, heat: i32) -> Self {
+        self.heat = heat;
+        self
+    }
+
+    pub fn with_throw_speed(mut self, throw_speed: f32) -> Self {
+        self.throw_speed = throw_speed;
+        self
+    }
+
+    // What an explosive material sets off when it detonates
+    pub fn for_type(particle_type: ParticleType) -> Option<Self> {
+        let properties = get_explosive_properties_for_type(particle_type)?;
+        Some(Explosion::new(properties.radius, properties.power).with_heat(properties.heat))
+    }
+
+    fn get_base_strength(&self, distance: f32) -> f32 {
+        self.power * (1. - distance / self.radius as f32).max(0.).powf(self.falloff)
+    }
+
+    /*
+        Strength of the blast where it reaches each cell, found by casting rays out from the center
+        Rays are close enough together to touch every cell at the edge, and a cell hit by several keeps the strongest
+    */
+    pub(crate) fn get_strengths(&self, center: GridVec, resistance: impl Fn(GridVec) -> f32) -> HashMap<GridVec, f32> {
+        let mut strengths = HashMap::new();
+        strengths.insert(center, self.power);
+
+        let ray_count = (std::f32::consts::TAU * self.radius as f32 * 2.).ceil().max(8.) as usize;
+        for ray in 0..ray_count {
+            let angle = ray as f32 / ray_count as f32 * std::f32::consts::TAU;
+            let direction = [angle.cos(), angle.sin()];
+
+            let mut lost = resistance(center);
+            let mut last = center;
+            for step in 1..=self.radius {
+                let cell = GridVec::new(
+                    center.x + (direction[0] * step as f32).round() as i32,
+                    center.y + (direction[1] * step as f32).round() as i32,
+                );
+                if cell == last {
+                    continue;
+                }
+                last = cell;
+
+                let strength = self.get_base_strength(step as f32) - lost;
+                if strength <= 0. {
+                    break;
+                }
+
+                let best = strengths.entry(cell).or_insert(0.);
+                *best = best.max(strength);
+                lost += resistance(cell);
+            }
+        }
+
+        strengths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::explosions::*;
+
+    fn open_air(_: GridVec) -> f32 {
+        get_blast_resistance_for_type(ParticleType::Air)
+    }
+
+    #[test]
+    fn strength_falls_off_with_distance() {
+        let explosion = Explosion::new(10, 8.);
+        let strengths = explosion.get_strengths(GridVec::new(0, 0), open_air);
+
+        let near = strengths[&GridVec::new(2, 0)];
+        let far = strengths[&GridVec::new(8, 0)];
+        assert!(near > far && far > 0.);
+        assert!(!strengths.contains_key(&GridVec::new(11, 0)));
+
+        // Every cell inside the radius is reached
+        for pos in GridBounds::new(GridVec::new(0, 0), GridVec::new(9, 9)).iter() {
+            if pos.sq_distance(GridVec::new(0, 0)) < 8 * 8 {
+                assert!(strengths.contains_key(&pos), "{:?} missed", pos);
+            }
+        }
+    }
+
+    #[test]
+    fn walls_shield_what_is_behind_them() {
+        let explosion = Explosion::new(12, 6.);
+        let wall = |pos: GridVec| {
+            if pos.x >= 3 && pos.x <= 5 { get_blast_resistance_for_type(ParticleType::Stone) } else { open_air(pos) }
+        };
+        let strengths = explosion.get_strengths(GridVec::new(0, 0), wall);
+
+        assert!(strengths.contains_key(&GridVec::new(-8, 0)));
+        assert!(strengths.contains_key(&GridVec::new(3, 0)));
+        assert!(!strengths.contains_key(&GridVec::new(8, 0)));
+    }
+
+    #[test]
+    fn explosives_have_blasts() {
+        assert!(Explosion::for_type(ParticleType::Gunpowder).is_some());
+        assert!(Explosion::for_type(ParticleType::Stone).is_none());
+    }
+}
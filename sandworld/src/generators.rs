@@ -0,0 +1,300 @@
+use std::sync::Arc;
+
+use gridmath::GridVec;
+
+use crate::chunk::{position_noise, CHUNK_SIZE};
+use crate::particle::*;
+use crate::WorldGenerator;
+
+/*
+    Combinators for assembling world generators out of smaller pieces
+    Generators laid over another (Layer, ReplaceWhere) use Air to mean "leave what's underneath alone",
+    so building blocks like ore veins only need to produce the cells they care about
+
+    Combinators are usually built through GeneratorExt, eg
+        terrain.masked(caves, 0.).replace_where(particle_set![ParticleType::Stone], ore_veins).warp(warp_x, warp_y, 12.)
+*/
+pub type SharedGenerator = Arc<dyn WorldGenerator + Send + Sync>;
+
+const CHUNK_AREA: usize = CHUNK_SIZE as usize * CHUNK_SIZE as usize;
+
+// A value at every world position, such as a noise function, used to drive masks, blends and warps
+pub trait ScalarField: Send + Sync {
+    fn sample(&self, pos: GridVec) -> f64;
+}
+
+impl<F: Fn(GridVec) -> f64 + Send + Sync> ScalarField for F {
+    fn sample(&self, pos: GridVec) -> f64 {
+        self(pos)
+    }
+}
+
+// The same particle everywhere
+pub struct Fill(pub Particle);
+
+// top wherever it isn't Air, otherwise base
+pub struct Layer {
+    base: SharedGenerator,
+    top: SharedGenerator,
+}
+
+// The generator wherever the field is at or above the threshold, Air everywhere else
+pub struct Mask {
+    generator: SharedGenerator,
+    field: Arc<dyn ScalarField>,
+    threshold: f64,
+}
+
+/*
+    Picks from a where the weight is 0 and from b where it is 1, in between each cell
+    is chosen with a stable per position dither so borders fade rather than cut
+*/
+pub struct Blend {
+    a: SharedGenerator,
+    b: SharedGenerator,
+    weight: Arc<dyn ScalarField>,
+}
+
+// Where the base produces one of the replace types, use the replacement instead unless it gives Air
+pub struct ReplaceWhere {
+    base: SharedGenerator,
+    replace: ParticleSet,
+    replacement: SharedGenerator,
+}
+
+// Shifts the generator, so it produces at pos what it would have at pos + offset
+pub struct Offset {
+    generator: SharedGenerator,
+    offset: GridVec,
+}
+
+// Distorts the generator by sampling it at a position pushed around by two fields
+pub struct DomainWarp {
+    generator: SharedGenerator,
+    warp_x: Arc<dyn ScalarField>,
+    warp_y: Arc<dyn ScalarField>,
+    strength: f64,
+}
+
+impl WorldGenerator for Fill {
+    fn get_particle(&self, _world_pos: GridVec) -> Particle {
+        self.0
+    }
+
+    fn fill_chunk(&self, _chunk_root: GridVec, particles: &mut [Particle]) {
+        particles.fill(self.0);
+    }
+}
+
+// World position of a particle in a chunk buffer passed to fill_chunk
+fn buffer_pos(chunk_root: GridVec, index: usize) -> GridVec {
+    chunk_root + GridVec::new((index % CHUNK_SIZE as usize) as i32, (index / CHUNK_SIZE as usize) as i32)
+}
+
+impl WorldGenerator for Layer {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        let top = self.top.get_particle(world_pos);
+        if top.particle_type != ParticleType::Air { top } else { self.base.get_particle(world_pos) }
+    }
+
+    fn fill_chunk(&self, chunk_root: GridVec, particles: &mut [Particle]) {
+        self.base.fill_chunk(chunk_root, particles);
+        let mut top = vec![Particle::default(); CHUNK_AREA];
+        self.top.fill_chunk(chunk_root, &mut top);
+
+        for (particle, top) in particles.iter_mut().zip(top) {
+            if top.particle_type != ParticleType::Air {
+                *particle = top;
+            }
+        }
+    }
+}
+
+impl WorldGenerator for Mask {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        if self.field.sample(world_pos) >= self.threshold {
+            self.generator.get_particle(world_pos)
+        }
+        else {
+            Particle::new(ParticleType::Air)
+        }
+    }
+
+    fn fill_chunk(&self, chunk_root: GridVec, particles: &mut [Particle]) {
+        self.generator.fill_chunk(chunk_root, particles);
+        for (index, particle) in particles.iter_mut().enumerate() {
+            if self.field.sample(buffer_pos(chunk_root, index)) < self.threshold {
+                *particle = Particle::new(ParticleType::Air);
+            }
+        }
+    }
+}
+
+impl WorldGenerator for Blend {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        let weight = self.weight.sample(world_pos);
+        if weight <= 0. {
+            self.a.get_particle(world_pos)
+        }
+        else if weight >= 1. {
+            self.b.get_particle(world_pos)
+        }
+        else {
+            let dither = (position_noise(world_pos) as f64 + 1.) / 2.;
+            if weight > dither { self.b.get_particle(world_pos) } else { self.a.get_particle(world_pos) }
+        }
+    }
+}
+
+impl WorldGenerator for ReplaceWhere {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        let base = self.base.get_particle(world_pos);
+        if self.replace.test(base.particle_type) {
+            let replacement = self.replacement.get_particle(world_pos);
+            if replacement.particle_type != ParticleType::Air {
+                return replacement;
+            }
+        }
+        base
+    }
+
+    fn fill_chunk(&self, chunk_root: GridVec, particles: &mut [Particle]) {
+        self.base.fill_chunk(chunk_root, particles);
+        if !particles.iter().any(|particle| self.replace.test(particle.particle_type)) {
+            return;
+        }
+
+        let mut replacements = vec![Particle::default(); CHUNK_AREA];
+        self.replacement.fill_chunk(chunk_root, &mut replacements);
+
+        for (particle, replacement) in particles.iter_mut().zip(replacements) {
+            if self.replace.test(particle.particle_type) && replacement.particle_type != ParticleType::Air {
+                *particle = replacement;
+            }
+        }
+    }
+}
+
+impl WorldGenerator for Offset {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        self.generator.get_particle(world_pos + self.offset)
+    }
+
+    fn fill_chunk(&self, chunk_root: GridVec, particles: &mut [Particle]) {
+        self.generator.fill_chunk(chunk_root + self.offset, particles);
+    }
+}
+
+impl WorldGenerator for DomainWarp {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        let warped = GridVec::new(
+            world_pos.x + (self.warp_x.sample(world_pos) * self.strength).round() as i32,
+            world_pos.y + (self.warp_y.sample(world_pos) * self.strength).round() as i32,
+        );
+        self.generator.get_particle(warped)
+    }
+}
+
+// Chaining versions of the combinators, available on any generator
+pub trait GeneratorExt: WorldGenerator + Send + Sync + Sized + 'static {
+    fn shared(self) -> SharedGenerator {
+        Arc::new(self)
+    }
+
+    fn layer(self, top: impl WorldGenerator + Send + Sync + 'static) -> Layer {
+        Layer { base: self.shared(), top: Arc::new(top) }
+    }
+
+    fn masked(self, field: impl ScalarField + 'static, threshold: f64) -> Mask {
+        Mask { generator: self.shared(), field: Arc::new(field), threshold }
+    }
+
+    fn blend(self, other: impl WorldGenerator + Send + Sync + 'static, weight: impl ScalarField + 'static) -> Blend {
+        Blend { a: self.shared(), b: Arc::new(other), weight: Arc::new(weight) }
+    }
+
+    fn replace_where(self, replace: ParticleSet, replacement: impl WorldGenerator + Send + Sync + 'static) -> ReplaceWhere {
+        ReplaceWhere { base: self.shared(), replace, replacement: Arc::new(replacement) }
+    }
+
+    fn offset(self, offset: GridVec) -> Offset {
+        Offset { generator: self.shared(), offset }
+    }
+
+    fn warp(self, warp_x: impl ScalarField + 'static, warp_y: impl ScalarField + 'static, strength: f64) -> DomainWarp {
+        DomainWarp { generator: self.shared(), warp_x: Arc::new(warp_x), warp_y: Arc::new(warp_y), strength }
+    }
+}
+
+impl<G: WorldGenerator + Send + Sync + 'static> GeneratorExt for G {}
+
+#[cfg(test)]
+mod tests {
+    use crate::generators::*;
+    use crate::particle_set;
+
+    struct Ground;
+
+    impl WorldGenerator for Ground {
+        fn get_particle(&self, world_pos: GridVec) -> Particle {
+            Particle::new(if world_pos.y < 0 { ParticleType::Stone } else { ParticleType::Air })
+        }
+    }
+
+    fn type_at(generator: &impl WorldGenerator, x: i32, y: i32) -> ParticleType {
+        generator.get_particle(GridVec::new(x, y)).particle_type
+    }
+
+    #[test]
+    fn layers_and_replacements_treat_air_as_transparent() {
+        let sea = Fill(Particle::new(ParticleType::Water)).masked(|pos: GridVec| -pos.y as f64, -10.);
+        let world = sea.layer(Ground);
+        assert_eq!(type_at(&world, 0, -5), ParticleType::Stone);
+        assert_eq!(type_at(&world, 0, 5), ParticleType::Water);
+        assert_eq!(type_at(&world, 0, 20), ParticleType::Air);
+
+        let veins = Fill(Particle::new(ParticleType::Gravel)).masked(|pos: GridVec| (pos.x % 2) as f64, 1.);
+        let world = Ground.replace_where(particle_set![ParticleType::Stone], veins);
+        assert_eq!(type_at(&world, 1, -5), ParticleType::Gravel);
+        assert_eq!(type_at(&world, 2, -5), ParticleType::Stone);
+        assert_eq!(type_at(&world, 1, 5), ParticleType::Air);
+    }
+
+    #[test]
+    fn offsets_and_warps_move_features() {
+        let raised = Ground.offset(GridVec::new(0, -10));
+        assert_eq!(type_at(&raised, 0, 5), ParticleType::Stone);
+        assert_eq!(type_at(&raised, 0, 15), ParticleType::Air);
+
+        let warped = Ground.warp(|_: GridVec| 0., |pos: GridVec| if pos.x > 0 { 1. } else { 0. }, 20.);
+        assert_eq!(type_at(&warped, -1, -5), ParticleType::Stone);
+        assert_eq!(type_at(&warped, 1, -5), ParticleType::Air);
+    }
+
+    #[test]
+    fn batch_fill_matches_per_cell() {
+        let veins = Fill(Particle::new(ParticleType::Gravel)).masked(|pos: GridVec| (pos.x % 3) as f64, 1.);
+        let world = Fill(Particle::new(ParticleType::Water)).masked(|pos: GridVec| -pos.y as f64, -10.)
+            .layer(Ground.offset(GridVec::new(3, -20)))
+            .replace_where(particle_set![ParticleType::Stone], veins);
+
+        let root = GridVec::new(-64, -32);
+        let mut particles = vec![Particle::default(); CHUNK_AREA];
+        world.fill_chunk(root, &mut particles);
+
+        for (index, particle) in particles.iter().enumerate() {
+            assert_eq!(*particle, world.get_particle(buffer_pos(root, index)));
+        }
+    }
+
+    #[test]
+    fn blend_follows_weight() {
+        let sand = Fill(Particle::new(ParticleType::Sand));
+        let world = Ground.blend(sand, |pos: GridVec| pos.x as f64 / 100.);
+        assert_eq!(type_at(&world, -10, 5), ParticleType::Air);
+        assert_eq!(type_at(&world, 150, -5), ParticleType::Sand);
+
+        let mixed = (0..100).filter(|x| type_at(&world, *x, 5) == ParticleType::Sand).count();
+        assert!(mixed > 20 && mixed < 80);
+    }
+}
@@ -3,7 +3,26 @@ mod chunk;
 mod region;
 mod sandworld;
 mod collisions;
+mod scripting;
+mod behavior;
+mod lighting;
+mod generators;
+mod worldgen;
+mod structures;
+mod bodies;
+mod ejecta;
+mod explosions;
 
 pub use sandworld::*;
 pub use particle::*;
 pub use chunk::*;
+pub use scripting::{ScriptEngine, ScriptedBehavior, ScriptError};
+pub use behavior::*;
+pub use lighting::{LightColor, LightMap, LightingOptions};
+pub use generators::*;
+pub use worldgen::*;
+pub use structures::*;
+pub use bodies::{BodyEvent, BodyId, BodyOptions, ParticleBody, BODY_COLLIDES};
+pub use ejecta::{Ejecta, EjectaOptions};
+pub use explosions::Explosion;
+pub use region::{REGION_THUMBNAIL_SIZE, THUMBNAIL_SCALE};
\ No newline at end of file
@@ -0,0 +1,298 @@
+use gridmath::{GridBounds, GridVec};
+
+use crate::chunk::{Chunk, CHUNK_SIZE};
+use crate::particle::*;
+
+pub type LightColor = [u8; 3];
+
+const CHUNK_AREA: usize = CHUNK_SIZE as usize * CHUNK_SIZE as usize;
+
+// Sweeps in each direction per relight, more passes let light bend around more corners within a chunk
+const PROPAGATION_PASSES: usize = 2;
+
+// Cells the brightest light crosses through air before it fades to nothing, so a change can't relight anything further away
+pub(crate) const LIGHT_FALLOFF_RADIUS: i32 = 44;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LightingOptions {
+    // Light falling straight down from open sky
+    pub sky_light: LightColor,
+    // Chunks above this world height with nothing loaded above them are treated as open to the sky
+    pub sky_level: i32,
+}
+
+impl Default for LightingOptions {
+    fn default() -> Self {
+        LightingOptions {
+            sky_light: [0xff, 0xf8, 0xec],
+            sky_level: 0,
+        }
+    }
+}
+
+/*
+    Light reaching each particle of a chunk, stored in the same order as the particles
+    Built from emissive particles, sunlight falling in from above, and light spilling over
+    from the edges of neighboring chunks as they were when this chunk was last lit
+*/
+#[derive(Debug, Clone)]
+pub struct LightMap {
+    light: Vec<LightColor>,
+    // Sunlight leaving the bottom of each column, feeds the chunk below
+    sky_exit: [LightColor; CHUNK_SIZE as usize],
+}
+
+// Light entering a chunk from outside, already attenuated by the particle it passed through
+pub(crate) struct LightBoundary {
+    pub left: [LightColor; CHUNK_SIZE as usize],
+    pub right: [LightColor; CHUNK_SIZE as usize],
+    pub bottom: [LightColor; CHUNK_SIZE as usize],
+    pub top: [LightColor; CHUNK_SIZE as usize],
+    pub sky: [LightColor; CHUNK_SIZE as usize],
+}
+
+impl LightBoundary {
+    pub fn dark() -> Self {
+        LightBoundary {
+            left: [[0; 3]; CHUNK_SIZE as usize],
+            right: [[0; 3]; CHUNK_SIZE as usize],
+            bottom: [[0; 3]; CHUNK_SIZE as usize],
+            top: [[0; 3]; CHUNK_SIZE as usize],
+            sky: [[0; 3]; CHUNK_SIZE as usize],
+        }
+    }
+}
+
+impl LightMap {
+    pub fn get(&self, x: u8, y: u8) -> LightColor {
+        self.light[y as usize * CHUNK_SIZE as usize + x as usize]
+    }
+
+    pub fn get_sky_exit(&self, x: u8) -> LightColor {
+        self.sky_exit[x as usize]
+    }
+}
+
+pub(crate) fn attenuate(light: LightColor, particle_type: ParticleType) -> LightColor {
+    let transmission = get_light_transmission_for_type(particle_type);
+    [
+        (light[0] as u16 * transmission[0] as u16 / 255) as u8,
+        (light[1] as u16 * transmission[1] as u16 / 255) as u8,
+        (light[2] as u16 * transmission[2] as u16 / 255) as u8,
+    ]
+}
+
+fn brightest(a: LightColor, b: LightColor) -> LightColor {
+    [a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2])]
+}
+
+pub(crate) fn compute_light(chunk: &Chunk, boundary: &LightBoundary) -> LightMap {
+    let dark = LightMap { light: vec![[0; 3]; CHUNK_AREA], sky_exit: [[0; 3]; CHUNK_SIZE as usize] };
+    relight(chunk, boundary, &dark, Chunk::all_cells())
+}
+
+/*
+    Cells that have to be relit after the particles in dirty (inclusive chunk local bounds) changed
+    Light spreads up to LIGHT_FALLOFF_RADIUS in any direction, and sunlight falls all the way to the bottom of the chunk
+*/
+pub(crate) fn get_relight_area(dirty: GridBounds) -> GridBounds {
+    let edge = CHUNK_SIZE as i32 - 1;
+    let grown = dirty.inflated_by(LIGHT_FALLOFF_RADIUS);
+    GridBounds::new_from_extents(
+        GridVec::new(grown.left().clamp(0, edge), 0),
+        GridVec::new(grown.right().clamp(0, edge), grown.top().clamp(0, edge)),
+    )
+}
+
+/*
+    Recompute the light of the cells in area (inclusive chunk local bounds), keeping previous for everything else
+    Cells outside the area are far enough from any change that their light still holds, so light spreading in
+    from them is taken as is, the same as light coming in from a neighboring chunk
+*/
+pub(crate) fn relight(chunk: &Chunk, boundary: &LightBoundary, previous: &LightMap, area: GridBounds) -> LightMap {
+    let mut light = previous.light.clone();
+    let mut sky_exit = previous.sky_exit;
+    let index = |x: u8, y: u8| y as usize * CHUNK_SIZE as usize + x as usize;
+    let (left, right) = (area.left() as u8, area.right() as u8);
+    let (bottom, top) = (area.bottom() as u8, area.top() as u8);
+
+    // Sunlight falls straight down, losing nothing through air but stopped or tinted by anything else
+    for x in left..=right {
+        let mut sun = boundary.sky[x as usize];
+        for y in (0..CHUNK_SIZE).rev() {
+            if y <= top && y >= bottom {
+                light[index(x, y)] = sun;
+            }
+            let particle_type = chunk.get_particle(x, y).particle_type;
+            if particle_type != ParticleType::Air {
+                sun = attenuate(sun, particle_type);
+            }
+        }
+        sky_exit[x as usize] = sun;
+    }
+
+    for y in bottom..=top {
+        for x in left..=right {
+            let emission = get_emission_for_type(chunk.get_particle(x, y).particle_type);
+            light[index(x, y)] = brightest(light[index(x, y)], emission);
+        }
+    }
+
+    // Light leaving a particle is filtered by that particle, so solids are lit on their surface but pass nothing on
+    let spread = |light: &Vec<LightColor>, x: u8, y: u8| attenuate(light[index(x, y)], chunk.get_particle(x, y).particle_type);
+
+    for _ in 0..PROPAGATION_PASSES {
+        for y in bottom..=top {
+            for x in left..=right {
+                let from_left = if x == 0 { boundary.left[y as usize] } else { spread(&light, x - 1, y) };
+                let from_below = if y == 0 { boundary.bottom[x as usize] } else { spread(&light, x, y - 1) };
+                light[index(x, y)] = brightest(light[index(x, y)], brightest(from_left, from_below));
+            }
+        }
+
+        for y in (bottom..=top).rev() {
+            for x in (left..=right).rev() {
+                let from_right = if x == CHUNK_SIZE - 1 { boundary.right[y as usize] } else { spread(&light, x + 1, y) };
+                let from_above = if y == CHUNK_SIZE - 1 { boundary.top[x as usize] } else { spread(&light, x, y + 1) };
+                light[index(x, y)] = brightest(light[index(x, y)], brightest(from_right, from_above));
+            }
+        }
+    }
+
+    LightMap { light, sky_exit }
+}
+
+/*
+    Neighboring chunks that read light from an edge that changed between two maps of a chunk, as offsets to each
+    neighbor paired with the cells along its shared edge (in its own local bounds) that now need relighting
+    No previous map means every edge counts as changed
+*/
+pub(crate) fn get_changed_edges(old: Option<&LightMap>, new: &LightMap) -> Vec<(GridVec, GridBounds)> {
+    let edge = CHUNK_SIZE - 1;
+    let last = edge as i32;
+    // First and last positions along an edge where the old map disagrees with the new one
+    let changed_span = |differs: &dyn Fn(&LightMap, u8) -> bool| {
+        let Some(old) = old else {
+            return Some((0, last));
+        };
+        let mut changed = (0..CHUNK_SIZE).filter(|i| differs(old, *i));
+        let first = changed.next()? as i32;
+        Some((first, changed.last().map_or(first, |i| i as i32)))
+    };
+
+    let sides: [(GridVec, Option<(i32, i32)>); 4] = [
+        (GridVec::new(-1, 0), changed_span(&|old, i| old.get(0, i) != new.get(0, i))),
+        (GridVec::new(1, 0), changed_span(&|old, i| old.get(edge, i) != new.get(edge, i))),
+        (GridVec::new(0, -1), changed_span(&|old, i| old.get(i, 0) != new.get(i, 0) || old.get_sky_exit(i) != new.get_sky_exit(i))),
+        (GridVec::new(0, 1), changed_span(&|old, i| old.get(i, edge) != new.get(i, edge))),
+    ];
+
+    sides.into_iter().filter_map(|(offset, span)| {
+        let (from, to) = span?;
+        let strip = match (offset.x, offset.y) {
+            (-1, _) => GridBounds::new_from_extents(GridVec::new(last, from), GridVec::new(last, to)),
+            (1, _) => GridBounds::new_from_extents(GridVec::new(0, from), GridVec::new(0, to)),
+            (_, -1) => GridBounds::new_from_extents(GridVec::new(from, last), GridVec::new(to, last)),
+            _ => GridBounds::new_from_extents(GridVec::new(from, 0), GridVec::new(to, 0)),
+        };
+        Some((offset, strip))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lighting::*;
+
+    #[test]
+    fn emissive_particles_light_nearby_air() {
+        let mut chunk = Chunk::new(GridVec::new(0, 0));
+        chunk.set_particle(32, 32, Particle::new(ParticleType::Lava));
+
+        let map = compute_light(&chunk, &LightBoundary::dark());
+        assert_eq!(map.get(32, 32), get_emission_for_type(ParticleType::Lava));
+        assert!(map.get(36, 32)[0] > 0);
+        assert!(map.get(36, 32)[0] < map.get(34, 32)[0]);
+        assert_eq!(map.get(0, 0), [0, 0, 0]);
+    }
+
+    #[test]
+    fn solids_block_light() {
+        let mut chunk = Chunk::new(GridVec::new(0, 0));
+        chunk.set_particle(10, 10, Particle::new(ParticleType::Lava));
+        for y in 0..CHUNK_SIZE {
+            chunk.set_particle(12, y, Particle::new(ParticleType::Stone));
+        }
+
+        let map = compute_light(&chunk, &LightBoundary::dark());
+        assert!(map.get(12, 10)[0] > 0);
+        assert_eq!(map.get(13, 10), [0, 0, 0]);
+    }
+
+    #[test]
+    fn sunlight_stops_at_the_surface() {
+        let mut chunk = Chunk::new(GridVec::new(0, 0));
+        for x in 0..CHUNK_SIZE {
+            chunk.set_particle(x, 20, Particle::new(ParticleType::Stone));
+        }
+
+        let mut boundary = LightBoundary::dark();
+        boundary.sky = [[200; 3]; CHUNK_SIZE as usize];
+
+        let map = compute_light(&chunk, &boundary);
+        assert_eq!(map.get(5, 40), [200; 3]);
+        assert_eq!(map.get(5, 20), [200; 3]);
+        assert_eq!(map.get(5, 10), [0; 3]);
+        assert_eq!(map.get_sky_exit(5), [0; 3]);
+    }
+
+    #[test]
+    fn falloff_radius_covers_the_brightest_light() {
+        let mut light = [0xff; 3];
+        for _ in 0..LIGHT_FALLOFF_RADIUS - 1 {
+            light = attenuate(light, ParticleType::Air);
+        }
+        assert_ne!(light, [0; 3]);
+        assert_eq!(attenuate(light, ParticleType::Air), [0; 3]);
+    }
+
+    #[test]
+    fn relighting_an_area_matches_a_full_relight() {
+        let mut chunk = Chunk::new(GridVec::new(0, 0));
+        chunk.set_particle(5, 5, Particle::new(ParticleType::Lava));
+        chunk.set_particle(58, 58, Particle::new(ParticleType::Lava));
+        let before = compute_light(&chunk, &LightBoundary::dark());
+
+        chunk.set_particle(5, 5, Particle::new(ParticleType::Air));
+        let area = get_relight_area(GridBounds::new_from_extents(GridVec::new(5, 5), GridVec::new(5, 5)));
+        assert!(!area.contains(GridVec::new(58, 58)));
+
+        let relit = relight(&chunk, &LightBoundary::dark(), &before, area);
+        let full = compute_light(&chunk, &LightBoundary::dark());
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                assert_eq!(relit.get(x, y), full.get(x, y), "light differs at {}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn only_changed_edges_wake_neighbors() {
+        let mut chunk = Chunk::new(GridVec::new(0, 0));
+        let before = compute_light(&chunk, &LightBoundary::dark());
+        assert_eq!(get_changed_edges(None, &before).len(), 4);
+
+        // Nothing is lit, so blocking it changes nothing at the edges
+        chunk.set_particle(32, 32, Particle::new(ParticleType::Stone));
+        let after = relight(&chunk, &LightBoundary::dark(), &before, get_relight_area(GridBounds::new_from_extents(GridVec::new(32, 32), GridVec::new(32, 32))));
+        assert!(get_changed_edges(Some(&before), &after).is_empty());
+
+        chunk.set_particle(2, 30, Particle::new(ParticleType::Lava));
+        let lit = relight(&chunk, &LightBoundary::dark(), &after, get_relight_area(GridBounds::new_from_extents(GridVec::new(2, 30), GridVec::new(2, 30))));
+        let changed = get_changed_edges(Some(&after), &lit);
+        // Its light reaches the left edge but fades out before the right one
+        assert!(!changed.iter().any(|(offset, _)| *offset == GridVec::new(1, 0)));
+        let (_, strip) = changed.iter().find(|(offset, _)| *offset == GridVec::new(-1, 0)).unwrap();
+        assert_eq!((strip.left(), strip.right()), (CHUNK_SIZE as i32 - 1, CHUNK_SIZE as i32 - 1));
+        assert!(strip.bottom() < 30 && strip.top() > 30);
+    }
+}
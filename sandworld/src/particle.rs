@@ -1,3 +1,5 @@
+use std::hash::{Hash, Hasher};
+
 use gridmath::GridVec;
 use rand::Rng;
 
@@ -16,13 +18,43 @@ pub enum ParticleType {
     Source,
     LaserBeam,
     LaserEmitter,
+    Snow,
+    Gunpowder,
     Boundary,
     RegionBoundary,
     Dirty,
 }
 
+impl ParticleType {
+    pub const ALL: [ParticleType; 18] = [
+        ParticleType::Air,
+        ParticleType::Sand,
+        ParticleType::Water,
+        ParticleType::Stone,
+        ParticleType::Gravel,
+        ParticleType::Steam,
+        ParticleType::Lava,
+        ParticleType::MoltenGlass,
+        ParticleType::Glass,
+        ParticleType::Ice,
+        ParticleType::Source,
+        ParticleType::LaserBeam,
+        ParticleType::LaserEmitter,
+        ParticleType::Snow,
+        ParticleType::Gunpowder,
+        ParticleType::Boundary,
+        ParticleType::RegionBoundary,
+        ParticleType::Dirty,
+    ];
+
+    // Look up a type by its name, ignoring case (eg "lava" or "MoltenGlass")
+    pub fn from_name(name: &str) -> Option<ParticleType> {
+        ParticleType::ALL.into_iter().find(|t| format!("{:?}", t).eq_ignore_ascii_case(name))
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub struct ParticleSet (u16);
+pub struct ParticleSet (u32);
 
 #[macro_export]
 macro_rules! particle_set {
@@ -37,66 +69,139 @@ macro_rules! particle_set {
     };
 }
 
-#[derive(Debug, Copy, Clone, Hash)]
+/*
+    Particles are 4 bytes, up from the original 2 byte layout with a single data byte
+    This doubles particle storage to 16 KiB per chunk (4 MiB per loaded region) in exchange for
+    a full 16 bit payload that no longer has to share space with the update flag
+*/
+#[derive(Debug, Copy, Clone)]
 pub struct Particle {
     pub particle_type: ParticleType,
     /*
         Highest bit is reserved for particle update flag
-        Other bits may be used for custom particle logic
+        Lowest 3 bits hold the color variant
+        Other bits are reserved
     */
-    data: u8, 
+    flags: u8,
+    /*
+        Payload for custom particle logic, split into fields that can be used at the same time:
+        bits 0-1    direction (up, right, down, left)
+        bits 2-5    mode, material specific state such as what a Source emits
+        bits 6-9    charge
+        bits 10-15  lifetime
+    */
+    data: u16,
 }
 
+const UPDATED_FLAG: u8 = 1 << 7;
+const VARIANT_MASK: u8 = 0b111;
+
+const DIRECTION_SHIFT: u16 = 0;
+const DIRECTION_BITS: u16 = 2;
+const MODE_SHIFT: u16 = 2;
+const MODE_BITS: u16 = 4;
+const CHARGE_SHIFT: u16 = 6;
+const CHARGE_BITS: u16 = 4;
+const LIFETIME_SHIFT: u16 = 10;
+const LIFETIME_BITS: u16 = 6;
+
 pub struct StateChange {
     melt: Option<(i32, ParticleType, f64)>,
     freeze: Option<(i32, ParticleType, f64)>,
 }
 
-pub(crate) struct CustomUpdateRules;
-
-pub(crate) enum ChunkCommand {
-    Add((GridVec, ParticleType, u8)),
-    Move(Vec<GridVec>),
-    MoveOrDestroy(Vec<GridVec>),
-    Remove,
-    Mutate(ParticleType, u8),
-}
-
 pub static SOLID_MATS: ParticleSet = particle_set![ParticleType::Stone, ParticleType::Glass, ParticleType::Ice];
 
-pub static POWDER_MATS: ParticleSet = particle_set![ParticleType::Sand, ParticleType::Gravel];
+pub static POWDER_MATS: ParticleSet = particle_set![ParticleType::Sand, ParticleType::Gravel, ParticleType::Snow, ParticleType::Gunpowder];
 pub static LIQUID_MATS: ParticleSet = particle_set![ParticleType::Water, ParticleType::MoltenGlass, ParticleType::Lava];
 
 
 impl Particle {
     pub fn new(particle_type: ParticleType) -> Self {
-        Particle{particle_type, data: 0}
+        Particle{particle_type, flags: 0, data: 0}
     }
     
     pub(crate)  fn new_already_updated(particle_type: ParticleType) -> Self {
-        Particle::new_with_data(particle_type, 1 << 7)
+        Particle{particle_type, flags: UPDATED_FLAG, data: 0}
     }
     
-    pub fn new_with_data(particle_type: ParticleType, particle_data: u8) -> Self {
-        Particle{particle_type, data: particle_data}
+    pub fn new_with_data(particle_type: ParticleType, particle_data: u16) -> Self {
+        Particle{particle_type, flags: 0, data: particle_data}
     }
     
+    // Full payload available to custom particle logic
+    pub fn data(&self) -> u16 {
+        self.data
+    }
+
+    fn get_field(&self, shift: u16, bits: u16) -> u8 {
+        ((self.data >> shift) & ((1 << bits) - 1)) as u8
+    }
+
+    fn with_field(mut self, shift: u16, bits: u16, val: u8) -> Self {
+        let mask = ((1 << bits) - 1) << shift;
+        self.data = (self.data & !mask) | ((val as u16) << shift & mask);
+        self
+    }
+
+    // 0 = up, 1 = right, 2 = down, 3 = left
+    pub fn direction(&self) -> u8 {
+        self.get_field(DIRECTION_SHIFT, DIRECTION_BITS)
+    }
+
+    pub fn with_direction(self, direction: u8) -> Self {
+        self.with_field(DIRECTION_SHIFT, DIRECTION_BITS, direction)
+    }
+
+    pub fn mode(&self) -> u8 {
+        self.get_field(MODE_SHIFT, MODE_BITS)
+    }
+
+    pub fn with_mode(self, mode: u8) -> Self {
+        self.with_field(MODE_SHIFT, MODE_BITS, mode)
+    }
+
+    pub fn charge(&self) -> u8 {
+        self.get_field(CHARGE_SHIFT, CHARGE_BITS)
+    }
+
+    pub fn with_charge(self, charge: u8) -> Self {
+        self.with_field(CHARGE_SHIFT, CHARGE_BITS, charge)
+    }
+
+    pub fn lifetime(&self) -> u8 {
+        self.get_field(LIFETIME_SHIFT, LIFETIME_BITS)
+    }
+
+    pub fn with_lifetime(self, lifetime: u8) -> Self {
+        self.with_field(LIFETIME_SHIFT, LIFETIME_BITS, lifetime)
+    }
+
+    pub fn variant(&self) -> u8 {
+        self.flags & VARIANT_MASK
+    }
+
+    pub fn with_variant(mut self, variant: u8) -> Self {
+        self.flags = (self.flags & !VARIANT_MASK) | (variant & VARIANT_MASK);
+        self
+    }
+
     pub(crate) fn updated_this_frame(&self) -> bool {
-        return self.data & (1<<7) != 0
+        self.flags & UPDATED_FLAG != 0
     }
     
     pub(crate) fn set_updated_this_frame(&mut self, val: bool) {
         if val {
-            self.data |= 1<<7;
+            self.flags |= UPDATED_FLAG;
         }
         else {
-            self.data &= !(1<<7);
+            self.flags &= !UPDATED_FLAG;
         }
     }
 
     pub fn get_possible_moves(particle_type: ParticleType) -> Vec::<Vec::<GridVec>> {
         match particle_type {
-            ParticleType::Sand => vec![
+            ParticleType::Sand | ParticleType::Gunpowder => vec![
                 vec![GridVec{x: 0, y: -1}, GridVec{x: 0, y: -2}], 
                 vec![GridVec{x: -1, y: -1}, GridVec{x: 1, y: -1}, GridVec{x: 2, y: -1}, GridVec{x: -2, y: -1}],
                 ],
@@ -105,6 +210,10 @@ impl Particle {
                 vec![GridVec{x: 0, y: -1}], 
                 vec![GridVec{x: 1, y: -1}, GridVec{x: -1, y: -1}],
                 ],
+            ParticleType::Snow => vec![
+                vec![GridVec{x: 0, y: -1}],
+                vec![GridVec{x: -1, y: -1}, GridVec{x: 1, y: -1}],
+                ],
             ParticleType::Water => vec![
                 vec![GridVec{x: 1, y: -2}, GridVec{x: -1, y: -2}, GridVec{x: 0, y: -2}, GridVec{x: 1, y: -1}, GridVec{x: -1, y: -1}, GridVec{x: 0, y: -1}],
                 vec![GridVec{x: 1, y: 0}, GridVec{x: -1, y: 0}, GridVec{x: 2, y: -1}, GridVec{x: -2, y: -1}, GridVec{x: 2, y: 0}, GridVec{x: -2, y: 0}, GridVec{x: 3, y: -1}, GridVec{x: -3, y: -1}],
@@ -129,7 +238,7 @@ impl Particle {
 
     pub fn get_replace_set(particle_type: ParticleType) -> ParticleSet {
         match particle_type {
-            ParticleType::Sand => particle_set![ParticleType::Water, ParticleType::Lava],
+            ParticleType::Sand | ParticleType::Gunpowder => particle_set![ParticleType::Water, ParticleType::Lava],
             ParticleType::Gravel => particle_set![ParticleType::Water, ParticleType::Steam, ParticleType::Lava],
             ParticleType::Steam => particle_set![ParticleType::Water, ParticleType::Lava],
             ParticleType::Lava => particle_set![ParticleType::Water, ParticleType::Steam],
@@ -144,14 +253,25 @@ impl Particle {
     }
 }
 
+// Equality and hashing ignore the update flag so identical particles compress together
 impl PartialEq for Particle {
     fn eq(&self, other: &Self) -> bool {
-        self.particle_type == other.particle_type && (self.data & 0b0111111) == (other.data & 0b01111111)
+        self.particle_type == other.particle_type 
+            && (self.flags & !UPDATED_FLAG) == (other.flags & !UPDATED_FLAG) 
+            && self.data == other.data
     }
 }
 
 impl Eq for Particle {}
 
+impl Hash for Particle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.particle_type.hash(state);
+        (self.flags & !UPDATED_FLAG).hash(state);
+        self.data.hash(state);
+    }
+}
+
 impl Default for Particle {
     fn default() -> Self { Particle::new(ParticleType::Air) }
 }
@@ -168,6 +288,8 @@ pub fn get_color_for_type(particle_type: ParticleType) -> [u8; 4] {
         ParticleType::MoltenGlass => [0xf0, 0x95, 0x16, 0xff], //#f09516
         ParticleType::Glass => [0x31, 0x60, 0x5e, 0xff], //#31605e
         ParticleType::Ice => [0xbf, 0xdb, 0xff, 0xff], //#bfdbff
+        ParticleType::Snow => [0xf2, 0xf6, 0xfb, 0xff], //#f2f6fb
+        ParticleType::Gunpowder => [0x3d, 0x3a, 0x40, 0xff], //#3d3a40
         ParticleType::Air => [0x1e, 0x1e, 0x1e, 0xff],
         ParticleType::Source => [0xf7, 0xdf, 0x00, 0xff],
         ParticleType::LaserBeam => [0xff, 0x11, 0x11, 0xff],
@@ -178,9 +300,73 @@ pub fn get_color_for_type(particle_type: ParticleType) -> [u8; 4] {
     }
 }
 
+pub const HEAT_GLOW_MIN_TEMP: i32 = 300;
+pub const HEAT_GLOW_MAX_TEMP: i32 = 1200;
+pub const HEAT_GLOW_COLOR: [u8; 4] = [0xff, 0xec, 0xaa, 0xff];
+
+// How strongly something at a local temperature glows, from 0 to 1
+pub fn get_heat_glow(temp: i32) -> f32 {
+    ((temp - HEAT_GLOW_MIN_TEMP) as f32 / (HEAT_GLOW_MAX_TEMP - HEAT_GLOW_MIN_TEMP) as f32).clamp(0., 1.)
+}
+
+pub const PALETTE_WIDTH: usize = 256;
+pub const PALETTE_ROWS: usize = 3;
+// Added to a material's heat so it fits in two unsigned bytes of the palette
+pub const PALETTE_HEAT_BIAS: i32 = 0x8000;
+
+/*
+    RGBA palette for index textures from Chunk::render_to_index_array, PALETTE_WIDTH pixels wide and PALETTE_ROWS tall
+    Row 0 is the color of each particle type and row 1 the color it glows towards when hot
+    Row 2 holds what the shader needs to shade a cell from its neighbors: its heat plus PALETTE_HEAT_BIAS as two bytes
+    (low then high), how much its color varies scaled to 255, and 255 in alpha for materials that get shaded at all
+    Nothing here depends on the draw options, so the palette is built once
+*/
+pub fn build_palette() -> Vec<u8> {
+    let mut palette = vec![0; PALETTE_WIDTH * PALETTE_ROWS * 4];
+
+    for particle_type in ParticleType::ALL {
+        let index = particle_type as usize * 4;
+        palette[index..index + 4].copy_from_slice(&get_color_for_type(particle_type));
+
+        let glow_index = (PALETTE_WIDTH + particle_type as usize) * 4;
+        palette[glow_index..glow_index + 4].copy_from_slice(&HEAT_GLOW_COLOR);
+
+        let shading_index = (PALETTE_WIDTH * 2 + particle_type as usize) * 4;
+        let heat = (get_heat_for_type(particle_type) + PALETTE_HEAT_BIAS) as u16;
+        let shaded = !matches!(particle_type, ParticleType::Air | ParticleType::Boundary | ParticleType::RegionBoundary | ParticleType::Dirty);
+        palette[shading_index..shading_index + 4].copy_from_slice(&[
+            (heat & 0xff) as u8,
+            (heat >> 8) as u8,
+            (get_color_variation_for_type(particle_type) * 255.).round() as u8,
+            if shaded { 0xff } else { 0 },
+        ]);
+    }
+
+    palette
+}
+
+// How much a material's color is allowed to vary from cell to cell, as a fraction of its brightness
+pub fn get_color_variation_for_type(particle_type: ParticleType) -> f32 {
+    match particle_type {
+        ParticleType::Sand => 0.08,
+        ParticleType::Gravel => 0.14,
+        ParticleType::Stone => 0.1,
+        ParticleType::Water => 0.03,
+        ParticleType::Steam => 0.04,
+        ParticleType::Lava => 0.12,
+        ParticleType::MoltenGlass => 0.08,
+        ParticleType::Glass => 0.05,
+        ParticleType::Ice => 0.05,
+        ParticleType::Snow => 0.03,
+        ParticleType::Gunpowder => 0.12,
+        _ => 0.,
+    }
+}
+
 pub fn get_heat_for_type(particle_type: ParticleType) -> i32 {
     match particle_type {
         ParticleType::Ice => -8,
+        ParticleType::Snow => -6,
         ParticleType::Water => -3,
         ParticleType::Stone => 0,
         ParticleType::Sand => 0,
@@ -195,6 +381,32 @@ pub fn get_heat_for_type(particle_type: ParticleType) -> i32 {
     }
 }
 
+// Light given off by a particle type, as RGB
+pub fn get_emission_for_type(particle_type: ParticleType) -> [u8; 3] {
+    match particle_type {
+        ParticleType::Lava => [0xff, 0x8c, 0x32],
+        ParticleType::MoltenGlass => [0xff, 0xaa, 0x46],
+        ParticleType::LaserBeam => [0xff, 0x28, 0x28],
+        ParticleType::LaserEmitter => [0xff, 0x60, 0x60],
+        _ => [0, 0, 0],
+    }
+}
+
+// Fraction of each RGB channel, out of 255, that light keeps when passing through one particle of a type
+pub fn get_light_transmission_for_type(particle_type: ParticleType) -> [u8; 3] {
+    match particle_type {
+        ParticleType::Air => [0xeb, 0xeb, 0xeb],
+        ParticleType::LaserBeam => [0xeb, 0xeb, 0xeb],
+        ParticleType::Steam => [0xe1, 0xe1, 0xe1],
+        ParticleType::Water => [0xb4, 0xd7, 0xeb],
+        ParticleType::Ice => [0xd2, 0xe1, 0xf0],
+        ParticleType::Glass => [0xdc, 0xeb, 0xe6],
+        ParticleType::Lava => [0xc8, 0x96, 0x64],
+        ParticleType::MoltenGlass => [0xd2, 0xaa, 0x78],
+        _ => [0, 0, 0],
+    }
+}
+
 pub fn get_viscosity_for_type(particle_type: ParticleType, temp: i32) -> i32 {
     match particle_type {
         ParticleType::Water => 2,
@@ -208,6 +420,7 @@ pub fn get_viscosity_for_type(particle_type: ParticleType, temp: i32) -> i32 {
 pub fn get_state_change_for_type(particle_type: ParticleType) -> StateChange {
     match particle_type {
         ParticleType::Ice => StateChange{           melt: Some((-28, ParticleType::Water, 0.5)),        freeze: None },
+        ParticleType::Snow => StateChange{          melt: Some((-32, ParticleType::Water, 0.5)),        freeze: None },
         ParticleType::Water => StateChange{         melt: Some((100, ParticleType::Steam, 0.15)),       freeze: Some((-40, ParticleType::Ice, 0.15)) },
         ParticleType::Steam => StateChange{         melt: None,                                         freeze: Some((200, ParticleType::Water, 0.25))},
         ParticleType::Stone => StateChange{         melt: Some((700, ParticleType::Lava, 0.15)),        freeze: None },
@@ -220,6 +433,59 @@ pub fn get_state_change_for_type(particle_type: ParticleType) -> StateChange {
     }
 }
 
+/*
+    How much a particle weakens a blast passing through it, see World::explode
+    Blasts stronger than this where they reach a particle break or throw it
+*/
+pub fn get_blast_resistance_for_type(particle_type: ParticleType) -> f32 {
+    match particle_type {
+        ParticleType::Air => 0.02,
+        ParticleType::Steam => 0.02,
+        ParticleType::LaserBeam => 0.02,
+        ParticleType::Sand => 0.5,
+        ParticleType::Gravel => 0.6,
+        ParticleType::Snow => 0.3,
+        ParticleType::Gunpowder => 0.3,
+        ParticleType::Water => 0.8,
+        ParticleType::Lava => 1.2,
+        ParticleType::MoltenGlass => 1.2,
+        ParticleType::Ice => 1.5,
+        ParticleType::Glass => 1.5,
+        ParticleType::Stone => 3.,
+        ParticleType::Source => 6.,
+        ParticleType::LaserEmitter => 6.,
+        _ => f32::INFINITY,
+    }
+}
+
+/*
+    Materials that detonate on their own once their local temperature reaches ignite_temp
+    Pressure is how many neighbors of the same type are needed, with no Air around, before heat sets it off
+*/
+pub struct ExplosiveProperties {
+    pub ignite_temp: i32,
+    pub min_pressure: u8,
+    pub radius: i32,
+    pub power: f32,
+    pub heat: i32,
+}
+
+pub fn get_explosive_properties_for_type(particle_type: ParticleType) -> Option<ExplosiveProperties> {
+    match particle_type {
+        ParticleType::Gunpowder => Some(ExplosiveProperties{ ignite_temp: 100, min_pressure: 0, radius: 6, power: 6., heat: 600 }),
+        ParticleType::Steam => Some(ExplosiveProperties{ ignite_temp: 200, min_pressure: 6, radius: 5, power: 4., heat: 0 }),
+        _ => None,
+    }
+}
+
+// Local temperature of a particle from its own type and its neighbors, as the chunk update works it out
+pub fn calculate_local_temp(particle_type: ParticleType, neighbors: &[ParticleType; 8]) -> i32 {
+    let own_temp = get_heat_for_type(particle_type);
+    neighbors.iter()
+        .map(|neighbor| if *neighbor == ParticleType::Air { own_temp / 2 } else { get_heat_for_type(*neighbor) })
+        .sum()
+}
+
 pub fn get_is_lonely_type(particle_type: ParticleType) -> bool {
     match particle_type {
         ParticleType::Stone => true,
@@ -252,84 +518,6 @@ pub fn try_state_change(particle_type: ParticleType, local_temperature: i32, rng
     return None;
 }
 
-pub(crate) fn get_update_fn_for_type(particle_type: ParticleType) -> Option<fn(GridVec, Particle, &[ParticleType; 8])->Vec<ChunkCommand>> {
-    match particle_type {
-        ParticleType::Source => Some(CustomUpdateRules::water_source_update),
-        ParticleType::LaserBeam => Some(CustomUpdateRules::laser_beam_update),
-        ParticleType::LaserEmitter => Some(CustomUpdateRules::laser_emitter_update),
-        _ => None
-    }
-}
-
-
-impl CustomUpdateRules {
-    fn water_source_update(position: GridVec, particle: Particle, neighbors: &[ParticleType; 8] ) -> Vec<ChunkCommand> {
-        let data_val = particle.data & !(1<<7);
-        if data_val == 0 {
-            let mut new_val = 0;
-            for part in neighbors {
-                new_val = match  part {
-                    ParticleType::Water => 1,
-                    ParticleType::Lava => 2,
-                    ParticleType::Sand => 3,
-                    ParticleType::Gravel => 4,
-                    ParticleType::Steam => 5,
-                    _ => 0,
-                };
-                if new_val != 0 {
-                    break;
-                }
-            }
-            vec![ChunkCommand::Mutate(particle.particle_type, new_val)]
-        }
-        else {
-            let emit_type = match data_val {
-                1 => ParticleType::Water,
-                2 => ParticleType::Lava,  
-                3 => ParticleType::Sand,
-                4 => ParticleType::Gravel,
-                5 => ParticleType::Steam,
-                _ => ParticleType::Air,
-            };
-            
-            vec![
-                ChunkCommand::Add((GridVec{x: position.x - 1, y: position.y}, emit_type, 0)),
-                ChunkCommand::Add((GridVec{x: position.x + 1, y: position.y}, emit_type, 0)),
-                ChunkCommand::Add((GridVec{x: position.x, y: position.y - 1}, emit_type, 0)),
-                ChunkCommand::Add((GridVec{x: position.x, y: position.y + 1}, emit_type, 0)),
-            ]
-        }
-    }
-    
-    fn laser_beam_update(_position: GridVec, particle: Particle, _neighbors: &[ParticleType; 8]) -> Vec<ChunkCommand> {
-        let dir_val = particle.data & !(1<<7);
-        let movement = match dir_val {
-            1 => GridVec::new(1, 0),
-            2 => GridVec::new(0, -1),
-            3 => GridVec::new(-1, 0),
-            _ => GridVec::new(0, 1),
-        };
-        
-        vec! [
-            ChunkCommand::MoveOrDestroy(vec![movement])
-        ]
-    }
-    
-    fn laser_emitter_update(position: GridVec, particle: Particle, _neighbors: &[ParticleType; 8]) -> Vec<ChunkCommand> {
-        let dir_val = particle.data & !(1<<7);
-        let movement = match dir_val {
-            1 => GridVec::new(1, 0),
-            2 => GridVec::new(0, -1),
-            3 => GridVec::new(-1, 0),
-            _ => GridVec::new(0, 1),
-        };
-        
-        vec![
-            ChunkCommand::Add((GridVec{x: position.x, y: position.y} + movement, ParticleType::LaserBeam, dir_val)),
-        ]
-    }
-} 
-
 impl ParticleSet {
     pub const fn none() -> Self {
         ParticleSet(0)
@@ -363,7 +551,7 @@ impl ParticleSet {
     }
 
     pub fn exclude(&mut self, part_type: ParticleType) -> &Self {
-        self.0 |= !(1 << (part_type as u8));
+        self.0 &= !(1 << (part_type as u8));
         self
     }
 
@@ -371,7 +559,71 @@ impl ParticleSet {
         ParticleSet(self.0 | other.0)
     }
 
+    pub const fn difference(&self, other: ParticleSet) -> ParticleSet {
+        ParticleSet(self.0 & !other.0)
+    }
+
     pub const fn test(&self, part_type: ParticleType) -> bool {
         (self.0 & 1 << (part_type as u8)) != 0
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::particle::*;
+
+    #[test]
+    fn particle_set_covers_all_types() {
+        let mut set = ParticleSet::none();
+        for particle_type in ParticleType::ALL {
+            assert!(!set.test(particle_type));
+            set.include(particle_type);
+            assert!(set.test(particle_type));
+        }
+        assert_eq!(set.count(), ParticleType::ALL.len() as u32);
+    }
+
+    #[test]
+    fn particle_size() {
+        // Was 2 bytes with a single data byte, see the layout notes on Particle
+        assert_eq!(std::mem::size_of::<Particle>(), 4);
+    }
+
+    #[test]
+    fn data_fields_are_independent() {
+        let part = Particle::new(ParticleType::LaserBeam)
+            .with_direction(3)
+            .with_mode(9)
+            .with_charge(15)
+            .with_lifetime(42)
+            .with_variant(5);
+
+        assert_eq!(part.direction(), 3);
+        assert_eq!(part.mode(), 9);
+        assert_eq!(part.charge(), 15);
+        assert_eq!(part.lifetime(), 42);
+        assert_eq!(part.variant(), 5);
+
+        let part = part.with_charge(2);
+        assert_eq!(part.direction(), 3);
+        assert_eq!(part.mode(), 9);
+        assert_eq!(part.charge(), 2);
+        assert_eq!(part.lifetime(), 42);
+    }
+
+    #[test]
+    fn oversized_field_values_are_truncated() {
+        let part = Particle::new(ParticleType::Sand).with_direction(5).with_lifetime(64);
+        assert_eq!(part.direction(), 1);
+        assert_eq!(part.lifetime(), 0);
+        assert_eq!(part.mode(), 0);
+    }
+
+    #[test]
+    fn equality_ignores_update_flag() {
+        let mut a = Particle::new(ParticleType::Water).with_variant(2);
+        let b = Particle::new(ParticleType::Water).with_variant(2);
+        a.set_updated_this_frame(true);
+        assert_eq!(a, b);
+        assert_ne!(a, b.with_variant(3));
+    }
+}
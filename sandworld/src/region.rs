@@ -1,10 +1,16 @@
 pub const REGION_SIZE: usize = 16;
+// Cells along each side of the block sampled for one thumbnail pixel
+pub const THUMBNAIL_SCALE: usize = 16;
+// Width and height of a region thumbnail in pixels
+pub const REGION_THUMBNAIL_SIZE: usize = REGION_SIZE * CHUNK_SIZE as usize / THUMBNAIL_SCALE;
 
+use std::collections::VecDeque;
 use std::sync::{atomic::AtomicU64, Arc};
 
 use gridmath::{gridline::GridLine, *};
 use rayon::prelude::*;
-use crate::{chunk::*, collisions::HitInfo, Particle, ParticleSet, ParticleType, World, WorldGenerator};
+use tracing::info_span;
+use crate::{chunk::*, collisions::HitInfo, behavior::{BehaviorRegistry, ParticleEvent}, explosions::Explosion, structures::StructureSet, get_color_for_type, Particle, ParticleSet, ParticleType, World, WorldGenerator};
 
 pub struct Region {
     pub position: GridVec,
@@ -23,9 +29,17 @@ pub struct Region {
 pub struct CompressedRegion {
     pub position: GridVec,
     chunks: Vec<CompressedChunk>,
+    // Taken while compressing, so maps can show the region without decompressing it
+    thumbnail: Arc<Vec<u8>>,
     generator: Arc<dyn WorldGenerator + Send + Sync>,
 }
 
+impl CompressedRegion {
+    pub fn get_thumbnail(&self) -> &[u8] {
+        &self.thumbnail
+    }
+}
+
 impl Region {
     pub fn new(position: GridVec, generator: Arc<dyn WorldGenerator + Send + Sync>) -> Self {
         let mut reg = Region {
@@ -67,9 +81,14 @@ impl Region {
         reg
     }
 
-    pub fn generate_terrain(&mut self) {
+    pub fn generate_terrain(&mut self, structures: &StructureSet) {
+        let placements = structures.get_overlapping_placements(self.position, &self.generator);
+
         self.chunks.par_iter_mut().for_each(|chunk| {
             chunk.regenerate(&self.generator);
+            for placed in placements.iter() {
+                structures.get(placed.structure).stamp.stamp_chunk(chunk, placed.origin);
+            }
         });
     }
 
@@ -93,10 +112,39 @@ impl Region {
         CompressedRegion {
             position: self.position,
             chunks: compressed_chunks,
+            thumbnail: Arc::new(self.render_thumbnail()),
             generator: self.generator.clone(),
         }
     }
 
+    /*
+        Downsampled picture of the region, REGION_THUMBNAIL_SIZE pixels square with rows ordered top to bottom
+        Each RGBA pixel is the color of the particle in the middle of its THUMBNAIL_SCALE sized block
+    */
+    pub fn render_thumbnail(&self) -> Vec<u8> {
+        let mut bytes = vec![0; REGION_THUMBNAIL_SIZE * REGION_THUMBNAIL_SIZE * 4];
+        let samples_per_chunk = CHUNK_SIZE as usize / THUMBNAIL_SCALE;
+        let region_root = self.position * REGION_SIZE as i32;
+
+        for chunk in self.chunks.iter() {
+            let chunk_offset = chunk.position - region_root;
+            for sample_y in 0..samples_per_chunk {
+                for sample_x in 0..samples_per_chunk {
+                    let local_x = (sample_x * THUMBNAIL_SCALE + THUMBNAIL_SCALE / 2) as u8;
+                    let local_y = (sample_y * THUMBNAIL_SCALE + THUMBNAIL_SCALE / 2) as u8;
+                    let color = get_color_for_type(chunk.get_particle(local_x, local_y).particle_type);
+
+                    let x = chunk_offset.x as usize * samples_per_chunk + sample_x;
+                    let y = chunk_offset.y as usize * samples_per_chunk + sample_y;
+                    let index = ((REGION_THUMBNAIL_SIZE - y - 1) * REGION_THUMBNAIL_SIZE + x) * 4;
+                    bytes[index..index + 4].copy_from_slice(&color);
+                }
+            }
+        }
+
+        bytes
+    }
+
     fn add_chunk(&mut self, chunkpos: GridVec) {
         let mut added = Box::new(Chunk::new(chunkpos));
 
@@ -374,6 +422,24 @@ impl Region {
         return set;
     }
 
+    pub(crate) fn mark_light_stale(&mut self) {
+        for chunk in self.chunks.iter_mut() {
+            chunk.light_dirty = Some(Chunk::all_cells());
+        }
+    }
+
+    pub(crate) fn take_events(&mut self, events: &mut Vec<ParticleEvent>) {
+        for chunk in self.chunks.iter_mut() {
+            events.append(&mut chunk.events);
+        }
+    }
+
+    pub(crate) fn take_explosions(&mut self, explosions: &mut VecDeque<(GridVec, Explosion)>) {
+        for chunk in self.chunks.iter_mut() {
+            explosions.extend(chunk.explosions.drain(..));
+        }
+    }
+
     pub fn get_chunk(&self, chunkpos: &GridVec) -> Option<&Box<Chunk>> {
         if self.contains_chunk(chunkpos) {
             Some(&self.chunks[self.chunkpos_to_region_index(chunkpos)])
@@ -426,7 +492,8 @@ impl Region {
         });
     }
 
-    pub fn update(&mut self, phase: i32) -> u64 {
+    pub fn update(&mut self, phase: i32, behaviors: &BehaviorRegistry) -> u64 {
+        let _span = info_span!("region_update", position = %self.position).entered();
         let updated_count = AtomicU64::new(0);
 
         let x_mod = (phase) % 2;
@@ -438,7 +505,7 @@ impl Region {
             if (chunk_pos.x % 2).abs() == x_mod && (chunk_pos.y % 2).abs() == y_mod {
                 if chunk.update_this_frame.is_some() || chunk.updated_last_frame.is_some() { 
                     updated_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                    chunk.update(); 
+                    chunk.update(behaviors); 
                 }
             }
         });
@@ -448,3 +515,20 @@ impl Region {
         updated
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::region::*;
+    use crate::generators::Fill;
+
+    #[test]
+    fn thumbnail_survives_compression() {
+        let mut region = Region::new(GridVec::new(1, -1), Arc::new(Fill(Particle::new(ParticleType::Stone))));
+        region.generate_terrain(&StructureSet::new(0));
+
+        let thumbnail = region.render_thumbnail();
+        assert_eq!(thumbnail.len(), REGION_THUMBNAIL_SIZE * REGION_THUMBNAIL_SIZE * 4);
+        assert_eq!(thumbnail[0..4], get_color_for_type(ParticleType::Stone));
+        assert_eq!(region.compress_region().get_thumbnail(), &thumbnail[..]);
+    }
+}
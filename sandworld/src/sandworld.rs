@@ -3,20 +3,53 @@ use gridmath::*;
 use rand::rngs::ThreadRng;
 use rand::{RngCore, Rng};
 use rayon::prelude::*;
+use tracing::info_span;
 use std::collections::{BinaryHeap, VecDeque};
 use std::mem::swap;
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicU64, AtomicBool};
+use std::time::{Duration, Instant};
 use crate::particle_set;
-use crate::{chunk::*, region::*, collisions::HitInfo, particle::*};
+use crate::{chunk::*, region::*, collisions::HitInfo, particle::*, scripting::*, behavior::*, lighting::*, structures::*, bodies::*, ejecta::*, explosions::*};
 
 pub const WORLD_WIDTH: i32 = 1440;
 pub const WORLD_HEIGHT: i32 = 960;
 
+// Cells searched upwards for space when an ejecta lands somewhere already full
+const EJECTA_SEARCH_HEIGHT: i32 = 16;
+// Tries a parked ejecta gets at landing once its region is loaded, before it's dropped
+const MAX_EJECTA_LANDING_ATTEMPTS: u32 = 8;
+
+// Explosions carried out per update, the rest wait so long chain reactions spread over several frames
+const MAX_EXPLOSIONS_PER_UPDATE: usize = 32;
+
+// Loose material a blast can throw, anything else it beats is broken or destroyed in place
+static THROWN_BY_BLAST: ParticleSet = POWDER_MATS.union(LIQUID_MATS);
+
+// Oldest particle events are dropped past this if nobody is taking them
+const MAX_PENDING_PARTICLE_EVENTS: usize = 4096;
+
+// Guess at the wall time of one chunk update before any have been measured
+const INITIAL_CHUNK_UPDATE_COST: f64 = 0.0001;
+// How much each update's measurement moves the chunk update cost estimate
+const CHUNK_UPDATE_COST_SMOOTHING: f64 = 0.1;
+
 pub const TRUE_REGION_SIZE: usize = REGION_SIZE as usize * CHUNK_SIZE as usize;
 
 pub trait WorldGenerator {
     fn get_particle(&self, world_pos: GridVec) -> Particle;
+
+    /*
+        Fill a whole chunk at once, particles are ordered in rows from the bottom left at chunk_root
+        Override this when cells can share work, such as noise that only depends on the column
+    */
+    fn fill_chunk(&self, chunk_root: GridVec, particles: &mut [Particle]) {
+        for y in 0..CHUNK_SIZE as i32 {
+            for x in 0..CHUNK_SIZE as i32 {
+                particles[(y * CHUNK_SIZE as i32 + x) as usize] = self.get_particle(chunk_root + GridVec::new(x, y));
+            }
+        }
+    }
 }
 
 pub struct World {
@@ -25,11 +58,34 @@ pub struct World {
     loading_regions: VecDeque<LoadingRegion>,
     unloading_regions: VecDeque<UnloadingRegion>,
     generator: Arc<dyn WorldGenerator + Sync + Send>,
+    structures: Arc<StructureSet>,
     removed_chunks: Vec<GridVec>,
+    behaviors: Arc<BehaviorRegistry>,
+    script_engine: ScriptEngine,
+    scripted_types: ParticleSet,
+    particle_events: Vec<ParticleEvent>,
+    lighting: LightingOptions,
+    relit_chunks: Vec<GridVec>,
+    bodies: Vec<ParticleBody>,
+    next_body_id: BodyId,
+    body_options: BodyOptions,
+    body_events: Vec<BodyEvent>,
+    ejecta: Vec<Ejecta>,
+    parked_ejecta: Vec<ParkedEjecta>,
+    ejecta_options: EjectaOptions,
+    pending_explosions: VecDeque<(GridVec, Explosion)>,
+    // Seconds of wall time per chunk update, averaged over recent updates
+    chunk_update_cost: f64,
+    // Calls to update since the world was created
+    update_count: u64,
 }
 
 pub struct WorldUpdateStats {
     pub chunk_updates: u64,
+    // Chunk updates the budget allowed this update
+    pub target_chunk_updates: u64,
+    // Current estimate of seconds per chunk update
+    pub chunk_update_cost: f64,
     pub loaded_regions: usize,
     pub loading_regions: usize,
     pub compressed_regions: usize,
@@ -37,12 +93,86 @@ pub struct WorldUpdateStats {
     pub region_updates: u64,
 }
 
+/*
+    Everything the world knows about a single loaded cell, for debug inspectors
+    Bounds are chunk local, and staleness and update priority belong to the region holding the chunk
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct CellInfo {
+    pub particle: Particle,
+    pub local_temp: i32,
+    pub updated_this_frame: bool,
+    pub chunk_pos: GridVec,
+    pub dirty_bounds: Option<GridBounds>,
+    pub update_bounds: Option<GridBounds>,
+    pub region_pos: GridVec,
+    pub staleness: u64,
+    pub update_priority: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionState {
+    Loaded,
+    Loading,
+    Compressed,
+    Compressing,
+}
+
+// Where a region is in its load cycle, and for loaded regions how they're being scheduled
+#[derive(Debug, Clone, Copy)]
+pub struct RegionStatus {
+    pub position: GridVec,
+    pub state: RegionState,
+    pub staleness: u64,
+    pub update_priority: u64,
+}
+
+// How much simulation an update is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateBudget {
+    // A fixed number of chunk updates
+    Chunks(u64),
+    // As many chunk updates as fit in this much time at the measured cost per chunk
+    Time(Duration),
+}
+
+impl UpdateBudget {
+    pub fn target_chunk_updates(&self, chunk_update_cost: f64) -> u64 {
+        match self {
+            UpdateBudget::Chunks(count) => *count,
+            // Always allow one, so a bad estimate can still be corrected by measuring again
+            UpdateBudget::Time(time) => ((time.as_secs_f64() / chunk_update_cost) as u64).max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct WorldUpdateOptions {
+    pub budget: UpdateBudget,
+    // Most regions simulated in one update, however much budget is left
+    pub max_update_regions: usize,
+    // Updates a region can go without simulating before it's compressed, if it's out of view
+    pub staleness_threshold: u64,
+    // Update priority shared between the visible regions, so they're always picked first
+    pub visible_priority_boost: u64,
     pub force_compress_decompress_all: bool,
 }
 
+impl Default for WorldUpdateOptions {
+    fn default() -> Self {
+        WorldUpdateOptions {
+            // Half a frame at 60fps
+            budget: UpdateBudget::Time(Duration::from_micros(8333)),
+            max_update_regions: 16,
+            staleness_threshold: 12,
+            visible_priority_boost: 65536,
+            force_compress_decompress_all: false,
+        }
+    }
+}
+
 enum LoadType {
-    Generate(Arc<dyn WorldGenerator + Send + Sync>),
+    Generate(Arc<dyn WorldGenerator + Send + Sync>, Arc<StructureSet>),
     Decompress(Arc<CompressedRegion>),
 }
 
@@ -68,12 +198,206 @@ impl World {
             loading_regions: VecDeque::new(),
             unloading_regions: VecDeque::new(),
             generator,
+            structures: Arc::new(StructureSet::default()),
             removed_chunks: Vec::new(),
+            behaviors: Arc::new(BehaviorRegistry::default()),
+            script_engine: ScriptEngine::new(),
+            scripted_types: ParticleSet::none(),
+            particle_events: Vec::new(),
+            lighting: LightingOptions::default(),
+            relit_chunks: Vec::new(),
+            bodies: Vec::new(),
+            next_body_id: 0,
+            body_options: BodyOptions::default(),
+            body_events: Vec::new(),
+            ejecta: Vec::new(),
+            parked_ejecta: Vec::new(),
+            ejecta_options: EjectaOptions::default(),
+            pending_explosions: VecDeque::new(),
+            chunk_update_cost: INITIAL_CHUNK_UPDATE_COST,
+            update_count: 0,
         };
 
         return created;
     }
 
+    // Replace the custom logic for a particle type, including any built in behavior it had
+    pub fn set_particle_behavior(&mut self, particle_type: ParticleType, behavior: Arc<dyn ParticleBehavior>) {
+        Arc::make_mut(&mut self.behaviors).set(particle_type, behavior);
+        self.scripted_types.exclude(particle_type);
+    }
+
+    // Return a particle type to its built in behavior, or none if it doesn't have one
+    pub fn reset_particle_behavior(&mut self, particle_type: ParticleType) {
+        Arc::make_mut(&mut self.behaviors).reset(particle_type);
+        self.scripted_types.exclude(particle_type);
+    }
+
+    // Drive all particles of the given type with a Rhai script instead of their current behavior
+    pub fn set_particle_script(&mut self, particle_type: ParticleType, source: &str) -> Result<(), ScriptError> {
+        let behavior = self.script_engine.compile(source)?;
+        self.set_particle_behavior(particle_type, Arc::new(behavior));
+        self.scripted_types.include(particle_type);
+        Ok(())
+    }
+
+    pub fn remove_particle_script(&mut self, particle_type: ParticleType) {
+        if self.scripted_types.test(particle_type) {
+            self.reset_particle_behavior(particle_type);
+        }
+    }
+
+    pub fn clear_particle_scripts(&mut self) {
+        for particle_type in ParticleType::ALL {
+            self.remove_particle_script(particle_type);
+        }
+    }
+
+    // Events raised by particle behaviors since this was last called
+    pub fn take_particle_events(&mut self) -> Vec<ParticleEvent> {
+        let mut events = Vec::new();
+        swap(&mut events, &mut self.particle_events);
+        events
+    }
+
+    /*
+        Set the structures placed over generated terrain
+        Only regions generated from now on are affected, so call this before the first update
+    */
+    pub fn set_structures(&mut self, structures: StructureSet) {
+        self.structures = Arc::new(structures);
+    }
+
+    pub fn set_lighting_options(&mut self, options: LightingOptions) {
+        self.lighting = options;
+        for region in self.regions.iter_mut() {
+            region.mark_light_stale();
+        }
+    }
+
+    /*
+        Recompute light for up to max_chunks visible chunks whose particles or neighbors' light changed
+        Only the cells near what changed are relit, see get_relight_area. Light crosses chunk edges using each
+        neighbor's last computed light, so changes spread out over following calls rather than all at once
+    */
+    pub fn update_lighting(&mut self, visible: GridBounds, max_chunks: usize) -> usize {
+        self.relit_chunks.clear();
+
+        let visible_chunks = GridBounds::new_from_extents(
+            Self::get_chunkpos(&visible.bottom_left()),
+            Self::get_chunkpos(&visible.top_right())
+        );
+
+        let stale: Vec<GridVec> = visible_chunks.iter()
+            .filter(|chunkpos| self.get_chunk(chunkpos).is_some_and(|chunk| chunk.light_dirty.is_some()))
+            .take(max_chunks)
+            .collect();
+
+        let world = &*self;
+        let relit: Vec<(GridVec, LightMap)> = stale.par_iter().map(|chunkpos| {
+            let chunk = world.get_chunk(chunkpos).unwrap();
+            let boundary = world.get_light_boundary(*chunkpos);
+            let light_map = match (&chunk.light, chunk.light_dirty) {
+                (Some(previous), Some(dirty)) => relight(chunk, &boundary, previous, get_relight_area(dirty)),
+                _ => compute_light(chunk, &boundary),
+            };
+            (*chunkpos, light_map)
+        }).collect();
+
+        for (chunkpos, light_map) in relit {
+            let chunk = self.get_chunk_mut(&chunkpos).unwrap();
+            let changed_edges = get_changed_edges(chunk.light.as_deref(), &light_map);
+            chunk.light = Some(Box::new(light_map));
+            chunk.light_dirty = None;
+
+            for (offset, strip) in changed_edges {
+                if let Some(neighbor) = self.get_chunk_mut(&(chunkpos + offset)) {
+                    neighbor.light_dirty = GridBounds::option_union(neighbor.light_dirty, Some(strip));
+                }
+            }
+
+            self.relit_chunks.push(chunkpos);
+        }
+
+        self.relit_chunks.len()
+    }
+
+    // Chunks whose light changed in the last call to update_lighting
+    pub fn get_relit_chunks(&self) -> Vec<GridVec> {
+        self.relit_chunks.clone()
+    }
+
+    /*
+        Colors of every cell in bounds as RGBA rows ordered top to bottom, composed from each chunk's
+        render_to_color_array. Cells in chunks that aren't loaded are left transparent
+    */
+    pub fn render_to_color_array(&self, bounds: GridBounds, options: &ChunkRenderOptions) -> Vec<u8> {
+        let width = bounds.width() as usize;
+        let mut bytes = vec![0; width * bounds.height() as usize * 4];
+
+        // Bounds include their top right edge, but iterating them doesn't
+        let chunks: Vec<GridVec> = GridBounds::new_from_extents(
+            Self::get_chunkpos(&bounds.bottom_left()),
+            Self::get_chunkpos(&bounds.top_right()) + GridVec::new(1, 1)
+        ).iter().collect();
+
+        let rendered: Vec<(GridVec, Vec<u8>)> = chunks.par_iter().filter_map(|chunkpos| {
+            self.get_chunk(chunkpos).map(|chunk| (*chunkpos, chunk.render_to_color_array(options)))
+        }).collect();
+
+        for (chunkpos, colors) in rendered {
+            let chunk_root = chunkpos * CHUNK_SIZE as i32;
+            let chunk_top_right = chunk_root + GridVec::new(CHUNK_SIZE as i32 - 1, CHUNK_SIZE as i32 - 1);
+
+            // Every chunk here overlaps bounds, since they came from the chunks its corners are in
+            for y in bounds.bottom().max(chunk_root.y)..=bounds.top().min(chunk_top_right.y) {
+                for x in bounds.left().max(chunk_root.x)..=bounds.right().min(chunk_top_right.x) {
+                    let local = GridVec::new(x, y) - chunk_root;
+                    let from = ((CHUNK_SIZE as usize - 1 - local.y as usize) * CHUNK_SIZE as usize + local.x as usize) * 4;
+                    let to = ((bounds.top() - y) as usize * width + (x - bounds.left()) as usize) * 4;
+                    bytes[to..to + 4].copy_from_slice(&colors[from..from + 4]);
+                }
+            }
+        }
+
+        bytes
+    }
+
+    fn get_light_boundary(&self, chunkpos: GridVec) -> LightBoundary {
+        let mut boundary = LightBoundary::dark();
+        let edge = CHUNK_SIZE - 1;
+
+        let lit_neighbor = |offset: GridVec| self.get_chunk(&(chunkpos + offset)).filter(|chunk| chunk.light.is_some());
+        let spilled = |chunk: &Chunk, x: u8, y: u8| attenuate(chunk.get_light().unwrap().get(x, y), chunk.get_particle(x, y).particle_type);
+
+        if let Some(left) = lit_neighbor(GridVec::new(-1, 0)) {
+            for i in 0..CHUNK_SIZE {
+                boundary.left[i as usize] = spilled(left, edge, i);
+            }
+        }
+        if let Some(right) = lit_neighbor(GridVec::new(1, 0)) {
+            for i in 0..CHUNK_SIZE {
+                boundary.right[i as usize] = spilled(right, 0, i);
+            }
+        }
+        if let Some(below) = lit_neighbor(GridVec::new(0, -1)) {
+            for i in 0..CHUNK_SIZE {
+                boundary.bottom[i as usize] = spilled(below, i, edge);
+            }
+        }
+        if let Some(above) = lit_neighbor(GridVec::new(0, 1)) {
+            for i in 0..CHUNK_SIZE {
+                boundary.top[i as usize] = spilled(above, i, 0);
+                boundary.sky[i as usize] = above.get_light().unwrap().get_sky_exit(i);
+            }
+        }
+        else if (chunkpos.y + 1) * CHUNK_SIZE as i32 > self.lighting.sky_level {
+            boundary.sky = [self.lighting.sky_light; CHUNK_SIZE as usize];
+        }
+
+        boundary
+    }
+
     fn _add_region_immediate(&mut self, regpos: GridVec) {
         if self.retrieve_region_if_compressed(regpos) {
             return;
@@ -105,7 +429,7 @@ impl World {
             return;
         }
 
-        self.loading_regions.push_back(LoadingRegion::new_generate(regpos, self.generator.clone()));
+        self.loading_regions.push_back(LoadingRegion::new_generate(regpos, self.generator.clone(), self.structures.clone()));
         self.loading_regions.back_mut().unwrap().start_load();
     }
 
@@ -331,6 +655,54 @@ impl World {
         return Particle::new(ParticleType::Boundary);
     }
 
+    // Thumbnail of a loaded or compressed region, see Region::render_thumbnail
+    pub fn get_region_thumbnail(&self, regpos: GridVec) -> Option<Vec<u8>> {
+        if let Some(index) = self.get_region_index(regpos) {
+            return Some(self.regions[index].render_thumbnail());
+        }
+
+        self.compressed_regions.iter()
+            .find(|region| region.position == regpos)
+            .map(|region| region.get_thumbnail().to_vec())
+    }
+
+    // Every region the world knows about, scheduling details are 0 for those that aren't loaded
+    pub fn get_region_statuses(&self) -> Vec<RegionStatus> {
+        let unloaded = |position: GridVec, state: RegionState| RegionStatus { position, state, staleness: 0, update_priority: 0 };
+
+        let mut statuses: Vec<RegionStatus> = self.regions.iter().map(|region| RegionStatus {
+            position: region.position,
+            state: RegionState::Loaded,
+            staleness: region.staleness,
+            update_priority: region.update_priority,
+        }).collect();
+        statuses.extend(self.loading_regions.iter().map(|region| unloaded(region.position, RegionState::Loading)));
+        statuses.extend(self.compressed_regions.iter().map(|region| unloaded(region.position, RegionState::Compressed)));
+        statuses.extend(self.unloading_regions.iter().map(|region| unloaded(region.position, RegionState::Compressing)));
+        statuses
+    }
+
+    // None if the cell isn't in a loaded region
+    pub fn inspect_cell(&self, pos: GridVec) -> Option<CellInfo> {
+        let chunk_pos = World::get_chunkpos(&pos);
+        let region = self.regions.iter().find(|reg| reg.contains_chunk(&chunk_pos))?;
+        let chunk = region.get_chunk(&chunk_pos)?;
+        let local = World::get_chunklocal(pos);
+        let particle = chunk.get_particle(local.x as u8, local.y as u8);
+
+        Some(CellInfo {
+            particle,
+            local_temp: chunk.caclulate_local_temp(local.x as i16, local.y as i16),
+            updated_this_frame: particle.updated_this_frame(),
+            chunk_pos,
+            dirty_bounds: *chunk.dirty.read().unwrap(),
+            update_bounds: chunk.update_this_frame,
+            region_pos: region.position,
+            staleness: region.staleness,
+            update_priority: region.update_priority,
+        })
+    }
+
     pub fn replace_particle(&mut self, pos: GridVec, new_val: Particle) {
         if !self.contains(pos) {
             let chunkpos = World::get_chunkpos(&pos);
@@ -341,8 +713,9 @@ impl World {
         let chunkpos = World::get_chunkpos(&pos);
         let chunklocal = World::get_chunklocal(pos);
 
+        let behaviors = self.behaviors.clone();
         if let Some(chunk) = self.get_chunk_mut(&chunkpos) {
-            chunk.set_particle(chunklocal.x as u8, chunklocal.y as u8, new_val);
+            chunk.replace_with_hooks(chunklocal.x as i16, chunklocal.y as i16, new_val, Some(&behaviors), &mut rand::thread_rng());
         }
     }
     
@@ -371,8 +744,16 @@ impl World {
         let chunkpos = World::get_chunkpos(&pos);
         let chunklocal = World::get_chunklocal(pos);
         
+        let behaviors = self.behaviors.clone();
         if let Some(chunk) = self.get_chunk_mut(&chunkpos) {
-            chunk.replace_particle_filtered(chunklocal.x as i16, chunklocal.y as i16, new_val, replace_types)
+            let old = chunk.get_particle(chunklocal.x as u8, chunklocal.y as u8);
+            let replaced = chunk.replace_particle_filtered(chunklocal.x as i16, chunklocal.y as i16, new_val, replace_types);
+
+            if replaced.is_some() && old.particle_type != new_val.particle_type {
+                chunk.run_replace_hooks(chunklocal.x as i16, chunklocal.y as i16, old, new_val, &behaviors, &mut rand::thread_rng());
+            }
+
+            replaced
         }
         else {
             None
@@ -380,16 +761,7 @@ impl World {
     }
 
     pub fn add_particle(&mut self, pos: GridVec, new_val: Particle) {
-        if !self.contains(pos) {
-            let chunkpos = World::get_chunkpos(&pos);
-            let regpos = World::get_regionpos_for_chunkpos(&chunkpos);
-            self.add_region(regpos);
-        }
-
-        let chunkpos = World::get_chunkpos(&pos);
-        let chunklocal = World::get_chunklocal(pos);
-
-        self.get_chunk_mut(&chunkpos).unwrap().add_particle(chunklocal.x as i16, chunklocal.y as i16, new_val);
+        self.replace_particle_filtered(pos, new_val, particle_set![ParticleType::Air]);
     }
 
     pub fn clear_circle(&mut self, pos: GridVec, radius: i32) {
@@ -477,7 +849,402 @@ impl World {
         particles
     }
 
-    pub fn update(&mut self, visible: GridBounds, target_chunk_updates: u64, update_options: WorldUpdateOptions) -> WorldUpdateStats {
+    pub fn set_body_options(&mut self, options: BodyOptions) {
+        self.body_options = options;
+    }
+
+    pub fn get_bodies(&self) -> &[ParticleBody] {
+        &self.bodies
+    }
+
+    pub fn get_body_mut(&mut self, id: BodyId) -> Option<&mut ParticleBody> {
+        self.bodies.iter_mut().find(|body| body.id() == id)
+    }
+
+    // Lift the particles at these positions that match the filter out of the grid into a new rigid body
+    pub fn create_body(&mut self, positions: &[GridVec], filter: ParticleSet) -> Option<BodyId> {
+        let mut cells = Vec::new();
+        for pos in positions.iter() {
+            let particle = self.get_particle(*pos);
+            if filter.test(particle.particle_type) {
+                cells.push((*pos, particle));
+                self.replace_particle(*pos, Particle::new(ParticleType::Air));
+            }
+        }
+
+        let body = ParticleBody::new(self.next_body_id, cells)?;
+        self.next_body_id += 1;
+        self.bodies.push(body);
+        Some(self.next_body_id - 1)
+    }
+
+    pub fn extract_body_circle(&mut self, pos: GridVec, radius: i32, filter: ParticleSet) -> Option<BodyId> {
+        let positions: Vec<GridVec> = GridBounds::new(pos, GridVec::new(radius, radius)).iter()
+            .filter(|test_pos| pos.sq_distance(*test_pos) < radius.pow(2))
+            .collect();
+        self.create_body(&positions, filter)
+    }
+
+    // Flood fill out from a position through particles matching the filter, giving up on pieces larger than max_cells
+    pub fn extract_connected_body(&mut self, start: GridVec, filter: ParticleSet, max_cells: usize) -> Option<BodyId> {
+        let mut found = vec![start];
+        let mut open = vec![start];
+        let mut seen = std::collections::HashSet::from([start]);
+
+        if !filter.test(self.get_particle(start).particle_type) {
+            return None;
+        }
+
+        while let Some(pos) = open.pop() {
+            for offset in [GridVec::new(1, 0), GridVec::new(-1, 0), GridVec::new(0, 1), GridVec::new(0, -1)] {
+                let next = pos + offset;
+                if seen.insert(next) && filter.test(self.get_particle(next).particle_type) {
+                    if found.len() >= max_cells {
+                        return None;
+                    }
+                    found.push(next);
+                    open.push(next);
+                }
+            }
+        }
+
+        self.create_body(&found, filter)
+    }
+
+    /*
+        Move every rigid body, writing back into the grid any that come to rest or shatter
+        Bodies collide with the grid as it is, so run this after the particle update
+    */
+    pub fn update_bodies(&mut self, dt: f32) {
+        let mut bodies = Vec::new();
+        swap(&mut bodies, &mut self.bodies);
+
+        let collides = |pos: GridVec| BODY_COLLIDES.test(self.get_particle(pos).particle_type);
+        let mut finished = Vec::new();
+        bodies.retain_mut(|body| {
+            match body.step(dt, &self.body_options, collides) {
+                BodyStep::Moving => true,
+                BodyStep::Resting => {
+                    // Bodies that land somewhere too crowded to take them keep trying until there's space
+                    let Some(cells) = body.get_settle_cells(collides, false) else {
+                        return true;
+                    };
+                    finished.push((cells, BodyEvent::Settled(body.id())));
+                    false
+                }
+                BodyStep::Shattered => {
+                    let Some(cells) = body.get_settle_cells(collides, true) else {
+                        return true;
+                    };
+                    finished.push((cells, BodyEvent::Shattered(body.id())));
+                    false
+                }
+            }
+        });
+
+        for (cells, event) in finished {
+            for (pos, particle) in cells {
+                self.replace_particle(pos, particle);
+            }
+            self.body_events.push(event);
+        }
+
+        self.bodies = bodies;
+    }
+
+    // Bodies that have settled or shattered since this was last called
+    pub fn take_body_events(&mut self) -> Vec<BodyEvent> {
+        let mut events = Vec::new();
+        swap(&mut events, &mut self.body_events);
+        events
+    }
+
+    pub fn set_ejecta_options(&mut self, options: EjectaOptions) {
+        self.ejecta_options = options;
+    }
+
+    pub fn get_ejecta(&self) -> &[Ejecta] {
+        &self.ejecta
+    }
+
+    // Free flying particle at a world position, returns false without spawning it if there are already too many
+    pub fn spawn_ejecta(&mut self, particle: Particle, temperature: i32, position: [f32; 2], velocity: [f32; 2]) -> bool {
+        if self.ejecta.len() >= self.ejecta_options.max_ejecta {
+            return false;
+        }
+
+        self.ejecta.push(Ejecta::new(particle, temperature, position, velocity));
+        true
+    }
+
+    // Temperature the simulation sees at a cell, see calculate_local_temp, 0 outside loaded regions
+    fn get_local_temp(&self, pos: GridVec) -> i32 {
+        let local = World::get_chunklocal(pos);
+        self.get_chunk(&World::get_chunkpos(&pos))
+            .map_or(0, |chunk| chunk.caclulate_local_temp(local.x as i16, local.y as i16))
+    }
+
+    /*
+        Throw the particles in a circle matching the filter outwards, faster the further they are from the center
+        Once the ejecta cap is reached the rest stay where they are. Returns how many were lifted
+    */
+    pub fn extract_ejecta_circle(&mut self, pos: GridVec, radius: i32, filter: ParticleSet, speed: f32) -> usize {
+        let mut thrown = 0;
+
+        for test_pos in GridBounds::new(pos, GridVec::new(radius, radius)).iter() {
+            if pos.sq_distance(test_pos) >= radius.pow(2) {
+                continue;
+            }
+
+            let particle = self.get_particle(test_pos);
+            if !filter.test(particle.particle_type) {
+                continue;
+            }
+
+            let offset = [(test_pos.x - pos.x) as f32, (test_pos.y - pos.y) as f32];
+            let length = offset[0].hypot(offset[1]);
+            let power = if length > 0. { speed * length / radius.pow(2) as f32 } else { 0. };
+            let position = [test_pos.x as f32 + 0.5, test_pos.y as f32 + 0.5];
+            let temperature = self.get_local_temp(test_pos);
+            if !self.spawn_ejecta(particle, temperature, position, [offset[0] * power, offset[1] * power]) {
+                break;
+            }
+
+            self.replace_particle(test_pos, Particle::new(ParticleType::Air));
+            thrown += 1;
+        }
+
+        thrown
+    }
+
+    /*
+        Move every ejecta, writing back into the grid any that land
+        Like bodies, run this after the particle update so ejecta collide with the grid as it is
+    */
+    pub fn update_ejecta(&mut self, dt: f32) {
+        let mut ejecta = Vec::new();
+        swap(&mut ejecta, &mut self.ejecta);
+
+        let get_type = |pos: GridVec| self.get_particle(pos).particle_type;
+        let mut landed = Vec::new();
+        let mut splashes = Vec::new();
+        ejecta.retain_mut(|piece| {
+            match piece.step(dt, &self.ejecta_options, get_type) {
+                EjectaStep::Flying => true,
+                EjectaStep::Splashed(pos) => {
+                    splashes.push((pos, piece.velocity));
+                    true
+                }
+                EjectaStep::Landed(at, hit) => {
+                    landed.push((piece.clone(), at, hit));
+                    false
+                }
+            }
+        });
+        self.ejecta = ejecta;
+
+        // Whatever was hit throws a little of itself back up the way the ejecta came
+        for (pos, velocity) in splashes {
+            let liquid = self.get_particle(pos);
+            let temperature = self.get_local_temp(pos);
+            if LIQUID_MATS.test(liquid.particle_type) && self.spawn_ejecta(liquid, temperature, [pos.x as f32 + 0.5, pos.y as f32 + 1.5], [velocity[0] * 0.5, -velocity[1] * 0.4]) {
+                self.replace_particle(pos, Particle::new(ParticleType::Air));
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        for (mut piece, at, hit) in landed {
+            if !self.land_ejecta(&piece, at, hit, &mut rng) {
+                // Nowhere to go yet, usually because it reached a region that isn't loaded
+                // Park it where it stopped rather than losing the particle, see below
+                piece.velocity = [0., 0.];
+                self.parked_ejecta.push(ParkedEjecta { ejecta: piece, at, hit, age: 0., attempts: 0 });
+            }
+        }
+
+        // Parked ejecta aren't moved, and only try again once the region they stopped in is loaded
+        // They're dropped if they still can't land after a few tries, or have waited too long for their region
+        let mut parked = Vec::new();
+        swap(&mut parked, &mut self.parked_ejecta);
+        for mut piece in parked {
+            piece.age += dt;
+            if self.contains(piece.at) {
+                if self.land_ejecta(&piece.ejecta, piece.at, piece.hit, &mut rng) {
+                    continue;
+                }
+                piece.attempts += 1;
+            }
+
+            if piece.attempts < MAX_EJECTA_LANDING_ATTEMPTS && piece.age < self.ejecta_options.park_time {
+                self.parked_ejecta.push(piece);
+            }
+        }
+    }
+
+    /*
+        Put a landed ejecta back in the grid at the first Air found going up from where it stopped
+        A powder or solid that stops inside a liquid takes its place, pushing the liquid back to the last Air the ejecta passed through
+        The temperature it was thrown with is given back to it and whatever it hit, as if it had been next to them in the grid
+        Returns false if it couldn't be placed, leaving the grid untouched
+    */
+    fn land_ejecta(&mut self, piece: &Ejecta, at: GridVec, hit: GridVec, rng: &mut ThreadRng) -> bool {
+        let particle = piece.particle();
+
+        for lift in 0..EJECTA_SEARCH_HEIGHT {
+            let target = at + GridVec::new(0, lift);
+            if !self.contains(target) {
+                return false;
+            }
+
+            let existing = self.get_particle(target);
+            let displaces_liquid = LIQUID_MATS.test(existing.particle_type) && !LIQUID_MATS.test(particle.particle_type);
+            if existing.particle_type != ParticleType::Air && !displaces_liquid {
+                continue;
+            }
+
+            self.replace_particle(target, particle);
+            if displaces_liquid {
+                if let Some(last_air) = piece.get_last_air() {
+                    self.replace_particle_filtered(last_air, existing, particle_set![ParticleType::Air]);
+                }
+            }
+
+            self.set_particle_temperature(target, piece.temperature(), rng);
+            if self.contains(hit) {
+                self.set_particle_temperature(hit, piece.temperature(), rng);
+            }
+            return true;
+        }
+
+        false
+    }
+
+    /*
+        Set off an explosion straight away
+        Particles the blast is stronger than where it reaches them give way: solids break into their loose forms,
+        loose material and liquids are thrown outwards as ejecta (as are solids hit twice as hard as they resist),
+        anything else is destroyed, and explosives go off in turn on the next update. Whatever is left standing is
+        exposed to the blast's heat, more likely the stronger it was there
+    */
+    pub fn explode(&mut self, pos: GridVec, explosion: Explosion) {
+        let strengths = explosion.get_strengths(pos, |cell| get_blast_resistance_for_type(self.get_particle(cell).particle_type));
+        let mut standing = Vec::new();
+
+        for (cell, strength) in strengths {
+            let particle = self.get_particle(cell);
+            let particle_type = particle.particle_type;
+            if particle_type == ParticleType::Air || !self.contains(cell) {
+                continue;
+            }
+
+            let resistance = get_blast_resistance_for_type(particle_type);
+            if strength <= resistance {
+                standing.push((cell, strength));
+                continue;
+            }
+
+            // Explosives that only need heat are set off by the blast itself, for chain reactions
+            if get_explosive_properties_for_type(particle_type).is_some_and(|properties| properties.min_pressure == 0) {
+                if let Some(chained) = Explosion::for_type(particle_type) {
+                    self.replace_particle(cell, Particle::new(ParticleType::Air));
+                    self.pending_explosions.push_back((cell, chained));
+                    continue;
+                }
+            }
+
+            let solid = SOLID_MATS.test(particle_type);
+            let broken = if solid { Particle::new(get_shattered_type(particle_type)) } else { particle };
+
+            if THROWN_BY_BLAST.test(broken.particle_type) && (!solid || strength > resistance * 2.) {
+                let offset = [(cell.x - pos.x) as f32, (cell.y - pos.y) as f32];
+                let length = offset[0].hypot(offset[1]).max(1.);
+                let speed = explosion.throw_speed * strength / explosion.power;
+                let velocity = [offset[0] / length * speed, offset[1] / length * speed];
+
+                let temperature = self.get_local_temp(cell);
+                if self.spawn_ejecta(broken, temperature, [cell.x as f32 + 0.5, cell.y as f32 + 0.5], velocity) {
+                    self.replace_particle(cell, Particle::new(ParticleType::Air));
+                    continue;
+                }
+            }
+
+            if !THROWN_BY_BLAST.test(broken.particle_type) {
+                self.replace_particle(cell, Particle::new(ParticleType::Air));
+            }
+            else if solid {
+                self.replace_particle(cell, broken);
+                standing.push((cell, strength));
+            }
+            else {
+                standing.push((cell, strength));
+            }
+        }
+
+        if explosion.heat != 0 {
+            let mut rng = rand::thread_rng();
+            for (cell, strength) in standing {
+                if rng.gen_bool((strength / explosion.power).clamp(0., 1.) as f64) {
+                    self.set_particle_temperature(cell, explosion.heat, &mut rng);
+                }
+            }
+        }
+    }
+
+    // Set off an explosion on the next update, along with any raised by explosive particles
+    pub fn queue_explosion(&mut self, pos: GridVec, explosion: Explosion) {
+        self.pending_explosions.push_back((pos, explosion));
+    }
+
+    fn update_explosions(&mut self) {
+        let count = self.pending_explosions.len().min(MAX_EXPLOSIONS_PER_UPDATE);
+        for _ in 0..count {
+            if let Some((pos, explosion)) = self.pending_explosions.pop_front() {
+                self.explode(pos, explosion);
+            }
+        }
+    }
+
+    /*
+        Push particles matching the filter out of a circle, as something solid moves into them
+        Each one goes to the nearest Air straight out from the center within max_push cells of the edge,
+        or stays put if there isn't any. Returns how many were moved
+    */
+    pub fn displace_circle(&mut self, pos: GridVec, radius: i32, filter: ParticleSet, max_push: i32) -> usize {
+        let mut moved = 0;
+
+        for test_pos in GridBounds::new(pos, GridVec::new(radius, radius)).iter() {
+            if pos.sq_distance(test_pos) >= radius.pow(2) {
+                continue;
+            }
+
+            let particle = self.get_particle(test_pos);
+            if !filter.test(particle.particle_type) {
+                continue;
+            }
+
+            let offset = test_pos - pos;
+            let length = (offset.x as f32).hypot(offset.y as f32);
+            let direction = if length > 0. { (offset.x as f32 / length, offset.y as f32 / length) } else { (0., 1.) };
+
+            for distance in radius..radius + max_push {
+                let target = pos + GridVec::new((direction.0 * distance as f32).round() as i32, (direction.1 * distance as f32).round() as i32);
+                if pos.sq_distance(target) >= radius.pow(2) && self.get_particle(target).particle_type == ParticleType::Air {
+                    self.replace_particle(target, particle);
+                    self.replace_particle(test_pos, Particle::new(ParticleType::Air));
+                    moved += 1;
+                    break;
+                }
+            }
+        }
+
+        moved
+    }
+
+    pub fn update(&mut self, visible: GridBounds, update_options: WorldUpdateOptions) -> WorldUpdateStats {
+        let _span = info_span!("world_update").entered();
+        self.update_count += 1;
+
+        let loading_span = info_span!("region_loading").entered();
         self.add_loaded_regions_to_sim();
         self.add_unloaded_region_to_list();
 
@@ -490,12 +1257,11 @@ impl World {
             self.add_region_if_needed(regpos);
         }
 
-        self.compress_idle_regions(visible_regions, 12, update_options.force_compress_decompress_all);
+        self.compress_idle_regions(visible_regions, update_options.staleness_threshold, update_options.force_compress_decompress_all);
+        loading_span.exit();
 
-        let max_update_regions = 16;
-        let visible_region_count = visible_regions.area();
-        let total_visible_priority_boost = 65536;
-        let visible_boost_per_region = (total_visible_priority_boost / visible_region_count) as u64;
+        let target_chunk_updates = update_options.budget.target_chunk_updates(self.chunk_update_cost);
+        let visible_boost_per_region = update_options.visible_priority_boost / visible_regions.area() as u64;
 
         let updated_chunk_count = AtomicU64::new(0);
         let updated_region_count = AtomicU64::new(0);
@@ -516,7 +1282,7 @@ impl World {
 
         while !heap.is_empty() 
             && estimated_chunk_updates < target_chunk_updates 
-            && to_update.len() < max_update_regions {
+            && to_update.len() < update_options.max_update_regions {
             let reg_wrap = heap.pop().unwrap();
             let region = reg_wrap.reg;
 
@@ -529,6 +1295,8 @@ impl World {
             to_skip.push(region);
         }
         
+        let simulate_start = Instant::now();
+        let commit_span = info_span!("region_commit", regions = to_update.len()).entered();
         rayon::scope(|s| {
             s.spawn(|_| {
                 to_update.par_iter_mut().for_each(|region| {
@@ -542,22 +1310,48 @@ impl World {
                 });
             });
         });
+        commit_span.exit();
 
+        let behaviors = &self.behaviors;
         let shift = (rand::thread_rng().next_u32() % 4) as i32;
         for i in 0..4 {
             let phase = i + shift;
+            let _phase_span = info_span!("checkerboard_phase", phase = phase % 4).entered();
             to_update.par_iter_mut().for_each(|region| {
                 if region.staleness == 0 {
-                    let region_chunk_updates = region.update(phase);
+                    let region_chunk_updates = region.update(phase, behaviors);
                     updated_chunk_count.fetch_add(region_chunk_updates, std::sync::atomic::Ordering::Relaxed); 
                 }
             });
         }
 
+        // Commits and phases together are the cost the budget pays for each chunk update
+        let simulated = updated_chunk_count.load(std::sync::atomic::Ordering::Relaxed);
+        if simulated > 0 {
+            let measured = simulate_start.elapsed().as_secs_f64() / simulated as f64;
+            self.chunk_update_cost += (measured - self.chunk_update_cost) * CHUNK_UPDATE_COST_SMOOTHING;
+        }
+
+        for region in to_update.iter_mut() {
+            region.take_events(&mut self.particle_events);
+            region.take_explosions(&mut self.pending_explosions);
+        }
+        if self.particle_events.len() > MAX_PENDING_PARTICLE_EVENTS {
+            let overflow = self.particle_events.len() - MAX_PENDING_PARTICLE_EVENTS;
+            self.particle_events.drain(..overflow);
+        }
+
+        {
+            let _span = info_span!("explosions").entered();
+            self.update_explosions();
+        }
+
         let chunk_updates = updated_chunk_count.load(std::sync::atomic::Ordering::Relaxed);
 
         WorldUpdateStats {
             chunk_updates,
+            target_chunk_updates,
+            chunk_update_cost: self.chunk_update_cost,
             loaded_regions: self.regions.len(),
             loading_regions: self.loading_regions.len(),
             compressed_regions: self.compressed_regions.len(),
@@ -566,6 +1360,11 @@ impl World {
         }
     }
 
+    // Number of times the world has been updated, for anything that needs to act every so many updates
+    pub fn update_count(&self) -> u64 {
+        self.update_count
+    }
+
     pub fn cast_ray(&self, hitmask: &ParticleSet, line: GridLine) -> Option<HitInfo> {
         let mut last_region = None;
 
@@ -656,7 +1455,9 @@ impl UnloadingRegion {
         let region = self.region.clone();
         let compressed_region = self.compressed_region.clone();
 
+        let position = self.position;
         rayon::spawn(move || {
+            let _span = info_span!("region_compress", position = %position).entered();
             let reg = region.compress_region();
             compressed_region.lock().unwrap().replace(reg);
             ready.store(true, std::sync::atomic::Ordering::Relaxed);
@@ -665,10 +1466,10 @@ impl UnloadingRegion {
 }
 
 impl LoadingRegion {
-    fn new_generate(position: GridVec, generator: Arc<dyn WorldGenerator + Send + Sync>) -> Self {
+    fn new_generate(position: GridVec, generator: Arc<dyn WorldGenerator + Send + Sync>, structures: Arc<StructureSet>) -> Self {
         LoadingRegion {
             position,
-            source: LoadType::Generate(generator),
+            source: LoadType::Generate(generator, structures),
             ready: Arc::new(false.into()),
             region: Arc::new(Mutex::new(None)),
         }
@@ -689,13 +1490,15 @@ impl LoadingRegion {
         let position = self.position.clone();
 
         match &self.source {
-            LoadType::Generate(gen) => {
+            LoadType::Generate(gen, structs) => {
                 let generator = gen.clone();
+                let structures = structs.clone();
                 // println!("Generating region {}", position);
 
                 rayon::spawn(move || {
+                    let _span = info_span!("region_generate", position = %position).entered();
                     let mut reg = Region::new(position, generator);
-                    reg.generate_terrain();
+                    reg.generate_terrain(&structures);
                     region.lock().unwrap().replace(reg);
                     ready.store(true, std::sync::atomic::Ordering::Relaxed);
                 });
@@ -705,6 +1508,7 @@ impl LoadingRegion {
                 // println!("Decompressing region {}", position);
 
                 rayon::spawn(move || {
+                    let _span = info_span!("region_decompress", position = %position).entered();
                     let reg = Region::from_compressed(&compressed);
                     region.lock().unwrap().replace(reg);
                     ready.store(true, std::sync::atomic::Ordering::Relaxed);
@@ -712,4 +1516,149 @@ impl LoadingRegion {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sandworld::*;
+    use crate::generators::{Fill, GeneratorExt};
+
+    // Update without simulating until every corner of the area is loaded, failing rather than hanging if it never is
+    fn load_area(world: &mut World, bounds: GridBounds) {
+        let corners = [bounds.bottom_left(), bounds.top_left(), bounds.bottom_right(), bounds.top_right()];
+        for _ in 0..5000 {
+            if corners.iter().all(|corner| world.contains(*corner)) {
+                return;
+            }
+            world.update(bounds, WorldUpdateOptions { budget: UpdateBudget::Chunks(0), ..Default::default() });
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        panic!("{:?} did not load", bounds);
+    }
+
+    #[test]
+    fn time_budget_fits_measured_cost() {
+        let budget = UpdateBudget::Time(Duration::from_millis(10));
+        assert_eq!(budget.target_chunk_updates(0.001), 10);
+        // Never starved completely, or the cost could never be measured again
+        assert_eq!(budget.target_chunk_updates(1.), 1);
+        assert_eq!(UpdateBudget::Chunks(64).target_chunk_updates(1.), 64);
+    }
+
+    #[test]
+    fn update_count_counts_every_update() {
+        let mut world = World::new(Arc::new(Fill(Particle::new(ParticleType::Air))));
+        let bounds = GridBounds::new_from_extents(GridVec::new(0, 0), GridVec::new(1, 1));
+        assert_eq!(world.update_count(), 0);
+        for _ in 0..3 {
+            // Counted even when the budget allows no simulation
+            world.update(bounds, WorldUpdateOptions { budget: UpdateBudget::Chunks(0), ..Default::default() });
+        }
+        assert_eq!(world.update_count(), 3);
+    }
+
+    #[test]
+    fn area_render_composes_chunks() {
+        let mut world = World::new(Arc::new(Fill(Particle::new(ParticleType::Stone))));
+        let bounds = GridBounds::new_from_extents(GridVec::new(-3, -2), GridVec::new(2, 1));
+        load_area(&mut world, bounds);
+        world.replace_particle(GridVec::new(-3, 1), Particle::new(ParticleType::Water));
+
+        // Straddles four chunks, the water is in the top left corner
+        let bytes = world.render_to_color_array(bounds, &ChunkRenderOptions::default());
+        assert_eq!(bytes.len(), 6 * 4 * 4);
+        assert_eq!(bytes[0..4], get_color_for_type(ParticleType::Water));
+        assert_eq!(bytes[bytes.len() - 4..], get_color_for_type(ParticleType::Stone));
+
+        // Nothing loaded out here
+        let unloaded = GridBounds::new_from_extents(GridVec::new(100_000, 0), GridVec::new(100_001, 1));
+        assert!(world.render_to_color_array(unloaded, &ChunkRenderOptions::default()).iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn ejecta_wait_for_their_region_to_load() {
+        let ground = Fill(Particle::new(ParticleType::Stone)).masked(|pos: GridVec| -1. - pos.y as f64, 0.);
+        let mut world = World::new(Arc::new(ground));
+        world.set_ejecta_options(EjectaOptions { max_ejecta: 1, ..Default::default() });
+
+        // Nothing is loaded yet, so there's nowhere for it to land, it's parked rather than kept flying
+        assert!(world.spawn_ejecta(Particle::new(ParticleType::Sand), 0, [0.5, 0.05], [0., 0.]));
+        for _ in 0..10 {
+            world.update_ejecta(1. / 60.);
+        }
+        assert!(world.get_ejecta().is_empty());
+        assert_eq!(world.parked_ejecta.len(), 1);
+        assert_eq!(world.parked_ejecta[0].attempts, 0);
+
+        // Parked ejecta don't count against the cap
+        assert!(world.spawn_ejecta(Particle::new(ParticleType::Sand), 0, [0.5, 100.], [0., 0.]));
+        world.ejecta.clear();
+
+        let bounds = GridBounds::new_from_extents(GridVec::new(0, -1), GridVec::new(1, 1));
+        load_area(&mut world, bounds);
+
+        world.update_ejecta(1. / 60.);
+        assert!(world.parked_ejecta.is_empty());
+        assert_eq!(world.get_particle(GridVec::new(0, 0)).particle_type, ParticleType::Sand);
+    }
+
+    #[test]
+    fn parked_ejecta_expire() {
+        let mut world = World::new(Arc::new(Fill(Particle::new(ParticleType::Stone))));
+        world.set_ejecta_options(EjectaOptions { park_time: 1., ..Default::default() });
+
+        world.spawn_ejecta(Particle::new(ParticleType::Sand), 0, [0.5, 0.5], [0., 0.]);
+        world.update_ejecta(0.6);
+        assert_eq!(world.parked_ejecta.len(), 1);
+        world.update_ejecta(0.6);
+        assert!(world.parked_ejecta.is_empty());
+    }
+
+    #[test]
+    fn ejecta_carry_their_temperature() {
+        let ground = Fill(Particle::new(ParticleType::Lava)).masked(|pos: GridVec| -1. - pos.y as f64, 0.);
+        let mut world = World::new(Arc::new(ground));
+        let bounds = GridBounds::new(GridVec::new(0, 0), GridVec::new(8, 8));
+        load_area(&mut world, bounds);
+
+        // Thrown from deep in the lava, so it takes the pool's heat with it
+        let lava = particle_set![ParticleType::Lava];
+        assert_eq!(world.extract_ejecta_circle(GridVec::new(0, -5), 1, lava, 0.), 1);
+        assert_eq!(world.get_ejecta()[0].temperature(), get_heat_for_type(ParticleType::Lava) * 8);
+        world.ejecta.clear();
+
+        // Cold sand lands as sand, sand carrying far more heat than it melts at lands molten and melts what it hit
+        world.replace_particle(GridVec::new(2, -1), Particle::new(ParticleType::Stone));
+        world.replace_particle(GridVec::new(4, -1), Particle::new(ParticleType::Stone));
+        world.spawn_ejecta(Particle::new(ParticleType::Sand), 0, [2.5, 0.5], [0., 0.]);
+        world.spawn_ejecta(Particle::new(ParticleType::Sand), 100_000, [4.5, 0.5], [0., 0.]);
+        for _ in 0..60 {
+            world.update_ejecta(1. / 60.);
+        }
+        assert!(world.get_ejecta().is_empty() && world.parked_ejecta.is_empty());
+        assert_eq!(world.get_particle(GridVec::new(2, 0)).particle_type, ParticleType::Sand);
+        assert_eq!(world.get_particle(GridVec::new(4, 0)).particle_type, ParticleType::MoltenGlass);
+        assert_eq!(world.get_particle(GridVec::new(4, -1)).particle_type, ParticleType::Lava);
+    }
+
+    #[test]
+    fn displace_circle_pushes_particles_out() {
+        let mut world = World::new(Arc::new(Fill(Particle::new(ParticleType::Air))));
+        let bounds = GridBounds::new(GridVec::new(0, 0), GridVec::new(40, 40));
+        load_area(&mut world, bounds);
+
+        let center = GridVec::new(3, -2);
+        world.place_circle(center, 10, Particle::new(ParticleType::Water), true);
+        let count_water = |world: &World| bounds.iter().filter(|pos| world.get_particle(*pos).particle_type == ParticleType::Water).count();
+        let before = count_water(&world);
+
+        let moved = world.displace_circle(center, 6, particle_set![ParticleType::Water], 12);
+        assert!(moved > 0);
+        assert_eq!(count_water(&world), before);
+        for pos in GridBounds::new(center, GridVec::new(6, 6)).iter() {
+            if center.sq_distance(pos) < 36 {
+                assert_eq!(world.get_particle(pos).particle_type, ParticleType::Air);
+            }
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use gridmath::GridVec;
+use rhai::{Array, Dynamic, Engine, Module, Scope, AST};
+
+use crate::behavior::{ChunkCommand, ParticleBehavior};
+use crate::explosions::Explosion;
+use crate::particle::{Particle, ParticleType};
+
+const UPDATE_FN_NAME: &str = "update";
+const PLACE_FN_NAME: &str = "on_place";
+const DESTROY_FN_NAME: &str = "on_destroy";
+
+// Scripts run inside chunk updates, so a runaway loop or recursion has to be cut off rather than hang the simulation
+// Going over either limit is a runtime error like any other
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_CALL_LEVELS: usize = 32;
+
+/*
+    Compiles Rhai scripts into particle behaviors
+    A script must define `fn update(pos, particle, neighbors)` and may also define
+    `fn on_place(pos, particle)` and `fn on_destroy(pos, particle)`. Each returns either a single
+    command, an array of commands, or nothing at all
+
+    pos         - position of the particle within its chunk (pos.x, pos.y)
+    particle    - the particle being updated (particle.kind, particle.data, and the data fields
+                  particle.direction, .mode, .charge, .lifetime, .variant)
+                  use particle.with_mode(2).data etc to build data for the commands below
+    neighbors   - array of the 8 surrounding particle types, clockwise starting from directly above
+
+    Commands available to scripts, see ChunkCommand for details:
+        add(pos, Type::Water, data)     - place a particle at a chunk position if it is Air
+        emit(vec(0, 1), Type::Steam, 0) - place a particle at an offset if it is Air
+        move_to([vec(0, -1), ...])      - replace the default movement with these offsets
+        move_or_destroy([vec(1, 0)])    - as move_to, but remove the particle if none are possible
+        swap(vec(0, -1))                - trade places with the particle at an offset
+        heat(vec(1, 0), 800)            - expose the particle at an offset to a temperature
+        remove()                        - replace the particle with Air
+        mutate(Type::Steam, data)       - replace the particle with a new type and data
+        event(id)                       - raise a ParticleEvent for the game to handle
+        explode(radius, power)          - set off an explosion centered on the particle
+*/
+#[derive(Clone)]
+pub struct ScriptEngine {
+    engine: Arc<Engine>,
+}
+
+pub struct ScriptedBehavior {
+    engine: Arc<Engine>,
+    ast: AST,
+    has_place_fn: bool,
+    has_destroy_fn: bool,
+    // A broken script fails for every particle on every update, so only the first error is printed
+    error_reported: AtomicBool,
+}
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Parse(String),
+    MissingUpdateFn,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Parse(msg) => write!(f, "failed to parse script: {}", msg),
+            ScriptError::MissingUpdateFn => write!(f, "script does not define `fn {}(pos, particle, neighbors)`", UPDATE_FN_NAME),
+        }
+    }
+}
+
+impl ScriptEngine {
+    pub fn new() -> Self {
+        ScriptEngine {
+            engine: Arc::new(Self::build_engine()),
+        }
+    }
+
+    fn build_engine() -> Engine {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS);
+
+        engine.register_type_with_name::<GridVec>("Vec")
+            .register_fn("vec", |x: i64, y: i64| GridVec::new(x as i32, y as i32))
+            .register_get("x", |v: &mut GridVec| v.x as i64)
+            .register_get("y", |v: &mut GridVec| v.y as i64)
+            .register_fn("+", |a: GridVec, b: GridVec| a + b)
+            .register_fn("-", |a: GridVec, b: GridVec| a - b);
+
+        engine.register_type_with_name::<ParticleType>("ParticleType")
+            .register_fn("==", |a: ParticleType, b: ParticleType| a == b)
+            .register_fn("!=", |a: ParticleType, b: ParticleType| a != b)
+            .register_fn("to_string", |t: &mut ParticleType| format!("{:?}", t));
+
+        engine.register_type_with_name::<Particle>("Particle")
+            .register_get("kind", |p: &mut Particle| p.particle_type)
+            .register_get("data", |p: &mut Particle| p.data() as i64)
+            .register_get("direction", |p: &mut Particle| p.direction() as i64)
+            .register_get("mode", |p: &mut Particle| p.mode() as i64)
+            .register_get("charge", |p: &mut Particle| p.charge() as i64)
+            .register_get("lifetime", |p: &mut Particle| p.lifetime() as i64)
+            .register_get("variant", |p: &mut Particle| p.variant() as i64)
+            .register_fn("with_direction", |p: Particle, v: i64| p.with_direction(v as u8))
+            .register_fn("with_mode", |p: Particle, v: i64| p.with_mode(v as u8))
+            .register_fn("with_charge", |p: Particle, v: i64| p.with_charge(v as u8))
+            .register_fn("with_lifetime", |p: Particle, v: i64| p.with_lifetime(v as u8));
+
+        let mut types = Module::new();
+        for particle_type in ParticleType::ALL {
+            types.set_var(format!("{:?}", particle_type), particle_type);
+        }
+        engine.register_static_module("Type", types.into());
+
+        engine.register_type_with_name::<ChunkCommand>("Command")
+            .register_fn("add", |pos: GridVec, particle_type: ParticleType, data: i64| ChunkCommand::Add((pos, particle_type, data as u16)))
+            .register_fn("emit", |offset: GridVec, particle_type: ParticleType, data: i64| ChunkCommand::Emit((offset, particle_type, data as u16)))
+            .register_fn("move_to", |moves: Array| ChunkCommand::Move(offsets_from_array(moves)))
+            .register_fn("move_or_destroy", |moves: Array| ChunkCommand::MoveOrDestroy(offsets_from_array(moves)))
+            .register_fn("swap", |offset: GridVec| ChunkCommand::Swap(offset))
+            .register_fn("heat", |offset: GridVec, temperature: i64| ChunkCommand::Heat((offset, temperature as i32)))
+            .register_fn("remove", || ChunkCommand::Remove)
+            .register_fn("mutate", |particle_type: ParticleType, data: i64| ChunkCommand::Mutate(particle_type, data as u16))
+            .register_fn("event", |event_id: i64| ChunkCommand::SpawnEvent(event_id as u32))
+            .register_fn("explode", |radius: i64, power: f64| ChunkCommand::Explode(Explosion::new(radius as i32, power as f32)));
+
+        engine
+    }
+
+    pub fn compile(&self, source: &str) -> Result<ScriptedBehavior, ScriptError> {
+        let ast = self.engine.compile(source).map_err(|err| ScriptError::Parse(err.to_string()))?;
+        let has_fn = |name: &str, params: usize| ast.iter_functions().any(|f| f.name == name && f.params.len() == params);
+
+        if !has_fn(UPDATE_FN_NAME, 3) {
+            return Err(ScriptError::MissingUpdateFn);
+        }
+
+        Ok(ScriptedBehavior {
+            engine: self.engine.clone(),
+            has_place_fn: has_fn(PLACE_FN_NAME, 2),
+            has_destroy_fn: has_fn(DESTROY_FN_NAME, 2),
+            error_reported: AtomicBool::new(false),
+            ast,
+        })
+    }
+}
+
+impl Default for ScriptEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScriptedBehavior {
+    fn call(&self, fn_name: &str, particle: Particle, args: impl rhai::FuncArgs) -> Vec<ChunkCommand> {
+        let mut scope = Scope::new();
+
+        match self.engine.call_fn::<Dynamic>(&mut scope, &self.ast, fn_name, args) {
+            Ok(result) => commands_from_result(result),
+            Err(err) => {
+                if !self.error_reported.swap(true, Ordering::Relaxed) {
+                    println!("Script {} for {:?} failed: {} (further errors from this script are not shown)", fn_name, particle.particle_type, err);
+                }
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl ParticleBehavior for ScriptedBehavior {
+    fn update(&self, position: GridVec, particle: Particle, neighbors: &[ParticleType; 8]) -> Vec<ChunkCommand> {
+        let neighbor_array: Array = neighbors.iter().map(|n| Dynamic::from(*n)).collect();
+        self.call(UPDATE_FN_NAME, particle, (position, particle, neighbor_array))
+    }
+
+    fn on_place(&self, position: GridVec, particle: Particle) -> Vec<ChunkCommand> {
+        if self.has_place_fn { self.call(PLACE_FN_NAME, particle, (position, particle)) } else { Vec::new() }
+    }
+
+    fn on_destroy(&self, position: GridVec, particle: Particle) -> Vec<ChunkCommand> {
+        if self.has_destroy_fn { self.call(DESTROY_FN_NAME, particle, (position, particle)) } else { Vec::new() }
+    }
+}
+
+fn offsets_from_array(moves: Array) -> Vec<GridVec> {
+    moves.into_iter().filter_map(|m| m.try_cast::<GridVec>()).collect()
+}
+
+fn commands_from_result(result: Dynamic) -> Vec<ChunkCommand> {
+    if result.is::<ChunkCommand>() {
+        vec![result.cast::<ChunkCommand>()]
+    }
+    else if result.is::<Array>() {
+        result.cast::<Array>().into_iter().filter_map(|c| c.try_cast::<ChunkCommand>()).collect()
+    }
+    else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scripting::*;
+
+    #[test]
+    fn script_without_update_is_rejected() {
+        let engine = ScriptEngine::new();
+        let result = engine.compile("fn tick(a) { a }");
+        assert!(matches!(result, Err(ScriptError::MissingUpdateFn)));
+    }
+
+    #[test]
+    fn script_commands_are_returned() {
+        let engine = ScriptEngine::new();
+        let behavior = engine.compile(r#"
+            fn update(pos, particle, neighbors) {
+                if neighbors[4] == Type::Water {
+                    [mutate(Type::Gravel, 0), add(pos + vec(0, 1), Type::Steam, 0)]
+                }
+            }
+        "#).unwrap();
+
+        let mut neighbors = [ParticleType::Air; 8];
+        let dry = behavior.update(GridVec::new(4, 4), Particle::new(ParticleType::Sand), &neighbors);
+        assert!(dry.is_empty());
+
+        neighbors[4] = ParticleType::Water;
+        let wet = behavior.update(GridVec::new(4, 4), Particle::new(ParticleType::Sand), &neighbors);
+        assert_eq!(wet.len(), 2);
+        assert!(matches!(wet[0], ChunkCommand::Mutate(ParticleType::Gravel, 0)));
+        assert!(matches!(wet[1], ChunkCommand::Add((GridVec { x: 4, y: 5 }, ParticleType::Steam, 0))));
+    }
+
+    #[test]
+    fn runtime_errors_are_reported_once() {
+        let engine = ScriptEngine::new();
+        let behavior = engine.compile("fn update(pos, particle, neighbors) { neighbors[20] }").unwrap();
+        assert!(!behavior.error_reported.load(Ordering::Relaxed));
+
+        for _ in 0..3 {
+            assert!(behavior.update(GridVec::new(0, 0), Particle::new(ParticleType::Sand), &[ParticleType::Air; 8]).is_empty());
+        }
+        assert!(behavior.error_reported.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn runaway_scripts_are_stopped() {
+        let engine = ScriptEngine::new();
+        let looping = engine.compile("fn update(pos, particle, neighbors) { while true {} }").unwrap();
+        assert!(looping.update(GridVec::new(0, 0), Particle::new(ParticleType::Sand), &[ParticleType::Air; 8]).is_empty());
+        assert!(looping.error_reported.load(Ordering::Relaxed));
+
+        let recursing = engine.compile(r#"
+            fn deeper(n) { deeper(n + 1) }
+            fn update(pos, particle, neighbors) { deeper(0) }
+        "#).unwrap();
+        assert!(recursing.update(GridVec::new(0, 0), Particle::new(ParticleType::Sand), &[ParticleType::Air; 8]).is_empty());
+        assert!(recursing.error_reported.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn missing_hooks_do_nothing() {
+        let engine = ScriptEngine::new();
+        let behavior = engine.compile("fn update(pos, particle, neighbors) { remove() }").unwrap();
+        assert!(behavior.on_place(GridVec::new(0, 0), Particle::new(ParticleType::Sand)).is_empty());
+        assert!(behavior.on_destroy(GridVec::new(0, 0), Particle::new(ParticleType::Sand)).is_empty());
+    }
+}
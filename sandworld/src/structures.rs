@@ -0,0 +1,457 @@
+use std::fmt;
+use std::sync::Arc;
+
+use gridmath::*;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::chunk::{Chunk, CHUNK_SIZE};
+use crate::particle::*;
+use crate::sandworld::{WorldGenerator, TRUE_REGION_SIZE};
+
+// How far down from a candidate position OnGround structures look for the ground
+const GROUND_SEARCH_DEPTH: i32 = 256;
+
+/*
+    A fixed arrangement of particles, stamped over generated terrain
+    Cells left as None keep whatever the generator put there
+*/
+#[derive(Debug, Clone)]
+pub struct Stamp {
+    width: i32,
+    height: i32,
+    cells: Vec<Option<Particle>>, // Rows from the bottom up
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StructurePlacement {
+    // Any position within the allowed heights
+    Anywhere,
+    // Bottom row resting on the first solid ground below the chosen position
+    OnGround,
+    // Only where the generator put something other than Air at the structure's center
+    Buried,
+}
+
+/*
+    A structure and the rules for where it appears
+    Each region gets `attempts` chances of `chance` to hold one, at positions picked from the world seed
+*/
+#[derive(Debug, Clone)]
+pub struct StructureType {
+    pub name: String,
+    pub stamp: Stamp,
+    pub placement: StructurePlacement,
+    pub attempts: u32,
+    pub chance: f64,
+    pub min_y: i32,
+    pub max_y: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacedStructure {
+    pub structure: usize, // Index into the StructureSet
+    pub origin: GridVec, // World position of the stamp's bottom left corner
+}
+
+// All the structures a world can place, and the seed deciding where they go
+#[derive(Debug, Clone, Default)]
+pub struct StructureSet {
+    seed: u64,
+    structures: Vec<StructureType>,
+}
+
+#[derive(Debug)]
+pub enum StampError {
+    MissingGrid,
+    BadLegendEntry(String),
+    UnknownParticleType(String),
+    BadSetting(String),
+    TooLarge,
+}
+
+impl fmt::Display for StampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StampError::MissingGrid => write!(f, "stamp has no grid after the `---` line"),
+            StampError::BadLegendEntry(line) => write!(f, "could not read stamp line `{}`", line),
+            StampError::UnknownParticleType(name) => write!(f, "unknown particle type `{}`", name),
+            StampError::BadSetting(line) => write!(f, "bad structure setting `{}`", line),
+            StampError::TooLarge => write!(f, "stamp is larger than a region"),
+        }
+    }
+}
+
+impl Stamp {
+    pub fn new(width: i32, height: i32) -> Self {
+        Stamp {
+            width,
+            height,
+            cells: vec![None; (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    // x and y from the bottom left
+    pub fn get(&self, x: i32, y: i32) -> Option<Particle> {
+        self.cells[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, particle: Option<Particle>) {
+        self.cells[(y * self.width + x) as usize] = particle;
+    }
+
+    // Write the part of the stamp that falls within a chunk
+    pub(crate) fn stamp_chunk(&self, chunk: &mut Chunk, origin: GridVec) {
+        let chunk_bounds = GridBounds::new_from_corner(chunk.position * CHUNK_SIZE as i32, GridVec::new(CHUNK_SIZE as i32, CHUNK_SIZE as i32));
+        let Some(overlap) = chunk_bounds.intersect(self.get_bounds(origin)) else {
+            return;
+        };
+
+        for world_pos in overlap.iter() {
+            let stamp_pos = world_pos - origin;
+            if let Some(particle) = self.get(stamp_pos.x, stamp_pos.y) {
+                let local = world_pos - chunk_bounds.bottom_left();
+                chunk.set_particle_sloppy(local.x as u8, local.y as u8, particle);
+            }
+        }
+    }
+
+    fn get_bounds(&self, origin: GridVec) -> GridBounds {
+        GridBounds::new_from_corner(origin, GridVec::new(self.width, self.height))
+    }
+}
+
+impl StructureType {
+    /*
+        Reads a structure from text, eg
+
+            # A small ruin that sits on the surface
+            placement = ground
+            chance = 0.5
+            S = Stone
+            E = LaserEmitter:1
+            ---
+            SSSS
+            S  S
+            SSSS
+
+        Lines above the `---` are either settings (name, placement, chance, attempts, min_y, max_y) or legend entries
+        giving a particle type for a single character, optionally with its raw data after a colon.
+        Rows below it are the stamp from the top down, any character not in the legend leaves the terrain alone
+    */
+    pub fn parse(name: &str, text: &str) -> Result<StructureType, StampError> {
+        let mut structure = StructureType {
+            name: name.to_string(),
+            stamp: Stamp::new(0, 0),
+            placement: StructurePlacement::Anywhere,
+            attempts: 1,
+            chance: 1.,
+            min_y: i32::MIN,
+            max_y: i32::MAX,
+        };
+        let mut legend: Vec<(char, Particle)> = Vec::new();
+
+        let mut lines = text.lines();
+        let mut found_grid = false;
+        for line in lines.by_ref() {
+            let line = line.trim();
+            if line == "---" {
+                found_grid = true;
+                break;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(StampError::BadLegendEntry(line.to_string()));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let bad_setting = || StampError::BadSetting(line.to_string());
+
+            match key {
+                "name" => structure.name = value.to_string(),
+                "placement" => structure.placement = match value {
+                    "anywhere" => StructurePlacement::Anywhere,
+                    "ground" => StructurePlacement::OnGround,
+                    "buried" => StructurePlacement::Buried,
+                    _ => return Err(bad_setting()),
+                },
+                "chance" => structure.chance = value.parse().map_err(|_| bad_setting())?,
+                "attempts" => structure.attempts = value.parse().map_err(|_| bad_setting())?,
+                "min_y" => structure.min_y = value.parse().map_err(|_| bad_setting())?,
+                "max_y" => structure.max_y = value.parse().map_err(|_| bad_setting())?,
+                _ => {
+                    let mut chars = key.chars();
+                    let (Some(symbol), None) = (chars.next(), chars.next()) else {
+                        return Err(StampError::BadLegendEntry(line.to_string()));
+                    };
+                    legend.push((symbol, parse_particle(value)?));
+                }
+            }
+        }
+
+        if !found_grid {
+            return Err(StampError::MissingGrid);
+        }
+
+        let rows: Vec<&str> = lines.collect();
+        let height = rows.len() as i32;
+        let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0) as i32;
+        if width > TRUE_REGION_SIZE as i32 || height > TRUE_REGION_SIZE as i32 {
+            return Err(StampError::TooLarge);
+        }
+
+        let mut stamp = Stamp::new(width, height);
+        for (row_index, row) in rows.iter().enumerate() {
+            let y = height - row_index as i32 - 1;
+            for (x, symbol) in row.chars().enumerate() {
+                let particle = legend.iter().find(|(c, _)| *c == symbol).map(|(_, particle)| *particle);
+                stamp.set(x as i32, y, particle);
+            }
+        }
+
+        structure.stamp = stamp;
+        Ok(structure)
+    }
+}
+
+fn parse_particle(value: &str) -> Result<Particle, StampError> {
+    let (type_name, data) = match value.split_once(':') {
+        Some((type_name, data)) => (type_name.trim(), data.trim().parse().map_err(|_| StampError::BadSetting(value.to_string()))?),
+        None => (value, 0),
+    };
+
+    let particle_type = ParticleType::from_name(type_name).ok_or_else(|| StampError::UnknownParticleType(type_name.to_string()))?;
+    Ok(Particle::new_with_data(particle_type, data))
+}
+
+// Mix a seed with a region position into a seed for that region's own random choices
+fn region_seed(seed: u64, regpos: GridVec, structure: usize) -> u64 {
+    let mut hash = seed ^ 0x9e3779b97f4a7c15;
+    for value in [regpos.x as u64, regpos.y as u64, structure as u64] {
+        hash = (hash ^ value).wrapping_mul(0xbf58476d1ce4e5b9);
+        hash ^= hash >> 31;
+    }
+    hash
+}
+
+impl StructureSet {
+    pub fn new(seed: u64) -> Self {
+        StructureSet {
+            seed,
+            structures: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, structure: StructureType) {
+        self.structures.push(structure);
+    }
+
+    pub fn get(&self, index: usize) -> &StructureType {
+        &self.structures[index]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.structures.is_empty()
+    }
+
+    // Structures whose origin falls in a region, the same every time for a given seed and generator
+    pub fn get_region_placements(&self, regpos: GridVec, generator: &Arc<dyn WorldGenerator + Send + Sync>) -> Vec<PlacedStructure> {
+        let region_size = TRUE_REGION_SIZE as i32;
+        let region_root = regpos * region_size;
+        let mut placements = Vec::new();
+
+        for (index, structure) in self.structures.iter().enumerate() {
+            let mut rng = StdRng::seed_from_u64(region_seed(self.seed, regpos, index));
+
+            for _ in 0..structure.attempts {
+                let candidate = region_root + GridVec::new(rng.gen_range(0..region_size), rng.gen_range(0..region_size));
+                if !rng.gen_bool(structure.chance.clamp(0., 1.)) {
+                    continue;
+                }
+
+                let origin = match structure.placement {
+                    StructurePlacement::Anywhere => Some(candidate),
+                    StructurePlacement::OnGround => find_ground(candidate, generator),
+                    StructurePlacement::Buried => {
+                        let center = candidate + GridVec::new(structure.stamp.width / 2, structure.stamp.height / 2);
+                        if generator.get_particle(center).particle_type != ParticleType::Air { Some(candidate) } else { None }
+                    }
+                };
+
+                if let Some(origin) = origin {
+                    if origin.y >= structure.min_y && origin.y <= structure.max_y {
+                        placements.push(PlacedStructure { structure: index, origin });
+                    }
+                }
+            }
+        }
+
+        placements
+    }
+
+    /*
+        Every structure overlapping a region, including ones placed by the regions around it
+        Stamps reach up and right from their origin, and OnGround structures can drop up to GROUND_SEARCH_DEPTH
+        from a candidate in the region above, so a stamp can end up overlapping any of its neighbors
+        Ordered so overlapping structures resolve the same way whichever region is generating
+    */
+    pub(crate) fn get_overlapping_placements(&self, regpos: GridVec, generator: &Arc<dyn WorldGenerator + Send + Sync>) -> Vec<PlacedStructure> {
+        let region_size = TRUE_REGION_SIZE as i32;
+        let region_bounds = GridBounds::new_from_corner(regpos * region_size, GridVec::new(region_size, region_size));
+
+        let mut placements = Vec::new();
+        for y in -1..=1 {
+            for x in -1..=1 {
+                for placed in self.get_region_placements(regpos + GridVec::new(x, y), generator) {
+                    if self.structures[placed.structure].stamp.get_bounds(placed.origin).overlaps(region_bounds) {
+                        placements.push(placed);
+                    }
+                }
+            }
+        }
+
+        placements
+    }
+}
+
+fn find_ground(start: GridVec, generator: &Arc<dyn WorldGenerator + Send + Sync>) -> Option<GridVec> {
+    let mut above_is_air = generator.get_particle(start).particle_type == ParticleType::Air;
+    for depth in 1..GROUND_SEARCH_DEPTH {
+        let pos = start - GridVec::new(0, depth);
+        let is_air = generator.get_particle(pos).particle_type == ParticleType::Air;
+        if above_is_air && !is_air {
+            return Some(pos + GridVec::new(0, 1));
+        }
+        above_is_air = is_air;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structures::*;
+
+    const HUT: &str = "
+        # test hut
+        placement = ground
+        attempts = 3
+        S = Stone
+        E = LaserEmitter:1
+        ---
+        SSSS
+        S  E
+        SSSS
+    ";
+
+    // Indented to sit with the test code, the stamp rows have to be trimmed before parsing
+    fn hut() -> StructureType {
+        StructureType::parse("hut", &HUT.lines().map(str::trim).collect::<Vec<_>>().join("\n")).unwrap()
+    }
+
+    struct Flat;
+
+    impl WorldGenerator for Flat {
+        fn get_particle(&self, world_pos: GridVec) -> Particle {
+            Particle::new(if world_pos.y < 10 { ParticleType::Sand } else { ParticleType::Air })
+        }
+    }
+
+    // Ground right below the top of region 0, so huts dropped from region 1 straddle the boundary
+    struct BoundaryGround;
+
+    impl WorldGenerator for BoundaryGround {
+        fn get_particle(&self, world_pos: GridVec) -> Particle {
+            Particle::new(if world_pos.y < TRUE_REGION_SIZE as i32 - 1 { ParticleType::Sand } else { ParticleType::Air })
+        }
+    }
+
+    #[test]
+    fn stamps_parse_from_text() {
+        let structure = hut();
+        assert_eq!(structure.placement, StructurePlacement::OnGround);
+        assert_eq!(structure.attempts, 3);
+        assert_eq!((structure.stamp.width(), structure.stamp.height()), (4, 3));
+        assert_eq!(structure.stamp.get(0, 2).unwrap().particle_type, ParticleType::Stone);
+        assert!(structure.stamp.get(1, 1).is_none());
+        assert_eq!(structure.stamp.get(3, 1).unwrap().direction(), 1);
+
+        assert!(matches!(StructureType::parse("bad", "S = Stone"), Err(StampError::MissingGrid)));
+        assert!(matches!(StructureType::parse("bad", "S = Bedrock\n---\nS"), Err(StampError::UnknownParticleType(_))));
+    }
+
+    #[test]
+    fn placements_are_deterministic_and_grounded() {
+        let structure = hut();
+        let generator: Arc<dyn WorldGenerator + Send + Sync> = Arc::new(Flat);
+
+        let mut set = StructureSet::new(42);
+        set.add(structure);
+
+        let regpos = GridVec::new(3, 0);
+        let placements = set.get_region_placements(regpos, &generator);
+        assert_eq!(placements, set.get_region_placements(regpos, &generator));
+        for placed in placements {
+            assert_eq!(placed.origin.y, 10);
+        }
+    }
+
+    #[test]
+    fn stamps_cross_chunk_edges() {
+        let structure = hut();
+        let origin = GridVec::new(62, 5);
+
+        let mut left = Chunk::new(GridVec::new(0, 0));
+        let mut right = Chunk::new(GridVec::new(1, 0));
+        structure.stamp.stamp_chunk(&mut left, origin);
+        structure.stamp.stamp_chunk(&mut right, origin);
+
+        assert_eq!(left.get_particle(62, 7).particle_type, ParticleType::Stone);
+        assert_eq!(left.get_particle(63, 6).particle_type, ParticleType::Air);
+        assert_eq!(right.get_particle(1, 6).particle_type, ParticleType::LaserEmitter);
+    }
+
+    #[test]
+    fn dropped_structures_reach_the_region_below() {
+        let mut structure = hut();
+        structure.attempts = 32;
+        let generator: Arc<dyn WorldGenerator + Send + Sync> = Arc::new(BoundaryGround);
+
+        let mut set = StructureSet::new(7);
+        set.add(structure);
+
+        // Placed by the region above, but resting on the ground in the region below
+        let dropped = set.get_region_placements(GridVec::new(0, 1), &generator);
+        assert!(!dropped.is_empty());
+        let below = set.get_overlapping_placements(GridVec::new(0, 0), &generator);
+        let above = set.get_overlapping_placements(GridVec::new(0, 1), &generator);
+        for &placed in dropped.iter() {
+            assert_eq!(placed.origin.y, TRUE_REGION_SIZE as i32 - 1);
+            assert!(below.contains(&placed));
+            assert!(above.contains(&placed));
+        }
+
+        // Both halves get stamped, whichever region generates first
+        let placed = dropped[0];
+        let chunk_x = placed.origin.x.div_euclid(CHUNK_SIZE as i32);
+        let boundary_chunk = TRUE_REGION_SIZE as i32 / CHUNK_SIZE as i32;
+        let mut bottom = Chunk::new(GridVec::new(chunk_x, boundary_chunk - 1));
+        let mut top = Chunk::new(GridVec::new(chunk_x, boundary_chunk));
+        for chunk in [&mut bottom, &mut top] {
+            chunk.regenerate(&generator);
+            set.get(placed.structure).stamp.stamp_chunk(chunk, placed.origin);
+        }
+        let x = placed.origin.x.rem_euclid(CHUNK_SIZE as i32) as u8;
+        assert_eq!(bottom.get_particle(x, CHUNK_SIZE - 1).particle_type, ParticleType::Stone);
+        assert_eq!(top.get_particle(x, 1).particle_type, ParticleType::Stone);
+    }
+}
@@ -0,0 +1,853 @@
+use gridmath::GridVec;
+use noise::{NoiseFn, Perlin};
+use crate::{particle_set, position_noise, Fill, CHUNK_SIZE, GeneratorExt, Particle, ParticleSet, ParticleType, ScalarField, WorldGenerator};
+
+pub struct Blankworld {}
+
+pub struct FlatPlain {
+    pub stone_height: i32,
+    pub sand_height: i32,
+}
+
+pub struct BasicPerlin {
+    noise: Perlin,
+    stone_threshold: f64,
+    scale_x: f64,
+    scale_y: f64,
+}
+
+pub struct LayeredPerlin {
+    noise: Perlin,
+    scale_macro: f64,
+    scale_detail: f64,
+    cave_noisiness: f64,
+}
+
+pub struct WorldBuilder {
+    noise: Perlin,
+    terrain_scale: f64,
+    terrain_height: f64,
+    cave_scale: f64,
+    lava_scale: f64,
+    lava_depth: f64, 
+}
+
+impl WorldGenerator for Blankworld {
+    fn get_particle(&self, _world_pos: GridVec) -> Particle {
+        Particle::new(ParticleType::Air)
+    }
+}
+
+impl WorldGenerator for FlatPlain {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        Particle::new(if world_pos.y > self.stone_height {
+            if world_pos.y > self.sand_height {
+                ParticleType::Air
+            } else {
+                ParticleType::Sand
+            }
+        } else {
+            ParticleType::Stone
+        })
+    }
+}
+
+impl BasicPerlin {
+    pub fn new(seed: u32, scale: f64) -> Self {
+        BasicPerlin {
+            noise: Perlin::new(seed),
+            stone_threshold: 0.05,
+            scale_x: scale,
+            scale_y: scale,
+        }
+    }
+}
+
+impl WorldGenerator for BasicPerlin {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        let sample_pos = [
+            world_pos.x as f64 * self.scale_x,
+            world_pos.y as f64 * self.scale_y,
+        ];
+        let noise_val = self.noise.get(sample_pos);
+
+        Particle::new(if noise_val < self.stone_threshold {
+            ParticleType::Air
+        } else {
+            ParticleType::Stone
+        })
+    }
+}
+
+impl LayeredPerlin {
+    pub fn new(seed: u32, scale_macro: f64, scale_detail: f64, cave_noisiness: f64) -> Self {
+        LayeredPerlin {
+            noise: Perlin::new(seed),
+            scale_macro,
+            scale_detail,
+            cave_noisiness,
+        }
+    }
+}
+
+impl WorldGenerator for LayeredPerlin {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        let macro_sample_pos = [
+            world_pos.x as f64 * self.scale_macro,
+            world_pos.y as f64 * self.scale_macro,
+        ];
+        let detail_sample_pos = [
+            world_pos.x as f64 * self.scale_detail,
+            world_pos.y as f64 * self.scale_detail,
+        ];
+        let lava_sample_pos = [
+            world_pos.x as f64 * self.scale_detail * 0.13,
+            world_pos.y as f64 * self.scale_detail * 0.21,
+        ];
+
+        //let cave_sample_pos = [world_pos.x as f64 * self.scale_cave_density, world_pos.y as f64 * self.scale_cave_density];
+        let macro_noise_val = self.noise.get(macro_sample_pos) / 2. + 1.;
+        let detail_noise_val = self.noise.get(detail_sample_pos) / 2. + 1.;
+        //let cave_sample_noise_val = self.noise.get(cave_sample_pos);
+
+        let lava_noise_val = self.noise.get(lava_sample_pos).powi(2);
+        
+        Particle::new(
+            if detail_noise_val < macro_noise_val * self.cave_noisiness {
+                ParticleType::Air
+            } else {
+                let surf_dist = 0.90 - (macro_noise_val * self.cave_noisiness / detail_noise_val);
+                if lava_noise_val < 0.005 * surf_dist {
+                    ParticleType::Lava
+                }
+                else {
+                    ParticleType::Stone
+                }
+            },
+        )
+    }
+}
+
+impl WorldBuilder {
+    pub fn new(seed: u32, terrain_scale: f64, terrain_height: f64, cave_scale: f64, lava_depth: f64, lava_scale: f64) -> Self {
+        WorldBuilder {
+            noise: Perlin::new(seed),
+            terrain_scale,
+            terrain_height,
+            cave_scale,
+            lava_depth: lava_depth * 100.,
+            lava_scale,
+        }
+    }
+}
+
+const DETAIL_SCALE_1: f64 = 8.7;
+const DETAIL_SCALE_2: f64 = 7.8;
+const DETAIL_SCALE_3: f64 = 9.3;
+
+impl WorldBuilder {
+    // Surface height only depends on x, so it can be shared by a whole column
+    fn terrain_height(&self, x: i32) -> f64 {
+        let terrain_sample_pos_broad = [x as f64 / self.terrain_scale, 0.];
+        let terrain_sample_pos_detail_1 = [x as f64 / (self.terrain_scale / DETAIL_SCALE_1), 0.1];
+        let terrain_sample_pos_detail_2 = [x as f64 / (self.terrain_scale / (DETAIL_SCALE_1 * DETAIL_SCALE_2)), 0.2];
+        let terrain_sample_pos_detail_3 = [x as f64 / (self.terrain_scale / (DETAIL_SCALE_1 * DETAIL_SCALE_2 * DETAIL_SCALE_3)), 0.3];
+
+        let terrain_height_broad = self.noise.get(terrain_sample_pos_broad) * self.terrain_height;
+        let terrain_height_detail_1 = self.noise.get(terrain_sample_pos_detail_1) * self.terrain_height / DETAIL_SCALE_1;
+        let terrain_height_detail_2 = self.noise.get(terrain_sample_pos_detail_2) * self.terrain_height / (DETAIL_SCALE_1 * DETAIL_SCALE_2);
+        let terrain_height_detail_3 = self.noise.get(terrain_sample_pos_detail_3) * self.terrain_height / (DETAIL_SCALE_1 * DETAIL_SCALE_2 * DETAIL_SCALE_3);
+        terrain_height_broad + terrain_height_detail_1 + terrain_height_detail_2 + terrain_height_detail_3
+    }
+
+    fn get_particle_at_height(&self, world_pos: GridVec, terrain_height: f64) -> Particle {
+        // let detail_sample_pos  = [world_pos.x as f64 / self.detail_scale, world_pos.y as f64 / self.detail_scale];
+
+        let from_terrain = world_pos.y as f64 - terrain_height;
+
+        Particle::new(
+            if from_terrain < 0. {
+                let cave_sample_pos = [world_pos.x as f64 / self.cave_scale / 2., world_pos.y as f64 / self.cave_scale];
+                let cave_detail_pos = [world_pos.x as f64 / (self.cave_scale / DETAIL_SCALE_1), world_pos.y as f64 / (self.cave_scale / DETAIL_SCALE_1)];
+                let cave_base_value = self.noise.get(cave_sample_pos).powi(2);
+                let cave_detail_value = self.noise.get(cave_detail_pos).abs();
+                let cave_value = cave_base_value + 0.03 * (cave_detail_value / DETAIL_SCALE_1);
+
+                let surface_avoidance = if world_pos.y <= 0 && terrain_height < 0. {
+                    (from_terrain / -256.).clamp(0., 1.)
+                }
+                else {
+                    let surface_dist_modifier: f64 =  (from_terrain / -1024.).clamp(0., 1.) * 0.67 + 0.33;
+                    let boundary_smoother = ((world_pos.y.abs() as f64 / 32.).clamp(0., 1.) + (terrain_height / 32.).clamp(0., 1.)).clamp(0., 1.);
+                    surface_dist_modifier * boundary_smoother
+                };
+
+                if cave_value + 0.001 < 0.01 * surface_avoidance {
+                    ParticleType::Air
+                }
+                else {
+                    let from_cave = (0.8 - ((0.01 * surface_avoidance) / (cave_value + 0.001))).clamp(0., 1.);
+                    let lava_mult = from_cave * (-from_terrain / self.lava_depth).abs();
+
+                    let lava_sample_pos = [world_pos.x as f64 / self.lava_scale, world_pos.y as f64 / self.lava_scale * 2.];
+                    let lava_noise_val = self.noise.get(lava_sample_pos).powi(2);
+
+                    if lava_noise_val + 0.01 < 0.1 * lava_mult {
+                        ParticleType::Lava
+                    }
+                    else {
+                        ParticleType::Stone
+                    }
+                }
+            }
+            else {
+                // Filled in, but ripple surface
+                if world_pos.y < 0 || (world_pos.y == 0 && world_pos.x % 3 == 0) {
+                    ParticleType::Water
+                }
+                else {
+                    ParticleType::Air
+                }
+            }
+        )
+    }
+}
+
+impl WorldGenerator for WorldBuilder {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        self.get_particle_at_height(world_pos, self.terrain_height(world_pos.x))
+    }
+
+    fn fill_chunk(&self, chunk_root: GridVec, particles: &mut [Particle]) {
+        let chunk_size = CHUNK_SIZE as i32;
+        let heights: Vec<f64> = (0..chunk_size).map(|x| self.terrain_height(chunk_root.x + x)).collect();
+
+        for y in 0..chunk_size {
+            for x in 0..chunk_size {
+                let world_pos = chunk_root + GridVec::new(x, y);
+                particles[(y * chunk_size + x) as usize] = self.get_particle_at_height(world_pos, heights[x as usize]);
+            }
+        }
+    }
+}
+
+/*
+    Building blocks for assembling worlds with the sandworld generator combinators
+    See assembled_world for an example of putting them together
+*/
+
+// Perlin noise from -1 to 1, usable as a field for masks, blends and warps
+#[derive(Clone)]
+pub struct NoiseField {
+    noise: Perlin,
+    scale_x: f64,
+    scale_y: f64,
+}
+
+// Rolling ground with a surface layer over a fill material, flooded up to sea level
+#[derive(Clone)]
+pub struct HeightmapTerrain {
+    noise: Perlin,
+    scale: f64,
+    base_height: f64,
+    amplitude: f64,
+    surface: Particle,
+    surface_depth: f64,
+    fill: Particle,
+    sea_level: i32,
+    sea: Particle,
+}
+
+// Field that drops below zero inside caves, which open up further with depth
+#[derive(Clone)]
+pub struct Caves {
+    noise: Perlin,
+    scale: f64,
+    openness: f64,
+    depth_scale: f64,
+}
+
+// Thin winding bands of one material, Air everywhere else
+pub struct OreVeins {
+    noise: Perlin,
+    ore: Particle,
+    scale: f64,
+    thickness: f64,
+}
+
+// Blobs of liquid below a given height, Air everywhere else
+pub struct LiquidPockets {
+    noise: Perlin,
+    liquid: Particle,
+    scale: f64,
+    threshold: f64,
+    max_height: i32,
+}
+
+impl NoiseField {
+    pub fn new(seed: u32, scale: f64) -> Self {
+        Self::stretched(seed, scale, scale)
+    }
+
+    pub fn stretched(seed: u32, scale_x: f64, scale_y: f64) -> Self {
+        NoiseField {
+            noise: Perlin::new(seed),
+            scale_x,
+            scale_y,
+        }
+    }
+}
+
+impl ScalarField for NoiseField {
+    fn sample(&self, pos: GridVec) -> f64 {
+        self.noise.get([pos.x as f64 / self.scale_x, pos.y as f64 / self.scale_y])
+    }
+}
+
+impl HeightmapTerrain {
+    pub fn new(seed: u32, scale: f64, base_height: f64, amplitude: f64) -> Self {
+        HeightmapTerrain {
+            noise: Perlin::new(seed),
+            scale,
+            base_height,
+            amplitude,
+            surface: Particle::new(ParticleType::Sand),
+            surface_depth: 24.,
+            fill: Particle::new(ParticleType::Stone),
+            sea_level: 0,
+            sea: Particle::new(ParticleType::Water),
+        }
+    }
+
+    pub fn with_materials(mut self, surface: Particle, surface_depth: f64, fill: Particle) -> Self {
+        self.surface = surface;
+        self.surface_depth = surface_depth;
+        self.fill = fill;
+        self
+    }
+
+    pub fn with_sea(mut self, sea_level: i32, sea: Particle) -> Self {
+        self.sea_level = sea_level;
+        self.sea = sea;
+        self
+    }
+
+    // Height of the ground at a column, broad hills with two octaves of detail
+    pub fn height_at(&self, x: i32) -> f64 {
+        let x = x as f64 / self.scale;
+        let broad = self.noise.get([x, 0.5]);
+        let detail = self.noise.get([x * 8., 1.5]) / 8. + self.noise.get([x * 64., 2.5]) / 64.;
+        self.base_height + (broad + detail) * self.amplitude
+    }
+}
+
+impl WorldGenerator for HeightmapTerrain {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        let depth = self.height_at(world_pos.x) - world_pos.y as f64;
+        if depth > self.surface_depth {
+            self.fill
+        }
+        else if depth > 0. {
+            self.surface
+        }
+        else if world_pos.y <= self.sea_level {
+            self.sea
+        }
+        else {
+            Particle::new(ParticleType::Air)
+        }
+    }
+}
+
+impl Caves {
+    pub fn new(seed: u32, scale: f64, openness: f64) -> Self {
+        Caves {
+            noise: Perlin::new(seed),
+            scale,
+            openness,
+            depth_scale: 2000.,
+        }
+    }
+}
+
+impl ScalarField for Caves {
+    fn sample(&self, pos: GridVec) -> f64 {
+        let tunnels = self.noise.get([pos.x as f64 / self.scale / 2., pos.y as f64 / self.scale]).abs();
+        let depth_boost = (-pos.y as f64 / self.depth_scale).clamp(0., 1.) + 1.;
+        tunnels - self.openness * depth_boost
+    }
+}
+
+impl OreVeins {
+    pub fn new(seed: u32, ore: Particle, scale: f64, thickness: f64) -> Self {
+        OreVeins {
+            noise: Perlin::new(seed),
+            ore,
+            scale,
+            thickness,
+        }
+    }
+}
+
+impl WorldGenerator for OreVeins {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        let sample_pos = [world_pos.x as f64 / self.scale, world_pos.y as f64 / self.scale];
+        if self.noise.get(sample_pos).abs() < self.thickness {
+            self.ore
+        }
+        else {
+            Particle::new(ParticleType::Air)
+        }
+    }
+}
+
+impl LiquidPockets {
+    pub fn new(seed: u32, liquid: Particle, scale: f64, threshold: f64, max_height: i32) -> Self {
+        LiquidPockets {
+            noise: Perlin::new(seed),
+            liquid,
+            scale,
+            threshold,
+            max_height,
+        }
+    }
+}
+
+impl WorldGenerator for LiquidPockets {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        let sample_pos = [world_pos.x as f64 / self.scale, world_pos.y as f64 / self.scale * 2.];
+        if world_pos.y < self.max_height && self.noise.get(sample_pos) > self.threshold {
+            self.liquid
+        }
+        else {
+            Particle::new(ParticleType::Air)
+        }
+    }
+}
+
+// Hills over caves with gravel veins, water pockets and deep lava, all built from the blocks above
+pub fn assembled_world(seed: u32) -> impl WorldGenerator + Send + Sync {
+    let sand_depth = 32.;
+    let terrain = HeightmapTerrain::new(seed, 5000., 0., 1500.)
+        .with_materials(Particle::new(ParticleType::Sand), sand_depth, Particle::new(ParticleType::Stone))
+        .with_sea(-100, Particle::new(ParticleType::Water));
+
+    // Keep caves under the sand so they don't punch holes in the sea
+    let surface = terrain.clone();
+    let caves = Caves::new(seed.wrapping_add(1), 500., 0.08);
+    let underground_caves = move |pos: GridVec| {
+        if pos.y as f64 > surface.height_at(pos.x) - sand_depth { 1. } else { caves.sample(pos) }
+    };
+
+    let gravel = OreVeins::new(seed.wrapping_add(2), Particle::new(ParticleType::Gravel), 180., 0.03);
+    let water = LiquidPockets::new(seed.wrapping_add(3), Particle::new(ParticleType::Water), 300., 0.4, -300);
+    let lava = LiquidPockets::new(seed.wrapping_add(4), Particle::new(ParticleType::Lava), 400., 0.45, -1500);
+    let lava_floor = Fill(Particle::new(ParticleType::Lava)).masked(|pos: GridVec| -pos.y as f64, 4000.);
+
+    terrain
+        .replace_where(particle_set![ParticleType::Stone], gravel)
+        .masked(underground_caves, 0.)
+        .replace_where(particle_set![ParticleType::Air], lava.layer(water))
+        .layer(lava_floor)
+        .warp(NoiseField::new(seed.wrapping_add(5), 300.), NoiseField::new(seed.wrapping_add(6), 300.), 24.)
+}
+
+/*
+    One entry in a BiomeWorld's biome table
+    Depths are measured down from the surface, with caves only opening up below cave_depth
+*/
+#[derive(Clone)]
+pub struct Biome {
+    pub name: String,
+    // Where this biome sits on the selection noise, from -1 to 1, biomes should be sorted by this
+    pub selector: f64,
+    // Raises or lowers the terrain, and scales its hills
+    pub height_offset: f64,
+    pub height_scale: f64,
+    pub surface: Particle,
+    pub surface_depth: f64,
+    pub subsurface: Particle,
+    pub subsurface_depth: f64,
+    pub fill: Particle,
+    // What fills the space above the ground below sea level
+    pub sea: Particle,
+    pub cave_depth: f64,
+    // Caves deeper than cave_liquid_depth are flooded with cave_liquid rather than left as Air
+    pub cave_liquid: Particle,
+    pub cave_liquid_depth: f64,
+}
+
+/*
+    Terrain split into biomes by low frequency noise along x, each with its own materials and terrain shape
+    Within blend_width of the border between two biomes their terrain shape is interpolated, and their materials
+    are mixed with a per particle dither so borders fade rather than cut
+*/
+pub struct BiomeWorld {
+    noise: Perlin,
+    biomes: Vec<Biome>,
+    biome_scale: f64,
+    blend_width: f64,
+    terrain_scale: f64,
+    terrain_height: f64,
+    sea_level: i32,
+    cave_scale: f64,
+    cave_openness: f64,
+}
+
+// The biomes either side of a column and how far it is towards the second
+#[derive(Clone, Copy)]
+struct BiomeColumn {
+    first: usize,
+    second: usize,
+    blend: f64,
+    height: f64,
+}
+
+fn smoothstep(t: f64) -> f64 {
+    let t = t.clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+impl Biome {
+    fn new(name: &str, selector: f64, surface: ParticleType, surface_depth: f64, subsurface: ParticleType, subsurface_depth: f64, sea: ParticleType) -> Self {
+        Biome {
+            name: name.to_string(),
+            selector,
+            height_offset: 0.,
+            height_scale: 1.,
+            surface: Particle::new(surface),
+            surface_depth,
+            subsurface: Particle::new(subsurface),
+            subsurface_depth,
+            fill: Particle::new(ParticleType::Stone),
+            sea: Particle::new(sea),
+            cave_depth: 48.,
+            cave_liquid: Particle::new(ParticleType::Water),
+            cave_liquid_depth: 1200.,
+        }
+    }
+
+    fn with_shape(mut self, height_offset: f64, height_scale: f64) -> Self {
+        self.height_offset = height_offset;
+        self.height_scale = height_scale;
+        self
+    }
+
+    fn with_cave_liquid(mut self, cave_liquid: ParticleType, cave_liquid_depth: f64) -> Self {
+        self.cave_liquid = Particle::new(cave_liquid);
+        self.cave_liquid_depth = cave_liquid_depth;
+        self
+    }
+
+    // Tundra, ocean, temperate hills, desert and volcanic biomes
+    pub fn default_table() -> Vec<Biome> {
+        vec![
+            Biome::new("Tundra", -0.6, ParticleType::Snow, 16., ParticleType::Ice, 64., ParticleType::Ice)
+                .with_shape(100., 0.8),
+            Biome::new("Ocean", -0.25, ParticleType::Sand, 12., ParticleType::Gravel, 40., ParticleType::Water)
+                .with_shape(-700., 0.4)
+                .with_cave_liquid(ParticleType::Water, 100.),
+            Biome::new("Temperate", 0.05, ParticleType::Gravel, 8., ParticleType::Stone, 0., ParticleType::Water),
+            Biome::new("Desert", 0.35, ParticleType::Sand, 160., ParticleType::Sand, 80., ParticleType::Water)
+                .with_shape(150., 0.5)
+                .with_cave_liquid(ParticleType::Lava, 2000.),
+            Biome::new("Volcanic", 0.65, ParticleType::Gravel, 24., ParticleType::Stone, 0., ParticleType::Lava)
+                .with_shape(-50., 1.4)
+                .with_cave_liquid(ParticleType::Lava, 300.),
+        ]
+    }
+}
+
+impl BiomeWorld {
+    // An empty table falls back to the default biomes, since there has to be something to pick
+    pub fn new(seed: u32, biomes: Vec<Biome>) -> Self {
+        BiomeWorld {
+            noise: Perlin::new(seed),
+            biomes: if biomes.is_empty() { Biome::default_table() } else { biomes },
+            biome_scale: 6000.,
+            blend_width: 0.08,
+            terrain_scale: 3000.,
+            terrain_height: 800.,
+            sea_level: 0,
+            cave_scale: 400.,
+            cave_openness: 0.09,
+        }
+    }
+
+    pub fn with_biome_scale(mut self, biome_scale: f64, blend_width: f64) -> Self {
+        self.biome_scale = biome_scale;
+        self.blend_width = blend_width;
+        self
+    }
+
+    pub fn with_terrain(mut self, terrain_scale: f64, terrain_height: f64, sea_level: i32) -> Self {
+        self.terrain_scale = terrain_scale;
+        self.terrain_height = terrain_height;
+        self.sea_level = sea_level;
+        self
+    }
+
+    pub fn with_caves(mut self, cave_scale: f64, cave_openness: f64) -> Self {
+        self.cave_scale = cave_scale;
+        self.cave_openness = cave_openness;
+        self
+    }
+
+    pub fn biome_at(&self, x: i32) -> &Biome {
+        let column = self.get_column(x);
+        &self.biomes[if column.blend < 0.5 { column.first } else { column.second }]
+    }
+
+    fn get_column(&self, x: i32) -> BiomeColumn {
+        let selector = self.noise.get([x as f64 / self.biome_scale, 17.3]).clamp(-1., 1.);
+
+        // Find the pair of biomes the selector falls between, past either end just use the end biome
+        let next = self.biomes.iter().position(|biome| biome.selector > selector).unwrap_or(self.biomes.len());
+        let (first, second, blend) = if next == 0 {
+            (0, 0, 0.)
+        }
+        else if next == self.biomes.len() {
+            (next - 1, next - 1, 0.)
+        }
+        else {
+            let border = (self.biomes[next - 1].selector + self.biomes[next].selector) / 2.;
+            let blend = smoothstep((selector - border) / self.blend_width.max(f64::EPSILON) + 0.5);
+            (next - 1, next, blend)
+        };
+
+        let (a, b) = (&self.biomes[first], &self.biomes[second]);
+        let hills = self.noise.get([x as f64 / self.terrain_scale, 0.5])
+            + self.noise.get([x as f64 / self.terrain_scale * 8., 1.5]) / 8.;
+        let height = hills * self.terrain_height * lerp(a.height_scale, b.height_scale, blend)
+            + lerp(a.height_offset, b.height_offset, blend);
+
+        BiomeColumn { first, second, blend, height }
+    }
+
+    fn get_particle_in_column(&self, world_pos: GridVec, column: BiomeColumn) -> Particle {
+        let dither = (position_noise(world_pos) as f64 + 1.) / 2.;
+        let biome = &self.biomes[if column.blend > dither { column.second } else { column.first }];
+        let depth = column.height - world_pos.y as f64;
+
+        if depth <= 0. {
+            return if world_pos.y <= self.sea_level { biome.sea } else { Particle::new(ParticleType::Air) };
+        }
+
+        if depth > biome.cave_depth {
+            let cave_sample_pos = [world_pos.x as f64 / self.cave_scale / 2., world_pos.y as f64 / self.cave_scale];
+            let depth_boost = (depth / 2000.).clamp(0., 1.) + 1.;
+            if self.noise.get(cave_sample_pos).abs() < self.cave_openness * depth_boost {
+                return if depth > biome.cave_liquid_depth { biome.cave_liquid } else { Particle::new(ParticleType::Air) };
+            }
+        }
+
+        if depth <= biome.surface_depth {
+            biome.surface
+        }
+        else if depth <= biome.surface_depth + biome.subsurface_depth {
+            biome.subsurface
+        }
+        else {
+            biome.fill
+        }
+    }
+}
+
+impl WorldGenerator for BiomeWorld {
+    fn get_particle(&self, world_pos: GridVec) -> Particle {
+        self.get_particle_in_column(world_pos, self.get_column(world_pos.x))
+    }
+
+    fn fill_chunk(&self, chunk_root: GridVec, particles: &mut [Particle]) {
+        let chunk_size = CHUNK_SIZE as i32;
+        let columns: Vec<BiomeColumn> = (0..chunk_size).map(|x| self.get_column(chunk_root.x + x)).collect();
+
+        for y in 0..chunk_size {
+            for x in 0..chunk_size {
+                let world_pos = chunk_root + GridVec::new(x, y);
+                particles[(y * chunk_size + x) as usize] = self.get_particle_in_column(world_pos, columns[x as usize]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::worldgen::*;
+
+    #[test]
+    fn empty_biome_table_uses_defaults() {
+        let world = BiomeWorld::new(1, Vec::new());
+        assert_eq!(world.biomes.len(), Biome::default_table().len());
+        world.get_particle(GridVec::new(0, 0));
+    }
+
+    #[test]
+    fn biomes_are_deterministic_per_seed() {
+        let world = BiomeWorld::new(42, Biome::default_table());
+        let again = BiomeWorld::new(42, Biome::default_table());
+        let other = BiomeWorld::new(43, Biome::default_table());
+
+        let mut differs = false;
+        for x in (-100_000..100_000).step_by(500) {
+            assert_eq!(world.biome_at(x).name, again.biome_at(x).name);
+            let pos = GridVec::new(x, -x / 100);
+            assert_eq!(world.get_particle(pos), again.get_particle(pos));
+            differs |= world.biome_at(x).name != other.biome_at(x).name;
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn biome_borders_mix_both_biomes() {
+        // Two flat biomes that only differ in their surface, deep enough that caves never reach
+        let biome = |name: &str, selector: f64, surface: ParticleType| {
+            let mut biome = Biome::new(name, selector, surface, 400., ParticleType::Stone, 0., ParticleType::Water)
+                .with_shape(0., 0.);
+            biome.cave_depth = 10_000.;
+            biome
+        };
+        let world = BiomeWorld::new(8, vec![biome("Sandy", -0.5, ParticleType::Sand), biome("Snowy", 0.5, ParticleType::Snow)])
+            .with_biome_scale(2000., 0.4);
+
+        let border = (-20_000..20_000).find(|x| {
+            let blend = world.get_column(*x).blend;
+            blend > 0.3 && blend < 0.7
+        }).expect("no border between the biomes");
+
+        let mut sand = 0;
+        let mut snow = 0;
+        for y in -300..0 {
+            match world.get_particle(GridVec::new(border, y)).particle_type {
+                ParticleType::Sand => sand += 1,
+                ParticleType::Snow => snow += 1,
+                other => panic!("unexpected {:?} at the border", other),
+            }
+        }
+        assert!(sand > 0 && snow > 0);
+
+        // Away from the border only the one biome's rules apply
+        let inside = (-20_000..20_000).find(|x| world.get_column(*x).blend == 0.).unwrap();
+        let surface = world.biome_at(inside).surface;
+        assert!((-300..0).all(|y| world.get_particle(GridVec::new(inside, y)) == surface));
+    }
+
+    #[test]
+    fn biome_fill_chunk_matches_get_particle() {
+        let world = BiomeWorld::new(5, Biome::default_table()).with_biome_scale(500., 0.2);
+        let size = CHUNK_SIZE as i32;
+        let mut particles = vec![Particle::default(); (size * size) as usize];
+
+        for root in [GridVec::new(0, 0), GridVec::new(-3 * size, -20 * size), GridVec::new(41 * size, 2 * size)] {
+            world.fill_chunk(root, &mut particles);
+            for (index, particle) in particles.iter().enumerate() {
+                let pos = root + GridVec::new(index as i32 % size, index as i32 / size);
+                assert_eq!(*particle, world.get_particle(pos));
+            }
+        }
+    }
+
+    #[test]
+    fn noise_field_stays_in_range() {
+        let field = NoiseField::stretched(3, 50., 20.);
+        for x in -200..200 {
+            let value = field.sample(GridVec::new(x * 7, x * -3));
+            assert!((-1. ..=1.).contains(&value));
+        }
+    }
+
+    #[test]
+    fn heightmap_stays_within_amplitude() {
+        let terrain = HeightmapTerrain::new(7, 400., 100., 50.)
+            .with_materials(Particle::new(ParticleType::Gravel), 10., Particle::new(ParticleType::Stone))
+            .with_sea(80, Particle::new(ParticleType::Lava));
+        // The two detail octaves add up to a little over the amplitude
+        let max_offset = 50. * (1. + 1. / 8. + 1. / 64.);
+
+        for x in -500..500 {
+            let height = terrain.height_at(x);
+            assert!((height - 100.).abs() <= max_offset);
+
+            let ground = height.ceil() as i32 - 1;
+            assert_eq!(terrain.get_particle(GridVec::new(x, ground)).particle_type, ParticleType::Gravel);
+            assert_eq!(terrain.get_particle(GridVec::new(x, ground - 20)).particle_type, ParticleType::Stone);
+            let above = terrain.get_particle(GridVec::new(x, ground + 1)).particle_type;
+            assert_eq!(above, if ground < 80 { ParticleType::Lava } else { ParticleType::Air });
+        }
+    }
+
+    #[test]
+    fn caves_only_carve_below_the_surface() {
+        let closed = Caves::new(5, 100., 0.);
+        let open = Caves::new(5, 100., 0.1);
+        let mut shallow = 0;
+        let mut deep = 0;
+        for x in 0..300 {
+            for y in 0..50 {
+                assert!(closed.sample(GridVec::new(x, y)) >= 0.);
+                if open.sample(GridVec::new(x, y * 10)) < 0. { shallow += 1; }
+                if open.sample(GridVec::new(x, y * 10 - 2500)) < 0. { deep += 1; }
+            }
+        }
+        assert!(shallow > 0);
+        assert!(deep > shallow);
+
+        // Masked the way assembled_world does it, nothing opens up within the surface layer
+        let terrain = HeightmapTerrain::new(5, 400., 0., 100.);
+        let surface = terrain.clone();
+        let underground = move |pos: GridVec| {
+            if pos.y as f64 > surface.height_at(pos.x) - 24. { 1. } else { open.sample(pos) }
+        };
+        let world = terrain.clone().masked(underground, 0.);
+        for x in 0..300 {
+            let top = terrain.height_at(x).ceil() as i32 - 1;
+            for y in top - 23..=top {
+                assert_eq!(world.get_particle(GridVec::new(x, y)), terrain.get_particle(GridVec::new(x, y)));
+            }
+        }
+    }
+
+    #[test]
+    fn ore_veins_only_replace_the_host() {
+        let veins = OreVeins::new(9, Particle::new(ParticleType::Gravel), 30., 0.1);
+        let ground = Fill(Particle::new(ParticleType::Sand))
+            .layer(Fill(Particle::new(ParticleType::Stone)).masked(|pos: GridVec| -pos.y as f64, 0.))
+            .replace_where(particle_set![ParticleType::Stone], veins);
+
+        let mut ore = 0;
+        for x in 0..100 {
+            for y in -100..100 {
+                let particle_type = ground.get_particle(GridVec::new(x, y)).particle_type;
+                if y > 0 {
+                    assert_eq!(particle_type, ParticleType::Sand);
+                }
+                else if particle_type == ParticleType::Gravel {
+                    ore += 1;
+                }
+                else {
+                    assert_eq!(particle_type, ParticleType::Stone);
+                }
+            }
+        }
+        assert!(ore > 0);
+    }
+
+    #[test]
+    fn liquid_pockets_stay_below_max_height() {
+        let pockets = LiquidPockets::new(11, Particle::new(ParticleType::Water), 20., 0.2, 50);
+        let mut liquid = 0;
+        for x in 0..200 {
+            for y in -100..150 {
+                if pockets.get_particle(GridVec::new(x, y)).particle_type == ParticleType::Water {
+                    assert!(y < 50);
+                    liquid += 1;
+                }
+            }
+        }
+        assert!(liquid > 0);
+    }
+}
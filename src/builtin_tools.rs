@@ -0,0 +1,317 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::*;
+use gridmath::{gridline::GridLine, GridVec};
+use rand::Rng;
+use sandworld::{particle_set, Particle, ParticleSet, ParticleType, POWDER_MATS, SOLID_MATS};
+
+use crate::tools::{AppToolExt, PreviewContext, Tool, ToolContext, ToolOptions};
+
+const BEAM_LENGTH: i32 = 512;
+// Cells a single fill can cover, so a click in open sky doesn't fill the world
+const MAX_FILL_CELLS: usize = 4096;
+// Speed the magnet pulls loose material in at, from the edge of its radius
+const MAGNET_SPEED: f32 = 160.;
+
+const LINE_PREVIEW_COLOR: Color = Color::rgba(0.9, 0.9, 0.9, 0.5);
+const BEAM_PREVIEW_COLOR: Color = Color::rgba(1., 0.3, 0.3, 0.6);
+const BLOCKED_PREVIEW_COLOR: Color = Color::rgba(0.9, 0.9, 0.9, 0.15);
+
+// Toolbar order follows registration order
+pub(crate) fn add_builtin_tools(app: &mut App) {
+    app.add_tool(BombTool)
+        .add_tool(BeamTool)
+        .add_tool(TemperatureTool { label: "MELT", temperature: 1800, radius: 10 })
+        .add_tool(BreakTool)
+        .add_tool(TemperatureTool { label: "CHILL", temperature: -100, radius: 20 })
+        .add_tool(CutTool)
+        .add_tool(SprayTool)
+        .add_tool(LineTool)
+        .add_tool(FillTool)
+        .add_tool(MagnetTool)
+        .add_tool(PlaceTool::new("Stone", ParticleType::Stone, 0, 20))
+        .add_tool(PlaceTool::new("Gravel", ParticleType::Gravel, 0, 10))
+        .add_tool(PlaceTool::new("Sand", ParticleType::Sand, 0, 10))
+        .add_tool(PlaceTool::new("Powder", ParticleType::Gunpowder, 0, 10))
+        .add_tool(PlaceTool::new("Ice", ParticleType::Ice, 0, 10))
+        .add_tool(PlaceTool::new("Snow", ParticleType::Snow, 0, 10))
+        .add_tool(PlaceTool::new("Water", ParticleType::Water, 0, 10))
+        .add_tool(PlaceTool::new("Steam", ParticleType::Steam, 0, 10))
+        .add_tool(PlaceTool::new("Lava", ParticleType::Lava, 0, 10))
+        .add_tool(PlaceTool::new("Emit", ParticleType::Source, 0, 1))
+        .add_tool(PlaceTool::new("LaserR", ParticleType::LaserEmitter, 1, 1))
+        .add_tool(PlaceTool::new("LaserL", ParticleType::LaserEmitter, 3, 1))
+        .add_tool(PlaceTool::new("LaserU", ParticleType::LaserEmitter, 0, 1))
+        .add_tool(PlaceTool::new("LaserD", ParticleType::LaserEmitter, 2, 1));
+}
+
+fn grid_to_world(pos: GridVec) -> Vec2 {
+    Vec2::new(pos.x as f32 + 0.5, pos.y as f32 + 0.5)
+}
+
+// Paints a material into empty space
+struct PlaceTool {
+    label: &'static str,
+    particle: Particle,
+    radius: i32,
+}
+
+impl PlaceTool {
+    fn new(label: &'static str, particle_type: ParticleType, data: u16, radius: i32) -> Self {
+        PlaceTool { label, particle: Particle::new_with_data(particle_type, data), radius }
+    }
+}
+
+impl Tool for PlaceTool {
+    fn label(&self) -> &str {
+        self.label
+    }
+
+    fn default_options(&self) -> ToolOptions {
+        ToolOptions::default().with_radius(self.radius)
+    }
+
+    fn material(&self) -> Option<Particle> {
+        Some(self.particle)
+    }
+
+    fn press(&mut self, ctx: &mut ToolContext) {
+        self.drag(ctx);
+    }
+
+    fn drag(&mut self, ctx: &mut ToolContext) {
+        ctx.world.place_circle(ctx.gridpos, ctx.options.radius, self.particle, false);
+    }
+}
+
+// Heats or cools everything under the brush, melting or freezing it over time
+struct TemperatureTool {
+    label: &'static str,
+    temperature: i32,
+    radius: i32,
+}
+
+impl Tool for TemperatureTool {
+    fn label(&self) -> &str {
+        self.label
+    }
+
+    fn default_options(&self) -> ToolOptions {
+        ToolOptions::default().with_radius(self.radius)
+    }
+
+    fn press(&mut self, ctx: &mut ToolContext) {
+        self.drag(ctx);
+    }
+
+    fn drag(&mut self, ctx: &mut ToolContext) {
+        ctx.world.temp_change_circle(ctx.gridpos, ctx.options.radius, 0.01, self.temperature);
+    }
+}
+
+struct BreakTool;
+
+impl Tool for BreakTool {
+    fn label(&self) -> &str {
+        "BREAK"
+    }
+
+    fn press(&mut self, ctx: &mut ToolContext) {
+        self.drag(ctx);
+    }
+
+    fn drag(&mut self, ctx: &mut ToolContext) {
+        ctx.world.break_circle(ctx.gridpos, ctx.options.radius, 0.1);
+    }
+}
+
+struct BombTool;
+
+impl Tool for BombTool {
+    fn label(&self) -> &str {
+        "BOMB"
+    }
+
+    fn press(&mut self, ctx: &mut ToolContext) {
+        crate::sandsim::spawn_bomb(ctx.commands, ctx.asset_server, ctx.cursor, ctx.time);
+    }
+
+    fn draw_preview(&self, _gizmos: &mut Gizmos, _ctx: &PreviewContext) {}
+}
+
+// Cuts a round piece out of solid ground and lets it fall as a rigid body
+struct CutTool;
+
+impl Tool for CutTool {
+    fn label(&self) -> &str {
+        "CUT"
+    }
+
+    fn default_options(&self) -> ToolOptions {
+        ToolOptions::default().with_radius(12)
+    }
+
+    fn press(&mut self, ctx: &mut ToolContext) {
+        ctx.world.extract_body_circle(ctx.gridpos, ctx.options.radius, SOLID_MATS);
+    }
+}
+
+// Heats whatever a beam from the click point towards the cursor hits first
+struct BeamTool;
+
+impl BeamTool {
+    fn get_beam(start: GridVec, cursor: Vec2) -> Option<GridLine> {
+        let direction = (cursor - grid_to_world(start)).normalize_or_zero();
+        if direction == Vec2::ZERO {
+            return None;
+        }
+
+        let end = start + GridVec::new((direction.x * BEAM_LENGTH as f32) as i32, (direction.y * BEAM_LENGTH as f32) as i32);
+        Some(GridLine::new(start, end))
+    }
+}
+
+impl Tool for BeamTool {
+    fn label(&self) -> &str {
+        "BEAM"
+    }
+
+    fn drag(&mut self, ctx: &mut ToolContext) {
+        let Some(beam) = ctx.click_start.and_then(|start| BeamTool::get_beam(start, ctx.cursor)) else {
+            return;
+        };
+
+        if let Some(hit) = ctx.world.cast_ray(&crate::chunk_colliders::COLLIDES, beam) {
+            ctx.world.temp_change_circle(hit.point, ctx.options.radius, 0.01, 1800);
+        }
+    }
+
+    fn draw_preview(&self, gizmos: &mut Gizmos, ctx: &PreviewContext) {
+        match ctx.click_start {
+            Some(start) => gizmos.line_2d(grid_to_world(start), ctx.cursor, BEAM_PREVIEW_COLOR),
+            None => {
+                gizmos.circle_2d(ctx.cursor, ctx.options.radius as f32, BEAM_PREVIEW_COLOR);
+            }
+        }
+    }
+}
+
+// Scatters the selected material loosely over the brush
+struct SprayTool;
+
+impl Tool for SprayTool {
+    fn label(&self) -> &str {
+        "SPRAY"
+    }
+
+    fn default_options(&self) -> ToolOptions {
+        ToolOptions::default().with_radius(16)
+    }
+
+    fn press(&mut self, ctx: &mut ToolContext) {
+        self.drag(ctx);
+    }
+
+    fn drag(&mut self, ctx: &mut ToolContext) {
+        let mut rng = rand::thread_rng();
+        let radius = ctx.options.radius;
+        for _ in 0..radius {
+            let offset = GridVec::new(rng.gen_range(-radius..=radius), rng.gen_range(-radius..=radius));
+            if offset.x * offset.x + offset.y * offset.y < radius * radius {
+                ctx.world.add_particle(ctx.gridpos + offset, ctx.material);
+            }
+        }
+    }
+}
+
+// Draws a straight line of the selected material from where the click started to where it ends
+struct LineTool;
+
+impl Tool for LineTool {
+    fn label(&self) -> &str {
+        "LINE"
+    }
+
+    fn default_options(&self) -> ToolOptions {
+        ToolOptions::default().with_radius(2)
+    }
+
+    fn release(&mut self, ctx: &mut ToolContext) {
+        let Some(start) = ctx.click_start else {
+            return;
+        };
+
+        for pos in GridLine::new(start, ctx.gridpos).along() {
+            ctx.world.place_circle(pos, ctx.options.radius, ctx.material, false);
+        }
+    }
+
+    fn draw_preview(&self, gizmos: &mut Gizmos, ctx: &PreviewContext) {
+        match ctx.click_start {
+            Some(start) => gizmos.line_2d(grid_to_world(start), ctx.cursor, LINE_PREVIEW_COLOR),
+            None => {
+                gizmos.circle_2d(ctx.cursor, ctx.options.radius as f32, LINE_PREVIEW_COLOR);
+            }
+        }
+    }
+}
+
+// Floods the empty space connected to the click with the selected material
+struct FillTool;
+
+impl Tool for FillTool {
+    fn label(&self) -> &str {
+        "FILL"
+    }
+
+    fn press(&mut self, ctx: &mut ToolContext) {
+        let empty = particle_set![ParticleType::Air];
+        if !empty.test(ctx.world.get_particle(ctx.gridpos).particle_type) {
+            return;
+        }
+
+        let mut open = VecDeque::from([ctx.gridpos]);
+        let mut seen = HashSet::from([ctx.gridpos]);
+        let mut filled = 0;
+
+        while let Some(pos) = open.pop_front() {
+            if filled >= MAX_FILL_CELLS {
+                break;
+            }
+
+            ctx.world.add_particle(pos, ctx.material);
+            filled += 1;
+
+            for offset in [GridVec::new(1, 0), GridVec::new(-1, 0), GridVec::new(0, 1), GridVec::new(0, -1)] {
+                let next = pos + offset;
+                if ctx.world.contains(next) && seen.insert(next) && empty.test(ctx.world.get_particle(next).particle_type) {
+                    open.push_back(next);
+                }
+            }
+        }
+    }
+
+    // Outlines the cell under the cursor, dimmed when it isn't empty and pressing would do nothing
+    fn draw_preview(&self, gizmos: &mut Gizmos, ctx: &PreviewContext) {
+        let empty = ctx.world.get_particle(ctx.gridpos).particle_type == ParticleType::Air;
+        let color = if empty { LINE_PREVIEW_COLOR } else { BLOCKED_PREVIEW_COLOR };
+        gizmos.rect_2d(grid_to_world(ctx.gridpos), 0., Vec2::splat(3.), color);
+    }
+}
+
+// Pulls loose material under the brush in towards the cursor
+struct MagnetTool;
+
+impl Tool for MagnetTool {
+    fn label(&self) -> &str {
+        "MAGNET"
+    }
+
+    fn default_options(&self) -> ToolOptions {
+        ToolOptions::default().with_radius(24)
+    }
+
+    fn drag(&mut self, ctx: &mut ToolContext) {
+        ctx.world.extract_ejecta_circle(ctx.gridpos, ctx.options.radius, POWDER_MATS, -MAGNET_SPEED);
+    }
+}
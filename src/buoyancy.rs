@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+use gridmath::{GridBounds, GridVec};
+use sandworld::{particle_set, ParticleSet, ParticleType, LIQUID_MATS};
+
+use crate::{player::Player, sandsim::Sandworld};
+
+pub struct BuoyancyPlugin;
+
+// Bodies moving faster than this push liquid out of their way
+const DISPLACE_SPEED: f32 = 30.;
+// How far a displaced particle can be pushed past the body's edge
+const MAX_DISPLACE_DISTANCE: i32 = 12;
+
+/*
+    How a liquid acts on bodies floating in it
+    Density is relative to the default collider density of 1, a body floats in a liquid denser than itself
+    Every liquid is denser than a default body, so those float in all of them and only heavier bodies sink
+    Drag is the fraction of velocity lost per second when fully submerged
+*/
+struct LiquidProperties {
+    density: f32,
+    drag: f32,
+}
+
+fn get_liquid_properties(particle_type: ParticleType) -> LiquidProperties {
+    match particle_type {
+        ParticleType::Water => LiquidProperties { density: 1.2, drag: 2.0 },
+        ParticleType::Lava => LiquidProperties { density: 2.0, drag: 6.0 },
+        ParticleType::MoltenGlass => LiquidProperties { density: 1.6, drag: 8.0 },
+        // Liquids without their own entry act like a thick water, floating what water floats
+        _ => LiquidProperties { density: 1.2, drag: 4.0 },
+    }
+}
+
+impl Plugin for BuoyancyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (apply_buoyancy, displace_liquids).chain().in_set(crate::UpdateStages::WorldUpdate).after(crate::sandsim::sand_update));
+    }
+}
+
+fn get_sample_bounds(aabb: &ColliderAabb) -> GridBounds {
+    GridBounds::new_from_extents(
+        GridVec::new(aabb.min.x.floor() as i32, aabb.min.y.floor() as i32),
+        GridVec::new(aabb.max.x.floor() as i32, aabb.max.y.floor() as i32),
+    )
+}
+
+type BuoyantBody<'a> = (&'a RigidBody, &'a Collider, &'a ColliderAabb, &'a Mass, &'a mut LinearVelocity, &'a mut AngularVelocity);
+
+/*
+    Physics bodies only collide with the solid parts of the world, so liquids are applied here instead
+    Buoyancy pushes against gravity by the weight of liquid displaced, and drag slows both movement and spin
+    The player handles its own swimming
+*/
+fn apply_buoyancy(
+    world: Res<Sandworld>,
+    gravity: Res<Gravity>,
+    mut body_query: Query<BuoyantBody, Without<Player>>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+
+    for (rigidbody, collider, aabb, mass, mut velocity, mut angular_velocity) in body_query.iter_mut() {
+        if *rigidbody != RigidBody::Dynamic {
+            continue;
+        }
+
+        let bounds = get_sample_bounds(aabb);
+        if world.world.count_matches_in_bounds(bounds, LIQUID_MATS) == 0 {
+            continue;
+        }
+
+        let area = bounds.area() as f32;
+        // The collider's mass at a density of 1 is its area, the bounds would make round bodies look lighter
+        let collider_area = collider.mass_properties(1.).mass.0;
+        let body_density = (mass.0 / collider_area.max(1.)).max(0.01);
+
+        let mut drag = 0.;
+        for liquid in ParticleType::ALL.into_iter().filter(|particle_type| LIQUID_MATS.test(*particle_type)) {
+            let submerged = world.world.count_matches_in_bounds(bounds, ParticleSet::with(liquid)) as f32 / area;
+            if submerged > 0. {
+                let properties = get_liquid_properties(liquid);
+                velocity.0 -= gravity.0 * (properties.density / body_density) * submerged * dt;
+                drag += properties.drag * submerged;
+            }
+        }
+
+        let keep = (-drag * dt).exp();
+        velocity.0 *= keep;
+        angular_velocity.0 *= keep;
+    }
+}
+
+// Make room in liquids for bodies moving through them, so they splash rather than pass through unseen
+fn displace_liquids(
+    mut world: ResMut<Sandworld>,
+    body_query: Query<(&RigidBody, &ColliderAabb, &LinearVelocity)>,
+) {
+    for (rigidbody, aabb, velocity) in body_query.iter() {
+        if *rigidbody != RigidBody::Dynamic || velocity.length() < DISPLACE_SPEED {
+            continue;
+        }
+
+        let bounds = get_sample_bounds(aabb);
+        // Too small to move anything out of the way
+        let radius = (bounds.width().min(bounds.height()) / 2) as i32;
+        if radius < 1 || world.world.count_matches_in_bounds(bounds, LIQUID_MATS) == 0 {
+            continue;
+        }
+
+        world.world.displace_circle(bounds.center(), radius, LIQUID_MATS.union(particle_set![ParticleType::Steam]), MAX_DISPLACE_DISTANCE);
+    }
+}
@@ -1,8 +1,17 @@
 use bevy::prelude::*;
 use gridmath::{GridBounds, GridVec};
 
+use crate::input_map::{Action, ActionState};
+
 pub struct CameraPlugin;
 
+// Fraction of the distance to a CameraTarget closed each second
+const FOLLOW_RATE: f32 = 6.;
+
+// The camera follows an entity with this instead of panning from the keyboard
+#[derive(Component)]
+pub struct CameraTarget;
+
 #[derive(Component)]
 struct IdleMover {
     x_move: f32,
@@ -39,8 +48,9 @@ pub fn cam_bounds(camera: &Camera, camera_transform: &GlobalTransform) -> GridBo
 
 fn camera_movement(
     mut query: Query<(&Camera, &mut OrthographicProjection, &mut Transform, &mut IdleMover)>,
+    target_query: Query<&Transform, (With<CameraTarget>, Without<Camera>)>,
     time: Res<Time>,
-    keys: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
 ) {
     let (_camera, mut ortho, mut camera_transform, mut idle) = query.single_mut();
 
@@ -51,40 +61,34 @@ fn camera_movement(
     let max_zoom = 0.7;
     let min_zoom = 0.1;
 
-    if keys.pressed(KeyCode::ShiftLeft) {
-        if keys.just_pressed(KeyCode::KeyD) {
+    if let Ok(target) = target_query.get_single() {
+        let follow = (FOLLOW_RATE * time.delta_seconds()).min(1.);
+        let target_pos = target.translation.truncate().extend(camera_transform.translation.z);
+        camera_transform.translation = camera_transform.translation.lerp(target_pos, follow);
+        idle.x_move = 0.;
+        idle.y_move = 0.;
+    }
+    else if actions.pressed(Action::CameraDrift) {
+        if actions.just_pressed(Action::CameraRight) {
             idle.x_move -= 10.;
         }
-        if keys.just_pressed(KeyCode::KeyA) {
+        if actions.just_pressed(Action::CameraLeft) {
             idle.x_move += 10.;
         }
-        if keys.just_pressed(KeyCode::KeyS) {
+        if actions.just_pressed(Action::CameraDown) {
             idle.y_move -= 10.;
         }
-        if keys.just_pressed(KeyCode::KeyW) {
+        if actions.just_pressed(Action::CameraUp) {
             idle.y_move += 10.;
         }
     }
     else {
-        if keys.pressed(KeyCode::KeyD) || keys.pressed(KeyCode::ArrowRight) {
-            camera_transform.translation =
-                (camera_transform.right() * move_speed * time.delta_seconds())
-                    + camera_transform.translation;
-        }
-        if keys.pressed(KeyCode::KeyA) || keys.pressed(KeyCode::ArrowLeft) {
-            camera_transform.translation =
-                (camera_transform.left() * move_speed * time.delta_seconds())
-                    + camera_transform.translation;
-        }
-        if keys.pressed(KeyCode::KeyW) || keys.pressed(KeyCode::ArrowUp) {
-            camera_transform.translation = (camera_transform.up() * move_speed * time.delta_seconds())
+        // Analog sticks pan slower the less they're pushed
+        let x = actions.value(Action::CameraRight) - actions.value(Action::CameraLeft);
+        let y = actions.value(Action::CameraUp) - actions.value(Action::CameraDown);
+        camera_transform.translation =
+            ((*camera_transform.right() * x + *camera_transform.up() * y) * move_speed * time.delta_seconds())
                 + camera_transform.translation;
-        }
-        if keys.pressed(KeyCode::KeyS) || keys.pressed(KeyCode::ArrowDown) {
-            camera_transform.translation =
-                (camera_transform.down() * move_speed * time.delta_seconds())
-                    + camera_transform.translation;
-        }
     }
     
 
@@ -99,12 +103,8 @@ fn camera_movement(
                 + camera_transform.translation;
     }
 
-    if keys.any_pressed([KeyCode::PageUp, KeyCode::BracketRight]) {
-        log_scale -= zoom_speed * time.delta_seconds();
-    }
-    if keys.any_pressed([KeyCode::PageDown, KeyCode::BracketRight]) {
-        log_scale += zoom_speed * time.delta_seconds();
-    }
+    log_scale -= actions.value(Action::ZoomIn) * zoom_speed * time.delta_seconds();
+    log_scale += actions.value(Action::ZoomOut) * zoom_speed * time.delta_seconds();
 
     ortho.scale = log_scale.exp().clamp(min_zoom, max_zoom);
 }
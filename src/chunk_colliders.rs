@@ -8,7 +8,7 @@ use bevy_xpbd_2d::{parry::shape::SharedShape, prelude::*};
 use crate::sandsim::Sandworld;
 use crate::chunk_display::ChunkDisplay;
 
-pub const COLLIDES: ParticleSet = particle_set!(ParticleType::Stone, ParticleType::Sand, ParticleType::Gravel, ParticleType::Ice, ParticleType::Glass);
+pub const COLLIDES: ParticleSet = particle_set!(ParticleType::Stone, ParticleType::Sand, ParticleType::Gravel, ParticleType::Ice, ParticleType::Glass, ParticleType::Snow, ParticleType::Gunpowder);
 const SIMPLIFICATION_EPSILLON: f32 = 1.0;
 const MAX_COLLIDER_UPDATES_PER_FRAME: usize = 64;
 
@@ -62,6 +62,7 @@ fn update_chunk_colliders(
     mut collider_gen: ResMut<AsyncColliderManager>,
     time: Res<Time>,
 ) {
+    let _span = info_span!("collider_queue").entered();
     let updated_chunks = world.world.get_updated_chunks();
 
     chunk_display_query.iter_mut().for_each(|(chunk_display, mut col_man)| {
@@ -106,6 +107,7 @@ fn apply_generated_colliders(
     }
 
     if ready_indices.len() > 0 {
+        let _span = info_span!("collider_apply", colliders = ready_indices.len()).entered();
         chunks_query.par_iter_mut().for_each(|(chunk_display, mut colman, mut collider, mut layers)| {
             for ready_collider in ready_cols.iter() {
                 if let Some(rep_pos) = chunk_display.chunk_pos {
@@ -153,8 +155,10 @@ impl ChunkColliderGenerator {
         let ready = self.ready.clone();
         let result = self.result.clone();
         let chunk_data = self.chunk_data.clone();
+        let position = self.position;
         
         rayon::spawn(move || {
+            let _span = info_span!("collider_generate", position = %position).entered();
             let mut polyline = marching_squares_polylines_from_chunkdata(&chunk_data);
             polyline.simplify(SIMPLIFICATION_EPSILLON);
             let shape = polyline.to_shared_shape_polyline();
@@ -1,9 +1,9 @@
 
-use bevy::{prelude::*, render::{render_asset::RenderAssetUsages, render_resource::{Extent3d, TextureFormat}}};
+use bevy::{prelude::*, render::{render_asset::RenderAssetUsages, render_resource::{AsBindGroup, Extent3d, ShaderRef, TextureFormat}, texture::ImageSampler}, sprite::{Material2d, Material2dPlugin, MaterialMesh2dBundle, Mesh2dHandle}};
 use bevy_xpbd_2d::{components::{CollisionLayers, RigidBody}, plugins::collision::Collider};
 use gridmath::{GridBounds, GridVec};
 use sandworld::CHUNK_SIZE;
-use crate::{camera::cam_bounds, sandsim::*};
+use crate::{camera::cam_bounds, overlays::DebugOverlay, sandsim::*};
 
 #[derive(Resource)]
 pub struct DrawOptions {
@@ -12,6 +12,60 @@ pub struct DrawOptions {
     pub world_stats: bool,
     pub force_redraw_all: bool,
     pub show_colliders: bool,
+    pub color_variation: f32,
+    pub edge_shading: f32,
+    pub heat_glow: f32,
+    pub lighting: bool,
+    pub overlay: DebugOverlay,
+}
+
+const CHUNK_SHADER_PATH: &str = "shaders/chunk_palette.wgsl";
+
+// Lowest light level drawn, so unlit caves are dim rather than pitch black
+pub const AMBIENT_LIGHT: u8 = 0x18;
+
+// Colors a chunk's material index texture from a shared palette, see Chunk::render_to_index_array
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+struct ChunkMaterial {
+    #[texture(0)]
+    cells: Handle<Image>,
+    #[texture(1)]
+    palette: Handle<Image>,
+    #[texture(2)]
+    #[sampler(3)]
+    light: Handle<Image>,
+    // Light strength, color variation, edge shading and heat glow strength, see shading_for
+    #[uniform(4)]
+    shading: Vec4,
+    // World position of the chunk's bottom left cell, to seed per cell color variation
+    #[uniform(5)]
+    chunk_root: IVec2,
+}
+
+impl Material2d for ChunkMaterial {
+    fn fragment_shader() -> ShaderRef {
+        CHUNK_SHADER_PATH.into()
+    }
+}
+
+// The draw options the shader applies per cell, so changing them doesn't need any chunk redrawn
+// A light strength of 0 ignores the light texture, 1 applies it fully
+fn shading_for(draw_options: &DrawOptions) -> Vec4 {
+    Vec4::new(
+        if draw_options.lighting { 1. } else { 0. },
+        draw_options.color_variation,
+        draw_options.edge_shading,
+        draw_options.heat_glow,
+    )
+}
+
+// Shared by every chunk display
+#[derive(Resource)]
+struct ChunkDisplayAssets {
+    mesh: Mesh2dHandle,
+    palette: Handle<Image>,
+    // Shading given to every chunk material
+    shading: Vec4,
 }
 
 #[derive(Component, Default)]
@@ -23,7 +77,7 @@ pub struct ChunkDisplay {
 #[derive(Bundle, Default)]
 struct ChunkDisplayBundle {
     chunk_display: ChunkDisplay,
-    sprite: SpriteBundle,
+    mesh: MaterialMesh2dBundle<ChunkMaterial>,
     collider: Collider,
     layers: CollisionLayers,
     rigidbody: RigidBody,
@@ -40,49 +94,101 @@ pub struct SandworldDisplayPlugin;
 impl Plugin for SandworldDisplayPlugin {
     fn build(&self, app: &mut App) {
         app
+        .add_plugins(Material2dPlugin::<ChunkMaterial>::default())
         .insert_resource(DrawOptions {
             update_bounds: false,
             chunk_bounds: false,
             world_stats: false,
             force_redraw_all: false,
             show_colliders: false,
+            color_variation: 1.,
+            edge_shading: 0.25,
+            heat_glow: 0.6,
+            lighting: true,
+            overlay: DebugOverlay::None,
         })
         .insert_resource(ChunkVisibilityCache {
             visible_chunk_bounds: GridBounds::new(GridVec::new(0, 0), GridVec::new(0, 0)),
         })
-        .add_systems(Update,(assign_chunk_displays, update_chunk_textures).in_set(crate::UpdateStages::WorldDraw))
+        .add_systems(Startup, setup_chunk_display_assets)
+        .add_systems(Update,(update_shading, assign_chunk_displays, update_chunk_textures).chain().in_set(crate::UpdateStages::WorldDraw))
         ;
     }
 }
 
+fn setup_chunk_display_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    draw_options: Res<DrawOptions>,
+) {
+    let chunk_size = CHUNK_SIZE as f32;
+    commands.insert_resource(ChunkDisplayAssets {
+        mesh: Mesh2dHandle(meshes.add(Rectangle::new(chunk_size, chunk_size))),
+        palette: images.add(create_palette_image()),
+        shading: shading_for(&draw_options),
+    });
+}
+
+// Shading changes only touch the chunk materials' uniforms, chunk textures stay as they are
+fn update_shading(
+    mut display_assets: ResMut<ChunkDisplayAssets>,
+    draw_options: Res<DrawOptions>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+) {
+    let shading = shading_for(&draw_options);
+    if display_assets.shading == shading {
+        return;
+    }
+
+    display_assets.shading = shading;
+    for (_, material) in materials.iter_mut() {
+        material.shading = shading;
+    }
+}
+
 fn update_chunk_textures(
     world: Res<Sandworld>,
-    mut chunk_display_query: Query<(&ChunkDisplay, &mut Handle<Image>)>,
+    chunk_display_query: Query<(&ChunkDisplay, &Handle<ChunkMaterial>)>,
     draw_options: Res<DrawOptions>,
     mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
     mut world_stats: ResMut<WorldStats>,
 ) {
+    let _span = info_span!("chunk_texture_upload").entered();
     let update_start = std::time::Instant::now();
 
     let updated_chunks = world.world.get_updated_chunks();
+    let relit_chunks = world.world.get_relit_chunks();
     let mut updated_textures_count = 0;
 
-    chunk_display_query.iter_mut().for_each(|(chunk_display, texture)| {
+    chunk_display_query.iter().for_each(|(chunk_display, material)| {
         // Is this display entity currently representing a chunk
         if let Some(chunk_pos) = chunk_display.chunk_pos {
+            let redraw_all = draw_options.force_redraw_all || chunk_display.redraw;
+
             // If the chunk this entity is representing needs to show an update
-            if draw_options.force_redraw_all || chunk_display.redraw || updated_chunks.contains(&chunk_pos) {
+            if redraw_all || updated_chunks.contains(&chunk_pos) {
                 // Get the chunk from the world, may fail if the world doesn't have the chunk yet
                 if let Some(world_chunk) = world.world.get_chunk(&chunk_pos) {
-                    let cur_tuxture = images.get_mut(texture.clone()).unwrap();
+                    // Fetching the material mutably flags it as changed, so its bind group is rebuilt with the new texture
+                    let cur_material = materials.get_mut(material).unwrap();
+                    cur_material.chunk_root = IVec2::new(chunk_pos.x, chunk_pos.y) * CHUNK_SIZE as i32;
+                    let cur_tuxture = images.get_mut(&cur_material.cells).unwrap();
 
-                    // TODO: do shader things to allow this to directly memcpy the chunks material data 
-                    // so color nonsense happens in shader land and only need to send 8 bits per particle
                     cur_tuxture.data = render_chunk_data(world_chunk, &draw_options);
 
                     updated_textures_count += 1;
                 }
             }
+
+            if draw_options.lighting && (redraw_all || relit_chunks.contains(&chunk_pos)) {
+                if let Some(world_chunk) = world.world.get_chunk(&chunk_pos) {
+                    let cur_material = materials.get_mut(material).unwrap();
+                    let light_texture = images.get_mut(&cur_material.light).unwrap();
+                    light_texture.data = world_chunk.render_light_array(AMBIENT_LIGHT);
+                }
+            }
         }
     });
 
@@ -102,6 +208,8 @@ fn assign_chunk_displays(
     cam_query: Query<(&Camera, &GlobalTransform), Or<(Changed<OrthographicProjection>, Changed<Camera>, Changed<GlobalTransform>)>>,
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    display_assets: Res<ChunkDisplayAssets>,
     mut visibility_cache: ResMut<ChunkVisibilityCache>,
 ) {
     let (camera, cam_transform) = cam_query.single();
@@ -177,10 +285,17 @@ fn assign_chunk_displays(
                 *layers = CollisionLayers::new(crate::chunk_colliders::ColliderLayer::Terrain, crate::chunk_colliders::MOBILE_COLLISION_LAYERS);
             }
             else {
-                let image = create_chunk_image();
+                let material = ChunkMaterial {
+                    cells: images.add(create_chunk_image()),
+                    palette: display_assets.palette.clone(),
+                    light: images.add(create_light_image()),
+                    shading: display_assets.shading,
+                    chunk_root: IVec2::new(chunk_pos.x, chunk_pos.y) * CHUNK_SIZE as i32,
+                };
                 commands.spawn(ChunkDisplayBundle {
-                    sprite: SpriteBundle { 
-                        texture: images.add(image),
+                    mesh: MaterialMesh2dBundle { 
+                        mesh: display_assets.mesh.clone(),
+                        material: materials.add(material),
                         transform: Transform::from_translation(position),
                         ..Default::default() 
                     },
@@ -199,7 +314,12 @@ fn assign_chunk_displays(
 }
 
 fn render_chunk_data(chunk: &sandworld::Chunk, draw_options: &DrawOptions) -> Vec<u8> {
-    chunk.render_to_color_array(draw_options.update_bounds, draw_options.chunk_bounds)
+    chunk.render_to_index_array(&sandworld::ChunkRenderOptions {
+        draw_dirty: draw_options.update_bounds,
+        draw_borders: draw_options.chunk_bounds,
+        // The rest of the shading is done by the chunk shader
+        ..Default::default()
+    })
 }
 
 fn create_chunk_image() -> Image {
@@ -211,7 +331,39 @@ fn create_chunk_image() -> Image {
             ..default()
         },
         bevy::render::render_resource::TextureDimension::D2,
-        vec![0; CHUNK_SIZE as usize * CHUNK_SIZE as usize * 4],
+        vec![0; CHUNK_SIZE as usize * CHUNK_SIZE as usize * 2],
+        TextureFormat::Rg8Unorm,
+        RenderAssetUsages::default()
+    )
+}
+
+// Light is filtered linearly so it blends smoothly between particles
+fn create_light_image() -> Image {
+    let side_size = sandworld::CHUNK_SIZE as u32;
+    let mut image = Image::new(
+        Extent3d {
+            width: side_size,
+            height: side_size,
+            ..default()
+        },
+        bevy::render::render_resource::TextureDimension::D2,
+        vec![AMBIENT_LIGHT; CHUNK_SIZE as usize * CHUNK_SIZE as usize * 4],
+        TextureFormat::Rgba8Unorm,
+        RenderAssetUsages::default()
+    );
+    image.sampler = ImageSampler::linear();
+    image
+}
+
+fn create_palette_image() -> Image {
+    Image::new(
+        Extent3d {
+            width: sandworld::PALETTE_WIDTH as u32,
+            height: sandworld::PALETTE_ROWS as u32,
+            ..default()
+        },
+        bevy::render::render_resource::TextureDimension::D2,
+        sandworld::build_palette(),
         TextureFormat::Rgba8Unorm,
         RenderAssetUsages::default()
     )
@@ -0,0 +1,260 @@
+use bevy::prelude::*;
+
+use crate::{
+    input_map::{Action, ActionState, InputBinding, InputMap, InputMapError, USER_INPUT_MAP_PATH},
+    ui::{spawn_button, text_style, PointerCaptureState, HOVERED_BUTTON, NORMAL_BUTTON, PANEL_COLOR, PRESSED_BUTTON},
+};
+
+pub struct ControlsScreenPlugin;
+
+const LISTENING_BUTTON: Color = Color::rgb(0.75, 0.55, 0.2);
+// How far a stick has to be pushed to be picked up as a new binding
+const AXIS_CAPTURE_THRESHOLD: f32 = 0.6;
+
+/*
+    Screen for remapping controls, opened with F12
+    Clicking an action waits for the next key, mouse or gamepad input and binds it, replacing the old
+    binding for that kind of device. Escape cancels, and every change is saved to the user input map over the shipped one
+*/
+#[derive(Resource, Default)]
+struct ControlsScreen {
+    open: bool,
+    listening: Option<Action>,
+}
+
+#[derive(Component)]
+struct ControlsRoot;
+
+#[derive(Component, Clone, Copy, PartialEq)]
+enum ControlsButton {
+    Rebind(Action),
+    Reset,
+    Close,
+}
+
+#[derive(Component)]
+struct BindingLabel(Action);
+
+impl Plugin for ControlsScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ControlsScreen>()
+            .add_systems(Startup, setup_controls_screen)
+            .add_systems(
+                Update,
+                (toggle_controls_screen, listen_for_binding, controls_buttons, refresh_controls_screen)
+                    .chain()
+                    .in_set(crate::UpdateStages::UI)
+                    .after(crate::ui::button_system)
+                    .before(crate::UpdateStages::Input),
+            );
+    }
+}
+
+fn setup_controls_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((NodeBundle {
+        style: Style {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            left: Val::Px(10.),
+            top: Val::Px(10.),
+            padding: UiRect::all(Val::Px(12.)),
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        background_color: PANEL_COLOR.into(),
+        ..default()
+    }, ControlsRoot, Interaction::default())).with_children(|parent| {
+        parent.spawn(TextBundle::from_section("Controls", text_style(&asset_server, 30.)));
+
+        // Two columns so every action fits on screen at once
+        parent.spawn(NodeBundle::default()).with_children(|parent| {
+            for column in Action::ALL.chunks(Action::ALL.len().div_ceil(2)) {
+                parent.spawn(NodeBundle {
+                    style: Style { flex_direction: FlexDirection::Column, ..default() },
+                    ..default()
+                }).with_children(|parent| {
+                    for action in column {
+                        parent.spawn(ButtonBundle {
+                            style: Style {
+                                width: Val::Px(480.),
+                                padding: UiRect::axes(Val::Px(8.), Val::Px(2.)),
+                                margin: UiRect::all(Val::Px(1.)),
+                                ..default()
+                            },
+                            background_color: NORMAL_BUTTON.into(),
+                            ..default()
+                        }).insert(ControlsButton::Rebind(*action)).with_children(|parent| {
+                            parent.spawn((TextBundle::from_section("", text_style(&asset_server, 16.)), BindingLabel(*action)));
+                        });
+                    }
+                });
+            }
+        });
+
+        parent.spawn(NodeBundle::default()).with_children(|parent| {
+            spawn_button(parent, &asset_server, "Reset", ControlsButton::Reset);
+            spawn_button(parent, &asset_server, "Close", ControlsButton::Close);
+        });
+    });
+}
+
+fn toggle_controls_screen(
+    mut screen: ResMut<ControlsScreen>,
+    actions: Res<ActionState>,
+) {
+    if actions.just_pressed(Action::Controls) {
+        screen.open = !screen.open;
+        screen.listening = None;
+    }
+}
+
+// Changes go to the user's file, the shipped defaults are left alone
+fn save_input_map(map: &InputMap) {
+    if let Err(err) = map.save(USER_INPUT_MAP_PATH) {
+        println!("{}", err);
+    }
+}
+
+fn clear_input_overrides() {
+    match std::fs::remove_file(USER_INPUT_MAP_PATH) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => println!("{}", InputMapError::Write(err)),
+        _ => (),
+    }
+}
+
+// The first input pressed this frame, checked in the same order a player is likely to reach for them
+fn get_pressed_binding(
+    keys: &ButtonInput<KeyCode>,
+    mouse: &ButtonInput<MouseButton>,
+    gamepads: &Gamepads,
+    pad_buttons: &ButtonInput<GamepadButton>,
+    pad_axes: &Axis<GamepadAxis>,
+) -> Option<InputBinding> {
+    let key = keys.get_just_pressed()
+        .map(|key| InputBinding::Key(*key))
+        .find(|binding| InputBinding::from_name(&binding.name()).is_some());
+    let button = mouse.get_just_pressed().map(|button| InputBinding::Mouse(*button)).next();
+    let pad = pad_buttons.get_just_pressed().map(|button| InputBinding::Pad(button.button_type)).next();
+    let axis = || {
+        let axis_types = [
+            GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY,
+            GamepadAxisType::RightStickX, GamepadAxisType::RightStickY,
+        ];
+        gamepads.iter().flat_map(|gamepad| axis_types.map(|axis| (gamepad, axis))).find_map(|(gamepad, axis)| {
+            let value = pad_axes.get(GamepadAxis::new(gamepad, axis))?;
+            (value.abs() > AXIS_CAPTURE_THRESHOLD).then_some(InputBinding::Axis(axis, value > 0.))
+        })
+    };
+
+    key.or(button).or(pad).or_else(axis)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn listen_for_binding(
+    mut screen: ResMut<ControlsScreen>,
+    mut map: ResMut<InputMap>,
+    mut actions: ResMut<ActionState>,
+    mut capture_state: ResMut<PointerCaptureState>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    pad_buttons: Res<ButtonInput<GamepadButton>>,
+    pad_axes: Res<Axis<GamepadAxis>>,
+) {
+    let Some(action) = screen.listening else {
+        return;
+    };
+
+    // Whatever is pressed while listening is for the screen, not the game
+    actions.suppress_held();
+    capture_state.click_consumed = true;
+
+    if keys.just_pressed(KeyCode::Escape) {
+        screen.listening = None;
+        return;
+    }
+
+    if let Some(binding) = get_pressed_binding(&keys, &mouse, &gamepads, &pad_buttons, &pad_axes) {
+        map.rebind(action, binding);
+        save_input_map(&map);
+        screen.listening = None;
+    }
+}
+
+// Any part of the panel that can be clicked
+type PanelFilter = Or<(With<ControlsRoot>, With<ControlsButton>)>;
+
+fn controls_buttons(
+    mut screen: ResMut<ControlsScreen>,
+    mut map: ResMut<InputMap>,
+    mut capture_state: ResMut<PointerCaptureState>,
+    mut button_query: Query<(&Interaction, &mut BackgroundColor, &ControlsButton)>,
+    panel_query: Query<&Interaction, PanelFilter>,
+) {
+    if !screen.open {
+        return;
+    }
+
+    // Clicks on the panel shouldn't also paint the world underneath
+    if panel_query.iter().any(|interaction| *interaction == Interaction::Pressed) {
+        capture_state.click_consumed = true;
+    }
+
+    for (interaction, mut color, button) in button_query.iter_mut() {
+        if screen.listening.is_some_and(|action| *button == ControlsButton::Rebind(action)) {
+            *color = LISTENING_BUTTON.into();
+            continue;
+        }
+
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                // Holding the mouse down over a row shouldn't start listening again every frame
+                if screen.listening.is_some() {
+                    continue;
+                }
+                match button {
+                    ControlsButton::Rebind(action) => {
+                        screen.listening = Some(*action);
+                    }
+                    ControlsButton::Reset => {
+                        map.reset();
+                        clear_input_overrides();
+                    }
+                    ControlsButton::Close => {
+                        screen.open = false;
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn refresh_controls_screen(
+    screen: Res<ControlsScreen>,
+    map: Res<InputMap>,
+    mut root_query: Query<&mut Style, With<ControlsRoot>>,
+    mut label_query: Query<(&mut Text, &BindingLabel)>,
+) {
+    if !screen.is_changed() && !map.is_changed() {
+        return;
+    }
+
+    root_query.single_mut().display = if screen.open { Display::Flex } else { Display::None };
+
+    for (mut text, label) in label_query.iter_mut() {
+        text.sections[0].value = if screen.listening == Some(label.0) {
+            format!("{}: press a key or button (Esc cancels)", label.0.label())
+        }
+        else {
+            let names: Vec<String> = map.get(label.0).iter().map(InputBinding::name).collect();
+            format!("{}: {}", label.0.label(), names.join(", "))
+        };
+    }
+}
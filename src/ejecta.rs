@@ -0,0 +1,70 @@
+use bevy::prelude::*;
+use sandworld::{get_color_for_type, get_heat_glow, Ejecta, HEAT_GLOW_COLOR};
+
+use crate::{chunk_display::DrawOptions, sandsim::Sandworld};
+
+pub struct EjectaPlugin;
+
+// One sprite per live ejecta, reused from frame to frame since ejecta come and go constantly
+#[derive(Resource, Default)]
+struct EjectaSprites {
+    entities: Vec<Entity>,
+}
+
+impl Plugin for EjectaPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EjectaSprites>()
+            .add_systems(Update, update_ejecta.in_set(crate::UpdateStages::WorldUpdate).after(crate::sandsim::sand_update))
+            .add_systems(Update, sync_ejecta_sprites.in_set(crate::UpdateStages::WorldDraw));
+    }
+}
+
+fn update_ejecta(
+    mut world: ResMut<Sandworld>,
+    time: Res<Time>,
+) {
+    world.world.update_ejecta(time.delta_seconds());
+}
+
+// Hot ejecta glow the same way they would in the grid, from the temperature they were thrown with
+fn get_ejecta_color(ejecta: &Ejecta, heat_glow: f32) -> Color {
+    let color = get_color_for_type(ejecta.particle().particle_type);
+    let glow = get_heat_glow(ejecta.temperature()) * heat_glow;
+
+    let mix = |channel: usize| (color[channel] as f32 + (HEAT_GLOW_COLOR[channel] as f32 - color[channel] as f32) * glow) / 255.;
+    Color::rgb(mix(0), mix(1), mix(2))
+}
+
+fn sync_ejecta_sprites(
+    world: Res<Sandworld>,
+    draw_options: Res<DrawOptions>,
+    mut sprites: ResMut<EjectaSprites>,
+    mut sprite_query: Query<(&mut Transform, &mut Sprite, &mut Visibility)>,
+    mut commands: Commands,
+) {
+    let ejecta = world.world.get_ejecta();
+
+    // New sprites show up next frame, which is soon enough for something this small
+    while sprites.entities.len() < ejecta.len() {
+        let entity = commands.spawn(SpriteBundle {
+            visibility: Visibility::Hidden,
+            ..default()
+        }).id();
+        sprites.entities.push(entity);
+    }
+
+    for (index, entity) in sprites.entities.iter().enumerate() {
+        let Ok((mut transform, mut sprite, mut visibility)) = sprite_query.get_mut(*entity) else {
+            continue;
+        };
+
+        match ejecta.get(index) {
+            Some(piece) => {
+                *transform = Transform::from_xyz(piece.position[0], piece.position[1], 0.1);
+                sprite.color = get_ejecta_color(piece, draw_options.heat_glow);
+                *visibility = Visibility::Visible;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+}
@@ -0,0 +1,689 @@
+use std::fmt;
+
+use bevy::{input::InputSystem, prelude::*, window::{CursorMoved, PrimaryWindow}};
+
+// Shipped defaults, only ever read
+pub const INPUT_MAP_PATH: &str = "assets/input.cfg";
+// Controls changed in game, read on top of the defaults
+pub const USER_INPUT_MAP_PATH: &str = "input.user.cfg";
+
+// Analog input below this counts as nothing, so resting sticks don't drift
+const AXIS_DEADZONE: f32 = 0.2;
+// Actions count as pressed once their value passes this
+const PRESS_THRESHOLD: f32 = 0.5;
+// Pixels per second the gamepad cursor moves at with the stick all the way over
+const GAMEPAD_CURSOR_SPEED: f32 = 900.;
+
+pub struct InputMapPlugin;
+
+// Everything the game can be told to do from a keyboard, mouse or gamepad
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    UseTool,
+    Erase,
+    RadiusUp,
+    RadiusDown,
+    NextTool,
+    PrevTool,
+    CursorLeft,
+    CursorRight,
+    CursorUp,
+    CursorDown,
+    CameraLeft,
+    CameraRight,
+    CameraUp,
+    CameraDown,
+    CameraDrift,
+    ZoomIn,
+    ZoomOut,
+    TogglePlayer,
+    PlayerLeft,
+    PlayerRight,
+    PlayerJump,
+    PlayerDown,
+    PlayerDig,
+    PlayerPlace,
+    PrevItem,
+    NextItem,
+    NewWorld,
+    Controls,
+    ToggleInspector,
+    ToggleMinimap,
+    ToggleRecording,
+    ReloadScripts,
+    ToggleChunkBounds,
+    ToggleUpdateBounds,
+    ToggleWorldStats,
+    ToggleColorVariation,
+    ToggleEdgeShading,
+    ToggleHeatGlow,
+    ToggleLighting,
+    CycleOverlay,
+    ToggleColliders,
+    ForceCompress,
+}
+
+// A single key, button or half of a stick that can drive an action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputBinding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Pad(GamepadButtonType),
+    // An axis pushed towards its positive or negative end
+    Axis(GamepadAxisType, bool),
+}
+
+#[derive(Debug)]
+pub enum InputMapError {
+    Read(std::io::Error),
+    Write(std::io::Error),
+    BadLine(String),
+    UnknownAction(String),
+    UnknownBinding(String),
+}
+
+impl fmt::Display for InputMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputMapError::Read(err) => write!(f, "could not read input map: {}", err),
+            InputMapError::Write(err) => write!(f, "could not save input map: {}", err),
+            InputMapError::BadLine(line) => write!(f, "expected `action = binding, binding`, found `{}`", line),
+            InputMapError::UnknownAction(action) => write!(f, "unknown action `{}`", action),
+            InputMapError::UnknownBinding(binding) => write!(f, "unknown key or button `{}`", binding),
+        }
+    }
+}
+
+// Keys that can be bound, Escape is left out since it cancels rebinding
+const KEYS: [KeyCode; 89] = [
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF, KeyCode::KeyG,
+    KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN,
+    KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU,
+    KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY, KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::ArrowLeft, KeyCode::ArrowRight, KeyCode::ArrowUp, KeyCode::ArrowDown,
+    KeyCode::Space, KeyCode::Enter, KeyCode::Tab, KeyCode::Backspace,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::AltLeft, KeyCode::AltRight,
+    KeyCode::PageUp, KeyCode::PageDown, KeyCode::Home, KeyCode::End, KeyCode::Insert, KeyCode::Delete,
+    KeyCode::Minus, KeyCode::Equal, KeyCode::BracketLeft, KeyCode::BracketRight, KeyCode::Backslash,
+    KeyCode::Semicolon, KeyCode::Quote, KeyCode::Comma, KeyCode::Period, KeyCode::Slash, KeyCode::Backquote,
+    KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+    KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9,
+];
+
+const MOUSE_BUTTONS: [MouseButton; 5] = [
+    MouseButton::Left, MouseButton::Right, MouseButton::Middle, MouseButton::Back, MouseButton::Forward,
+];
+
+const PAD_BUTTONS: [GamepadButtonType; 19] = [
+    GamepadButtonType::South, GamepadButtonType::East, GamepadButtonType::North, GamepadButtonType::West,
+    GamepadButtonType::C, GamepadButtonType::Z,
+    GamepadButtonType::LeftTrigger, GamepadButtonType::LeftTrigger2,
+    GamepadButtonType::RightTrigger, GamepadButtonType::RightTrigger2,
+    GamepadButtonType::Select, GamepadButtonType::Start, GamepadButtonType::Mode,
+    GamepadButtonType::LeftThumb, GamepadButtonType::RightThumb,
+    GamepadButtonType::DPadUp, GamepadButtonType::DPadDown, GamepadButtonType::DPadLeft, GamepadButtonType::DPadRight,
+];
+
+const PAD_AXES: [GamepadAxisType; 6] = [
+    GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY, GamepadAxisType::LeftZ,
+    GamepadAxisType::RightStickX, GamepadAxisType::RightStickY, GamepadAxisType::RightZ,
+];
+
+impl Action {
+    pub const ALL: [Action; 42] = [
+        Action::UseTool,
+        Action::Erase,
+        Action::RadiusUp,
+        Action::RadiusDown,
+        Action::NextTool,
+        Action::PrevTool,
+        Action::CursorLeft,
+        Action::CursorRight,
+        Action::CursorUp,
+        Action::CursorDown,
+        Action::CameraLeft,
+        Action::CameraRight,
+        Action::CameraUp,
+        Action::CameraDown,
+        Action::CameraDrift,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::TogglePlayer,
+        Action::PlayerLeft,
+        Action::PlayerRight,
+        Action::PlayerJump,
+        Action::PlayerDown,
+        Action::PlayerDig,
+        Action::PlayerPlace,
+        Action::PrevItem,
+        Action::NextItem,
+        Action::NewWorld,
+        Action::Controls,
+        Action::ToggleInspector,
+        Action::ToggleMinimap,
+        Action::ToggleRecording,
+        Action::ReloadScripts,
+        Action::ToggleChunkBounds,
+        Action::ToggleUpdateBounds,
+        Action::ToggleWorldStats,
+        Action::ToggleColorVariation,
+        Action::ToggleEdgeShading,
+        Action::ToggleHeatGlow,
+        Action::ToggleLighting,
+        Action::CycleOverlay,
+        Action::ToggleColliders,
+        Action::ForceCompress,
+    ];
+
+    // Name used in the input map file
+    pub fn key(&self) -> &'static str {
+        match self {
+            Action::UseTool => "use_tool",
+            Action::Erase => "erase",
+            Action::RadiusUp => "radius_up",
+            Action::RadiusDown => "radius_down",
+            Action::NextTool => "next_tool",
+            Action::PrevTool => "prev_tool",
+            Action::CursorLeft => "cursor_left",
+            Action::CursorRight => "cursor_right",
+            Action::CursorUp => "cursor_up",
+            Action::CursorDown => "cursor_down",
+            Action::CameraLeft => "camera_left",
+            Action::CameraRight => "camera_right",
+            Action::CameraUp => "camera_up",
+            Action::CameraDown => "camera_down",
+            Action::CameraDrift => "camera_drift",
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::TogglePlayer => "toggle_player",
+            Action::PlayerLeft => "player_left",
+            Action::PlayerRight => "player_right",
+            Action::PlayerJump => "player_jump",
+            Action::PlayerDown => "player_down",
+            Action::PlayerDig => "player_dig",
+            Action::PlayerPlace => "player_place",
+            Action::PrevItem => "prev_item",
+            Action::NextItem => "next_item",
+            Action::NewWorld => "new_world",
+            Action::Controls => "controls",
+            Action::ToggleInspector => "toggle_inspector",
+            Action::ToggleMinimap => "toggle_minimap",
+            Action::ToggleRecording => "toggle_recording",
+            Action::ReloadScripts => "reload_scripts",
+            Action::ToggleChunkBounds => "toggle_chunk_bounds",
+            Action::ToggleUpdateBounds => "toggle_update_bounds",
+            Action::ToggleWorldStats => "toggle_world_stats",
+            Action::ToggleColorVariation => "toggle_color_variation",
+            Action::ToggleEdgeShading => "toggle_edge_shading",
+            Action::ToggleHeatGlow => "toggle_heat_glow",
+            Action::ToggleLighting => "toggle_lighting",
+            Action::CycleOverlay => "cycle_overlay",
+            Action::ToggleColliders => "toggle_colliders",
+            Action::ForceCompress => "force_compress",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::UseTool => "Use tool",
+            Action::Erase => "Erase",
+            Action::RadiusUp => "Grow brush",
+            Action::RadiusDown => "Shrink brush",
+            Action::NextTool => "Next tool",
+            Action::PrevTool => "Previous tool",
+            Action::CursorLeft => "Cursor left",
+            Action::CursorRight => "Cursor right",
+            Action::CursorUp => "Cursor up",
+            Action::CursorDown => "Cursor down",
+            Action::CameraLeft => "Camera left",
+            Action::CameraRight => "Camera right",
+            Action::CameraUp => "Camera up",
+            Action::CameraDown => "Camera down",
+            Action::CameraDrift => "Camera drift (hold)",
+            Action::ZoomIn => "Zoom in",
+            Action::ZoomOut => "Zoom out",
+            Action::TogglePlayer => "Toggle player",
+            Action::PlayerLeft => "Player left",
+            Action::PlayerRight => "Player right",
+            Action::PlayerJump => "Player jump",
+            Action::PlayerDown => "Player swim down",
+            Action::PlayerDig => "Player dig",
+            Action::PlayerPlace => "Player place",
+            Action::PrevItem => "Previous item",
+            Action::NextItem => "Next item",
+            Action::NewWorld => "New world screen",
+            Action::Controls => "Controls screen",
+            Action::ToggleInspector => "Cell inspector",
+            Action::ToggleMinimap => "Minimap",
+            Action::ToggleRecording => "Record timelapse",
+            Action::ReloadScripts => "Reload scripts",
+            Action::ToggleChunkBounds => "Chunk bounds",
+            Action::ToggleUpdateBounds => "Update bounds",
+            Action::ToggleWorldStats => "World stats",
+            Action::ToggleColorVariation => "Color variation",
+            Action::ToggleEdgeShading => "Edge shading",
+            Action::ToggleHeatGlow => "Heat glow",
+            Action::ToggleLighting => "Lighting",
+            Action::CycleOverlay => "Debug overlay",
+            Action::ToggleColliders => "Collider outlines",
+            Action::ForceCompress => "Force compress",
+        }
+    }
+
+    pub fn default_bindings(&self) -> Vec<InputBinding> {
+        use InputBinding::*;
+        match self {
+            Action::UseTool => vec![Mouse(MouseButton::Left), Pad(GamepadButtonType::RightTrigger2)],
+            Action::Erase => vec![Mouse(MouseButton::Right), Pad(GamepadButtonType::LeftTrigger2)],
+            Action::RadiusUp => vec![Key(KeyCode::Equal), Pad(GamepadButtonType::DPadUp)],
+            Action::RadiusDown => vec![Key(KeyCode::Minus), Pad(GamepadButtonType::DPadDown)],
+            Action::NextTool => vec![Pad(GamepadButtonType::DPadRight)],
+            Action::PrevTool => vec![Pad(GamepadButtonType::DPadLeft)],
+            Action::CursorLeft => vec![Axis(GamepadAxisType::RightStickX, false)],
+            Action::CursorRight => vec![Axis(GamepadAxisType::RightStickX, true)],
+            Action::CursorUp => vec![Axis(GamepadAxisType::RightStickY, true)],
+            Action::CursorDown => vec![Axis(GamepadAxisType::RightStickY, false)],
+            Action::CameraLeft => vec![Key(KeyCode::KeyA), Key(KeyCode::ArrowLeft), Axis(GamepadAxisType::LeftStickX, false)],
+            Action::CameraRight => vec![Key(KeyCode::KeyD), Key(KeyCode::ArrowRight), Axis(GamepadAxisType::LeftStickX, true)],
+            Action::CameraUp => vec![Key(KeyCode::KeyW), Key(KeyCode::ArrowUp), Axis(GamepadAxisType::LeftStickY, true)],
+            Action::CameraDown => vec![Key(KeyCode::KeyS), Key(KeyCode::ArrowDown), Axis(GamepadAxisType::LeftStickY, false)],
+            Action::CameraDrift => vec![Key(KeyCode::ShiftLeft)],
+            Action::ZoomIn => vec![Key(KeyCode::PageUp), Key(KeyCode::BracketRight), Pad(GamepadButtonType::RightTrigger)],
+            Action::ZoomOut => vec![Key(KeyCode::PageDown), Key(KeyCode::BracketLeft), Pad(GamepadButtonType::LeftTrigger)],
+            Action::TogglePlayer => vec![Key(KeyCode::KeyP), Pad(GamepadButtonType::North)],
+            Action::PlayerLeft => vec![Key(KeyCode::KeyA), Key(KeyCode::ArrowLeft), Axis(GamepadAxisType::LeftStickX, false)],
+            Action::PlayerRight => vec![Key(KeyCode::KeyD), Key(KeyCode::ArrowRight), Axis(GamepadAxisType::LeftStickX, true)],
+            Action::PlayerJump => vec![Key(KeyCode::KeyW), Key(KeyCode::ArrowUp), Key(KeyCode::Space), Pad(GamepadButtonType::South)],
+            Action::PlayerDown => vec![Key(KeyCode::KeyS), Key(KeyCode::ArrowDown), Axis(GamepadAxisType::LeftStickY, false)],
+            Action::PlayerDig => vec![Mouse(MouseButton::Left), Pad(GamepadButtonType::RightTrigger2)],
+            Action::PlayerPlace => vec![Mouse(MouseButton::Right), Pad(GamepadButtonType::LeftTrigger2)],
+            Action::PrevItem => vec![Key(KeyCode::KeyQ), Pad(GamepadButtonType::West)],
+            Action::NextItem => vec![Key(KeyCode::KeyE), Pad(GamepadButtonType::East)],
+            Action::NewWorld => vec![Key(KeyCode::F1), Pad(GamepadButtonType::Start)],
+            Action::Controls => vec![Key(KeyCode::F12), Pad(GamepadButtonType::Select)],
+            Action::ToggleInspector => vec![Key(KeyCode::KeyI)],
+            Action::ToggleMinimap => vec![Key(KeyCode::KeyM)],
+            Action::ToggleRecording => vec![Key(KeyCode::KeyR)],
+            Action::ReloadScripts => vec![Key(KeyCode::F5)],
+            Action::ToggleChunkBounds => vec![Key(KeyCode::F2)],
+            Action::ToggleUpdateBounds => vec![Key(KeyCode::F3)],
+            Action::ToggleWorldStats => vec![Key(KeyCode::F4)],
+            Action::ToggleColorVariation => vec![Key(KeyCode::F6)],
+            Action::ToggleEdgeShading => vec![Key(KeyCode::F7)],
+            Action::ToggleHeatGlow => vec![Key(KeyCode::F8)],
+            Action::ToggleLighting => vec![Key(KeyCode::F9)],
+            Action::CycleOverlay => vec![Key(KeyCode::F11)],
+            Action::ToggleColliders => vec![Key(KeyCode::KeyC)],
+            Action::ForceCompress => vec![Key(KeyCode::F10)],
+        }
+    }
+}
+
+impl InputBinding {
+    // Name used in the input map file, eg `KeyA`, `Mouse:Left`, `Pad:South` or `Axis:LeftStickX+`
+    pub fn name(&self) -> String {
+        match self {
+            InputBinding::Key(key) => format!("{:?}", key),
+            InputBinding::Mouse(button) => format!("Mouse:{:?}", button),
+            InputBinding::Pad(button) => format!("Pad:{:?}", button),
+            InputBinding::Axis(axis, positive) => format!("Axis:{:?}{}", axis, if *positive { '+' } else { '-' }),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<InputBinding> {
+        fn find<T: Copy + fmt::Debug>(options: &[T], name: &str) -> Option<T> {
+            options.iter().copied().find(|option| format!("{:?}", option).eq_ignore_ascii_case(name))
+        }
+
+        if let Some(button) = name.strip_prefix("Mouse:") {
+            find(&MOUSE_BUTTONS, button).map(InputBinding::Mouse)
+        }
+        else if let Some(button) = name.strip_prefix("Pad:") {
+            find(&PAD_BUTTONS, button).map(InputBinding::Pad)
+        }
+        else if let Some(axis) = name.strip_prefix("Axis:") {
+            let (axis, positive) = match axis.strip_suffix('+') {
+                Some(axis) => (axis, true),
+                None => (axis.strip_suffix('-')?, false),
+            };
+            find(&PAD_AXES, axis).map(|axis| InputBinding::Axis(axis, positive))
+        }
+        else {
+            find(&KEYS, name).map(InputBinding::Key)
+        }
+    }
+
+    pub fn is_gamepad(&self) -> bool {
+        matches!(self, InputBinding::Pad(_) | InputBinding::Axis(..))
+    }
+}
+
+/*
+    Which bindings drive each action, loaded from a file with one action per line, eg
+
+        zoom_in = PageUp, BracketRight, Pad:RightTrigger
+        camera_left = KeyA, Axis:LeftStickX-
+
+    Actions missing from the file keep their defaults, and an action with nothing after the `=` is unbound
+*/
+#[derive(Resource, Debug, Clone)]
+pub struct InputMap {
+    bindings: Vec<Vec<InputBinding>>, // Indexed by Action, in the same order as Action::ALL
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        InputMap {
+            bindings: Action::ALL.iter().map(|action| action.default_bindings()).collect(),
+        }
+    }
+}
+
+impl InputMap {
+    // Falls back to the defaults if the file is missing, other problems are reported
+    pub fn load(path: &str) -> Result<Self, InputMapError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(InputMap::default()),
+            Err(err) => Err(InputMapError::Read(err)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, InputMapError> {
+        let mut map = InputMap::default();
+        map.apply_overrides(text)?;
+        Ok(map)
+    }
+
+    // The shipped bindings, or the built in ones if they can't be read
+    pub fn load_defaults() -> Self {
+        InputMap::load(INPUT_MAP_PATH).unwrap_or_else(|err| {
+            println!("Error in {}: {}", INPUT_MAP_PATH, err);
+            InputMap::default()
+        })
+    }
+
+    // Read a file of changed bindings over this map, a missing file changes nothing
+    pub fn load_overrides(&mut self, path: &str) -> Result<(), InputMapError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => self.apply_overrides(&text),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(InputMapError::Read(err)),
+        }
+    }
+
+    // Replace the bindings of every action in the text, leaving the map as it was if any line is bad
+    pub fn apply_overrides(&mut self, text: &str) -> Result<(), InputMapError> {
+        let mut map = self.clone();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(InputMapError::BadLine(line.to_string()));
+            };
+            let key = key.trim();
+
+            let action = Action::ALL.into_iter().find(|action| action.key() == key)
+                .ok_or_else(|| InputMapError::UnknownAction(key.to_string()))?;
+            let bindings = value.split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| InputBinding::from_name(name).ok_or_else(|| InputMapError::UnknownBinding(name.to_string())))
+                .collect::<Result<Vec<_>, _>>()?;
+            map.bindings[action as usize] = bindings;
+        }
+
+        *self = map;
+        Ok(())
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), InputMapError> {
+        std::fs::write(path, self.to_text()).map_err(InputMapError::Write)
+    }
+
+    // The file save writes, which parse reads back to the same map
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# Input bindings, controls changed in game (F12) are saved to input.user.cfg and read over assets/input.cfg\n");
+        text += "# Keys use their names (KeyA, F1, ArrowLeft), mouse buttons Mouse:Left, gamepad buttons Pad:South,\n";
+        text += "# and gamepad sticks Axis:LeftStickX+ or Axis:LeftStickX- for either direction\n\n";
+        for action in Action::ALL {
+            let names: Vec<String> = self.get(action).iter().map(InputBinding::name).collect();
+            text += &format!("{} = {}\n", action.key(), names.join(", "));
+        }
+        text
+    }
+
+    pub fn get(&self, action: Action) -> &[InputBinding] {
+        &self.bindings[action as usize]
+    }
+
+    // Swap out the action's binding for the same kind of device, keeping the rest
+    pub fn rebind(&mut self, action: Action, binding: InputBinding) {
+        let bindings = &mut self.bindings[action as usize];
+        bindings.retain(|existing| existing.is_gamepad() != binding.is_gamepad());
+        bindings.push(binding);
+    }
+
+    // Back to the shipped bindings
+    pub fn reset(&mut self) {
+        *self = InputMap::load_defaults();
+    }
+}
+
+/*
+    Current value of every action, updated from the InputMap at the start of each frame
+    Buttons are 0 or 1 and sticks anywhere in between, the strongest binding wins
+
+    Also tracks the cursor the tools and player aim with, which the gamepad can move with the
+    cursor actions until the mouse moves again
+*/
+#[derive(Resource)]
+pub struct ActionState {
+    values: Vec<f32>,
+    previous: Vec<f32>,
+    // Held inputs ignored until they're let go, so the input that finishes a rebind doesn't also act
+    suppressed: Vec<bool>,
+    gamepad_cursor: Option<Vec2>,
+}
+
+impl Default for ActionState {
+    fn default() -> Self {
+        ActionState {
+            values: vec![0.; Action::ALL.len()],
+            previous: vec![0.; Action::ALL.len()],
+            suppressed: vec![false; Action::ALL.len()],
+            gamepad_cursor: None,
+        }
+    }
+}
+
+impl ActionState {
+    pub fn value(&self, action: Action) -> f32 {
+        self.values[action as usize]
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.values[action as usize] > PRESS_THRESHOLD
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed(action) && self.previous[action as usize] <= PRESS_THRESHOLD
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        !self.pressed(action) && self.previous[action as usize] > PRESS_THRESHOLD
+    }
+
+    // Release every action and ignore whatever is held until it's let go
+    pub fn suppress_held(&mut self) {
+        self.values.fill(0.);
+        self.previous.fill(0.);
+        self.suppressed.fill(true);
+    }
+
+    // Screen position of the gamepad cursor if it's in use, otherwise the mouse
+    pub fn cursor_position(&self, window: &Window) -> Option<Vec2> {
+        self.gamepad_cursor.or_else(|| window.cursor_position())
+    }
+}
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        let mut map = InputMap::load_defaults();
+        if let Err(err) = map.load_overrides(USER_INPUT_MAP_PATH) {
+            println!("Error in {}: {}", USER_INPUT_MAP_PATH, err);
+        }
+
+        app.insert_resource(map)
+            .init_resource::<ActionState>()
+            .add_systems(PreUpdate, (update_action_state, move_gamepad_cursor).chain().after(InputSystem));
+    }
+}
+
+fn apply_deadzone(value: f32) -> f32 {
+    if value < AXIS_DEADZONE { 0. } else { ((value - AXIS_DEADZONE) / (1. - AXIS_DEADZONE)).min(1.) }
+}
+
+fn update_action_state(
+    map: Res<InputMap>,
+    mut state: ResMut<ActionState>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    pad_buttons: Res<ButtonInput<GamepadButton>>,
+    pad_axes: Res<Axis<GamepadAxis>>,
+) {
+    let binding_value = |binding: &InputBinding| -> f32 {
+        match *binding {
+            InputBinding::Key(key) => keys.pressed(key) as u8 as f32,
+            InputBinding::Mouse(button) => mouse.pressed(button) as u8 as f32,
+            InputBinding::Pad(button) => {
+                gamepads.iter().any(|gamepad| pad_buttons.pressed(GamepadButton::new(gamepad, button))) as u8 as f32
+            }
+            InputBinding::Axis(axis, positive) => {
+                let sign = if positive { 1. } else { -1. };
+                gamepads.iter()
+                    .filter_map(|gamepad| pad_axes.get(GamepadAxis::new(gamepad, axis)))
+                    .map(|value| apply_deadzone(value * sign))
+                    .fold(0., f32::max)
+            }
+        }
+    };
+
+    let state = &mut *state;
+    for action in Action::ALL {
+        let index = action as usize;
+        let value = map.get(action).iter().map(binding_value).fold(0., f32::max);
+
+        if state.suppressed[index] && value > 0. {
+            state.values[index] = 0.;
+            continue;
+        }
+        state.suppressed[index] = false;
+        state.previous[index] = state.values[index];
+        state.values[index] = value;
+    }
+}
+
+// The cursor actions steer a cursor of their own, handing control back once the mouse moves
+fn move_gamepad_cursor(
+    mut state: ResMut<ActionState>,
+    mut cursor_events: EventReader<CursorMoved>,
+    wnds: Query<&Window, With<PrimaryWindow>>,
+    time: Res<Time>,
+) {
+    if cursor_events.read().last().is_some() {
+        state.gamepad_cursor = None;
+    }
+
+    let Ok(window) = wnds.get_single() else {
+        return;
+    };
+
+    // Screen space runs top down
+    let direction = Vec2::new(
+        state.value(Action::CursorRight) - state.value(Action::CursorLeft),
+        state.value(Action::CursorDown) - state.value(Action::CursorUp),
+    );
+    if direction == Vec2::ZERO {
+        return;
+    }
+
+    let size = Vec2::new(window.width(), window.height());
+    let start = state.cursor_position(window).unwrap_or(size / 2.);
+    state.gamepad_cursor = Some((start + direction * GAMEPAD_CURSOR_SPEED * time.delta_seconds()).clamp(Vec2::ZERO, size));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input_map::*;
+
+    #[test]
+    fn saved_text_parses_back() {
+        let mut map = InputMap::default();
+        map.rebind(Action::ZoomIn, InputBinding::Mouse(MouseButton::Middle));
+        map.rebind(Action::CameraLeft, InputBinding::Axis(GamepadAxisType::RightStickX, false));
+
+        let parsed = InputMap::parse(&map.to_text()).unwrap();
+        for action in Action::ALL {
+            assert_eq!(parsed.get(action), map.get(action), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn binding_names_parse() {
+        assert_eq!(InputBinding::from_name("Axis:LeftStickX-"), Some(InputBinding::Axis(GamepadAxisType::LeftStickX, false)));
+        assert_eq!(InputBinding::from_name("Axis:LeftStickX+"), Some(InputBinding::Axis(GamepadAxisType::LeftStickX, true)));
+        assert_eq!(InputBinding::from_name("Pad:South"), Some(InputBinding::Pad(GamepadButtonType::South)));
+        assert_eq!(InputBinding::from_name("BracketLeft"), Some(InputBinding::Key(KeyCode::BracketLeft)));
+        assert_eq!(InputBinding::from_name("Mouse:Left"), Some(InputBinding::Mouse(MouseButton::Left)));
+        // Axes need a direction
+        assert_eq!(InputBinding::from_name("Axis:LeftStickX"), None);
+    }
+
+    #[test]
+    fn empty_binding_list_unbinds() {
+        let map = InputMap::parse("zoom_in =\n# comment\n\ncamera_left = KeyJ").unwrap();
+        assert!(map.get(Action::ZoomIn).is_empty());
+        assert_eq!(map.get(Action::CameraLeft), [InputBinding::Key(KeyCode::KeyJ)]);
+        // Anything not mentioned keeps its defaults
+        assert_eq!(map.get(Action::ZoomOut), Action::ZoomOut.default_bindings());
+    }
+
+    #[test]
+    fn overrides_replace_only_their_actions() {
+        let mut map = InputMap::parse("zoom_in = KeyJ\nzoom_out = KeyK").unwrap();
+        map.apply_overrides("zoom_in = KeyL").unwrap();
+        assert_eq!(map.get(Action::ZoomIn), [InputBinding::Key(KeyCode::KeyL)]);
+        assert_eq!(map.get(Action::ZoomOut), [InputBinding::Key(KeyCode::KeyK)]);
+
+        // A bad file doesn't leave the map half overridden
+        assert!(map.apply_overrides("zoom_out = KeyM\nzoom_in = NotAKey").is_err());
+        assert_eq!(map.get(Action::ZoomOut), [InputBinding::Key(KeyCode::KeyK)]);
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        assert!(matches!(InputMap::parse("not_an_action = KeyA"), Err(InputMapError::UnknownAction(_))));
+        assert!(matches!(InputMap::parse("zoom_in = KeyA, NotAKey"), Err(InputMapError::UnknownBinding(_))));
+        assert!(matches!(InputMap::parse("zoom_in KeyA"), Err(InputMapError::BadLine(_))));
+    }
+
+    #[test]
+    fn rebind_keeps_the_other_device() {
+        let mut map = InputMap::default();
+        map.rebind(Action::PlayerJump, InputBinding::Key(KeyCode::KeyK));
+        assert_eq!(map.get(Action::PlayerJump), [InputBinding::Pad(GamepadButtonType::South), InputBinding::Key(KeyCode::KeyK)]);
+
+        map.rebind(Action::PlayerJump, InputBinding::Axis(GamepadAxisType::LeftStickY, true));
+        assert_eq!(map.get(Action::PlayerJump), [InputBinding::Key(KeyCode::KeyK), InputBinding::Axis(GamepadAxisType::LeftStickY, true)]);
+    }
+}
@@ -0,0 +1,132 @@
+use bevy::prelude::*;
+use gridmath::GridBounds;
+use sandworld::{BiomeWorld, CellInfo};
+
+use crate::{
+    input_map::{Action, ActionState},
+    sandsim::{Sandworld, WorldStats},
+    ui::PANEL_COLOR,
+    worldgen_settings::{GeneratorKind, WorldGenSettings},
+};
+
+pub struct InspectorPlugin;
+
+/*
+    Readout of the cell under the cursor, toggled with I
+    Shows the particle and its data bits, the temperature the simulation sees there,
+    the update state of the chunk and region holding it, and the biome when the world has them
+*/
+#[derive(Resource, Default)]
+struct InspectorState {
+    open: bool,
+}
+
+#[derive(Component)]
+struct InspectorRoot;
+
+#[derive(Component)]
+struct InspectorText;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InspectorState>()
+            .add_systems(Startup, spawn_inspector)
+            .add_systems(Update, toggle_inspector.in_set(crate::UpdateStages::Input))
+            // After the world update, so the updated flags are for this frame
+            .add_systems(Update, update_inspector.in_set(crate::UpdateStages::UI).after(crate::UpdateStages::WorldUpdate));
+    }
+}
+
+fn spawn_inspector(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((NodeBundle {
+        style: Style {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.),
+            bottom: Val::Px(80.),
+            padding: UiRect::all(Val::Px(8.)),
+            ..default()
+        },
+        background_color: PANEL_COLOR.into(),
+        ..default()
+    }, InspectorRoot)).with_children(|parent| {
+        parent.spawn((TextBundle::from_section("", TextStyle {
+            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+            font_size: 18.0,
+            color: Color::rgb(0.9, 0.9, 0.9),
+        }), InspectorText));
+    });
+}
+
+fn toggle_inspector(
+    mut state: ResMut<InspectorState>,
+    actions: Res<ActionState>,
+) {
+    if actions.just_pressed(Action::ToggleInspector) {
+        state.open = !state.open;
+    }
+}
+
+fn format_bounds(bounds: Option<GridBounds>) -> String {
+    match bounds {
+        Some(bounds) => format!("{} to {}", bounds.bottom_left(), bounds.top_right()),
+        None => "none".to_string(),
+    }
+}
+
+fn format_cell_info(info: &CellInfo) -> String {
+    let particle = info.particle;
+    format!(
+        "{:?}\nData: {:#06x} (direction {}, mode {}, charge {}, lifetime {})\nVariant: {}\nLocal temp: {}\nUpdated this frame: {}\n\
+        Chunk {}\n  Dirty: {}\n  Updating: {}\nRegion {}\n  Staleness: {}\n  Update priority: {}",
+        particle.particle_type,
+        particle.data(),
+        particle.direction(),
+        particle.mode(),
+        particle.charge(),
+        particle.lifetime(),
+        particle.variant(),
+        info.local_temp,
+        if info.updated_this_frame { "yes" } else { "no" },
+        info.chunk_pos,
+        format_bounds(info.dirty_bounds),
+        format_bounds(info.update_bounds),
+        info.region_pos,
+        info.staleness,
+        info.update_priority,
+    )
+}
+
+fn update_inspector(
+    state: Res<InspectorState>,
+    world: Res<Sandworld>,
+    stats: Res<WorldStats>,
+    mut root_query: Query<&mut Style, With<InspectorRoot>>,
+    mut text_query: Query<&mut Text, With<InspectorText>>,
+    settings: Res<WorldGenSettings>,
+    mut biomes: Local<Option<BiomeWorld>>,
+) {
+    // Settings only change when the world is recreated from them, so this always matches the world
+    if settings.is_changed() {
+        *biomes = (settings.generator == GeneratorKind::Biomes).then(|| settings.build_biome_world());
+    }
+
+    if state.is_changed() {
+        root_query.single_mut().display = if state.open { Display::Flex } else { Display::None };
+    }
+    if !state.open {
+        return;
+    }
+
+    // World stats already track the cell under the cursor, whether it's the mouse or the gamepad's
+    let pos = stats.mouse_grid_pos;
+    let details = match world.world.inspect_cell(pos) {
+        Some(info) => format_cell_info(&info),
+        None => "Not loaded".to_string(),
+    };
+    let biome = match biomes.as_ref() {
+        Some(biomes) => format!("\nBiome: {}", biomes.biome_at(pos.x).name),
+        None => String::new(),
+    };
+    text_query.single_mut().sections[0].value = format!("Cell {}{}\n{}", pos, biome, details);
+}
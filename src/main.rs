@@ -7,10 +7,23 @@ use bevy::{prelude::*, window::PresentMode, window::WindowResolution};
 use bevy_xpbd_2d::prelude::*;
 
 mod camera;
+mod input_map;
+mod controls_screen;
+mod inspector;
+mod minimap;
+mod overlays;
 mod perf;
+mod recorder;
 mod sandsim;
 mod ui;
-mod worldgen;
+mod worldgen_settings;
+mod new_world;
+mod particle_bodies;
+mod player;
+mod buoyancy;
+mod ejecta;
+mod tools;
+mod builtin_tools;
 mod polyline;
 mod chunk_display;
 mod chunk_colliders;
@@ -40,9 +53,21 @@ fn main() {
                     ..default()
                 }),
         )
+        .add_plugins(crate::input_map::InputMapPlugin)
         .add_plugins(crate::sandsim::SandSimulationPlugin)
         .add_plugins(crate::camera::CameraPlugin)
+        .add_plugins(crate::tools::ToolsPlugin)
         .add_plugins(crate::ui::UiPlugin)
+        .add_plugins(crate::new_world::NewWorldPlugin)
+        .add_plugins(crate::controls_screen::ControlsScreenPlugin)
+        .add_plugins(crate::inspector::InspectorPlugin)
+        .add_plugins(crate::minimap::MinimapPlugin)
+        .add_plugins(crate::recorder::RecorderPlugin)
+        .add_plugins(crate::overlays::OverlayPlugin)
+        .add_plugins(crate::particle_bodies::ParticleBodyPlugin)
+        .add_plugins(crate::player::PlayerPlugin)
+        .add_plugins(crate::buoyancy::BuoyancyPlugin)
+        .add_plugins(crate::ejecta::EjectaPlugin)
         .add_plugins(crate::perf::PerfControlPlugin)
         .add_plugins(PhysicsPlugins::default())
         // .add_plugins(PhysicsDebugPlugin::default())
@@ -0,0 +1,263 @@
+use bevy::{prelude::*, render::{render_asset::RenderAssetUsages, render_resource::{Extent3d, TextureDimension, TextureFormat}}, ui::RelativeCursorPosition};
+use gridmath::GridVec;
+use sandworld::{REGION_THUMBNAIL_SIZE, TRUE_REGION_SIZE};
+
+use crate::{
+    camera::cam_bounds,
+    input_map::{Action, ActionState},
+    overlays::get_region_state_color,
+    sandsim::Sandworld,
+    ui::{PointerCaptureState, PANEL_COLOR},
+};
+
+pub struct MinimapPlugin;
+
+// Regions shown across each side of the map, odd so the camera's region is in the middle
+const MINIMAP_REGIONS: i32 = 5;
+const MINIMAP_PIXELS: usize = MINIMAP_REGIONS as usize * REGION_THUMBNAIL_SIZE;
+// On screen size of the map
+const MINIMAP_DISPLAY_SIZE: f32 = 240.;
+// Rendering a loaded region's thumbnail samples every one of its chunks, so don't do it every frame
+const MINIMAP_REFRESH_SECONDS: f32 = 0.5;
+
+const UNKNOWN_REGION_COLOR: [u8; 4] = [16, 16, 16, 255];
+const VIEWPORT_COLOR: Color = Color::rgb(0.95, 0.95, 0.95);
+
+/*
+    Map of the regions around the camera, toggled with M
+    Loaded regions are drawn from their chunks, compressed ones from the thumbnail cached when they were compressed,
+    and each region is outlined in its load state color. Clicking the map moves the camera there
+*/
+#[derive(Resource)]
+struct Minimap {
+    open: bool,
+    image: Handle<Image>,
+    // Region in the middle of the map when it was last drawn
+    center: GridVec,
+    refresh_timer: f32,
+}
+
+impl Minimap {
+    // World position of the bottom left corner of the map
+    fn origin(&self) -> Vec2 {
+        let corner = (self.center - GridVec::new(MINIMAP_REGIONS / 2, MINIMAP_REGIONS / 2)) * TRUE_REGION_SIZE as i32;
+        Vec2::new(corner.x as f32, corner.y as f32)
+    }
+
+    // Normalized map position, top down like UI coordinates, to world position
+    fn to_world(&self, normalized: Vec2) -> Vec2 {
+        let span = (MINIMAP_REGIONS as usize * TRUE_REGION_SIZE) as f32;
+        self.origin() + Vec2::new(normalized.x, 1. - normalized.y) * span
+    }
+}
+
+#[derive(Component)]
+struct MinimapRoot;
+
+#[derive(Component)]
+struct MinimapViewport;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_minimap)
+            .add_systems(
+                Update,
+                minimap_click
+                    .in_set(crate::UpdateStages::UI)
+                    .after(crate::ui::button_system)
+                    .before(crate::UpdateStages::Input),
+            )
+            .add_systems(Update, toggle_minimap.in_set(crate::UpdateStages::Input))
+            .add_systems(Update, (draw_minimap, update_minimap_viewport).chain().in_set(crate::UpdateStages::WorldDraw));
+    }
+}
+
+fn spawn_minimap(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let image = images.add(Image::new_fill(
+        Extent3d { width: MINIMAP_PIXELS as u32, height: MINIMAP_PIXELS as u32, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        &UNKNOWN_REGION_COLOR,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    ));
+
+    commands.spawn((NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.),
+            top: Val::Px(10.),
+            padding: UiRect::all(Val::Px(4.)),
+            ..default()
+        },
+        background_color: PANEL_COLOR.into(),
+        // Under the other panels, the new world screen opens in the same corner
+        z_index: ZIndex::Global(-1),
+        ..default()
+    }, MinimapRoot)).with_children(|parent| {
+        parent.spawn((ImageBundle {
+            style: Style {
+                width: Val::Px(MINIMAP_DISPLAY_SIZE),
+                height: Val::Px(MINIMAP_DISPLAY_SIZE),
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            image: UiImage::new(image.clone()),
+            ..default()
+        }, Interaction::default(), RelativeCursorPosition::default())).with_children(|parent| {
+            parent.spawn((NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    border: UiRect::all(Val::Px(1.)),
+                    ..default()
+                },
+                border_color: VIEWPORT_COLOR.into(),
+                ..default()
+            }, MinimapViewport));
+        });
+    });
+
+    commands.insert_resource(Minimap {
+        open: true,
+        image,
+        center: GridVec::new(0, 0),
+        // Draw on the first frame
+        refresh_timer: 0.,
+    });
+}
+
+fn toggle_minimap(
+    mut minimap: ResMut<Minimap>,
+    actions: Res<ActionState>,
+    mut root_query: Query<&mut Style, With<MinimapRoot>>,
+) {
+    if actions.just_pressed(Action::ToggleMinimap) {
+        minimap.open = !minimap.open;
+        minimap.refresh_timer = 0.;
+        root_query.single_mut().display = if minimap.open { Display::Flex } else { Display::None };
+    }
+}
+
+fn minimap_click(
+    minimap: Res<Minimap>,
+    mut capture_state: ResMut<PointerCaptureState>,
+    map_query: Query<(&Interaction, &RelativeCursorPosition)>,
+    mut camera_query: Query<&mut Transform, With<Camera>>,
+) {
+    if !minimap.open {
+        return;
+    }
+
+    for (interaction, cursor) in map_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        // Clicks on the map shouldn't also paint the world underneath
+        capture_state.click_consumed = true;
+
+        // Holding the button and dragging keeps the camera following the cursor
+        if let Some(normalized) = cursor.normalized {
+            let target = minimap.to_world(normalized.clamp(Vec2::ZERO, Vec2::ONE));
+            let mut camera_transform = camera_query.single_mut();
+            camera_transform.translation = target.extend(camera_transform.translation.z);
+        }
+    }
+}
+
+fn copy_thumbnail(data: &mut [u8], thumbnail: &[u8], slot: GridVec) {
+    let row_bytes = REGION_THUMBNAIL_SIZE * 4;
+    // Image rows run top down, so the top row of regions comes first
+    let left = slot.x as usize * REGION_THUMBNAIL_SIZE;
+    let top = (MINIMAP_REGIONS - 1 - slot.y) as usize * REGION_THUMBNAIL_SIZE;
+    for row in 0..REGION_THUMBNAIL_SIZE {
+        let start = ((top + row) * MINIMAP_PIXELS + left) * 4;
+        data[start..start + row_bytes].copy_from_slice(&thumbnail[row * row_bytes..(row + 1) * row_bytes]);
+    }
+}
+
+// Outline a thumbnail in the color for its region's load state
+fn outline_region(pixels: &mut [u8], color: Color) {
+    let [r, g, b, _] = color.as_rgba_u8();
+    let last = REGION_THUMBNAIL_SIZE - 1;
+    for y in 0..REGION_THUMBNAIL_SIZE {
+        for x in 0..REGION_THUMBNAIL_SIZE {
+            if x == 0 || y == 0 || x == last || y == last {
+                let index = (y * REGION_THUMBNAIL_SIZE + x) * 4;
+                pixels[index..index + 4].copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+    }
+}
+
+fn draw_minimap(
+    mut minimap: ResMut<Minimap>,
+    world: Res<Sandworld>,
+    time: Res<Time>,
+    mut images: ResMut<Assets<Image>>,
+    camera_query: Query<&GlobalTransform, With<Camera>>,
+) {
+    if !minimap.open {
+        return;
+    }
+
+    let camera_pos = camera_query.single().translation().truncate();
+    let center = GridVec::new(
+        (camera_pos.x / TRUE_REGION_SIZE as f32).floor() as i32,
+        (camera_pos.y / TRUE_REGION_SIZE as f32).floor() as i32,
+    );
+
+    minimap.refresh_timer -= time.delta_seconds();
+    if minimap.refresh_timer > 0. && center == minimap.center {
+        return;
+    }
+    minimap.refresh_timer = MINIMAP_REFRESH_SECONDS;
+    minimap.center = center;
+
+    let Some(image) = images.get_mut(&minimap.image) else {
+        return;
+    };
+
+    let statuses = world.world.get_region_statuses();
+    let half = MINIMAP_REGIONS / 2;
+    for y in 0..MINIMAP_REGIONS {
+        for x in 0..MINIMAP_REGIONS {
+            let slot = GridVec::new(x, y);
+            let regpos = center + slot - GridVec::new(half, half);
+            let state = statuses.iter().find(|status| status.position == regpos).map(|status| status.state);
+
+            let mut pixels = match world.world.get_region_thumbnail(regpos) {
+                Some(thumbnail) => thumbnail,
+                None => UNKNOWN_REGION_COLOR.repeat(REGION_THUMBNAIL_SIZE * REGION_THUMBNAIL_SIZE),
+            };
+            if let Some(state) = state {
+                outline_region(&mut pixels, get_region_state_color(state));
+            }
+            copy_thumbnail(&mut image.data, &pixels, slot);
+        }
+    }
+}
+
+fn update_minimap_viewport(
+    minimap: Res<Minimap>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut viewport_query: Query<&mut Style, With<MinimapViewport>>,
+) {
+    if !minimap.open {
+        return;
+    }
+
+    let (camera, camera_transform) = camera_query.single();
+    let bounds = cam_bounds(camera, camera_transform);
+    let span = (MINIMAP_REGIONS as usize * TRUE_REGION_SIZE) as f32;
+    let origin = minimap.origin();
+
+    let bottom_left = bounds.bottom_left();
+    let top_right = bounds.top_right();
+    let left = (bottom_left.x as f32 - origin.x) / span;
+    let top = 1. - (top_right.y as f32 - origin.y) / span;
+
+    let mut style = viewport_query.single_mut();
+    style.left = Val::Percent(left * 100.);
+    style.top = Val::Percent(top * 100.);
+    style.width = Val::Percent(bounds.width() as f32 / span * 100.);
+    style.height = Val::Percent(bounds.height() as f32 / span * 100.);
+}
@@ -0,0 +1,335 @@
+use bevy::{prelude::*, render::{render_asset::RenderAssetUsages, render_resource::{Extent3d, TextureDimension, TextureFormat}}, ui::RelativeCursorPosition};
+use gridmath::GridVec;
+use rayon::prelude::*;
+
+use crate::{
+    input_map::{Action, ActionState},
+    sandsim::RecreateWorld,
+    ui::{spawn_button, text_style, PointerCaptureState, HOVERED_BUTTON, NORMAL_BUTTON, PANEL_COLOR, PRESSED_BUTTON},
+    worldgen_settings::{WorldGenParam, WorldGenSettings},
+};
+
+pub struct NewWorldPlugin;
+
+const PREVIEW_WIDTH: usize = 256;
+const PREVIEW_HEIGHT: usize = 128;
+// World cells covered by each preview pixel
+const PREVIEW_SCALE: i32 = 48;
+
+const SLIDER_TRACK: Color = Color::rgb(0.2, 0.2, 0.2);
+const SLIDER_FILL: Color = Color::rgb(0.35, 0.55, 0.75);
+
+/*
+    Screen for rolling a new world, opened with F1
+    Edits a copy of the current WorldGenSettings, showing a low resolution preview sampled straight from the generator,
+    and only replaces the world when Create is pressed
+*/
+#[derive(Resource)]
+struct NewWorldScreen {
+    open: bool,
+    draft: WorldGenSettings,
+    preview: Handle<Image>,
+    preview_stale: bool,
+}
+
+#[derive(Component)]
+struct NewWorldRoot;
+
+#[derive(Component, Clone, Copy, PartialEq)]
+enum NewWorldButton {
+    CycleGenerator,
+    RerollSeed,
+    Create,
+    Cancel,
+}
+
+#[derive(Component)]
+struct ParamRow(WorldGenParam);
+
+#[derive(Component)]
+struct ParamSlider(WorldGenParam);
+
+#[derive(Component)]
+struct ParamSliderFill(WorldGenParam);
+
+#[derive(Component)]
+struct ParamLabel(WorldGenParam);
+
+#[derive(Component)]
+struct SeedLabel;
+
+impl Plugin for NewWorldPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_new_world_screen)
+            .add_systems(
+                Update,
+                (toggle_new_world_screen, new_world_buttons, drag_param_sliders, refresh_new_world_screen, update_preview)
+                    .chain()
+                    .in_set(crate::UpdateStages::UI)
+                    .after(crate::ui::button_system)
+                    .before(crate::UpdateStages::Input),
+            );
+    }
+}
+
+fn setup_new_world_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    settings: Res<WorldGenSettings>,
+) {
+    let preview = images.add(Image::new_fill(
+        Extent3d { width: PREVIEW_WIDTH as u32, height: PREVIEW_HEIGHT as u32, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    ));
+
+    commands.spawn((NodeBundle {
+        style: Style {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.),
+            top: Val::Px(10.),
+            padding: UiRect::all(Val::Px(12.)),
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        background_color: PANEL_COLOR.into(),
+        ..default()
+    }, NewWorldRoot, Interaction::default())).with_children(|parent| {
+        parent.spawn(TextBundle::from_section("New World", text_style(&asset_server, 30.)));
+
+        parent.spawn(NodeBundle {
+            style: Style { align_items: AlignItems::Center, ..default() },
+            ..default()
+        }).with_children(|parent| {
+            spawn_button(parent, &asset_server, "", NewWorldButton::CycleGenerator);
+            parent.spawn((TextBundle::from_section("", text_style(&asset_server, 20.)), SeedLabel));
+            spawn_button(parent, &asset_server, "Reroll", NewWorldButton::RerollSeed);
+        });
+
+        for param in WorldGenParam::ALL {
+            let (min, max) = param.range();
+            parent.spawn((NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    margin: UiRect::vertical(Val::Px(4.)),
+                    ..default()
+                },
+                ..default()
+            }, ParamRow(param))).with_children(|parent| {
+                parent.spawn((TextBundle::from_section(param.label(), text_style(&asset_server, 18.)), ParamLabel(param)));
+                parent.spawn((NodeBundle {
+                    style: Style {
+                        width: Val::Px(PREVIEW_WIDTH as f32 * 1.5),
+                        height: Val::Px(14.),
+                        ..default()
+                    },
+                    background_color: SLIDER_TRACK.into(),
+                    ..default()
+                }, ParamSlider(param), Interaction::default(), RelativeCursorPosition::default())).with_children(|parent| {
+                    let fill = ((settings.get(param) - min) / (max - min)).clamp(0., 1.);
+                    parent.spawn((NodeBundle {
+                        style: Style {
+                            width: Val::Percent(fill as f32 * 100.),
+                            height: Val::Percent(100.),
+                            ..default()
+                        },
+                        background_color: SLIDER_FILL.into(),
+                        ..default()
+                    }, ParamSliderFill(param)));
+                });
+            });
+        }
+
+        parent.spawn(ImageBundle {
+            style: Style {
+                width: Val::Px(PREVIEW_WIDTH as f32 * 1.5),
+                height: Val::Px(PREVIEW_HEIGHT as f32 * 1.5),
+                margin: UiRect::vertical(Val::Px(8.)),
+                ..default()
+            },
+            image: UiImage::new(preview.clone()),
+            ..default()
+        });
+
+        parent.spawn(NodeBundle::default()).with_children(|parent| {
+            spawn_button(parent, &asset_server, "Create", NewWorldButton::Create);
+            spawn_button(parent, &asset_server, "Cancel", NewWorldButton::Cancel);
+        });
+    });
+
+    commands.insert_resource(NewWorldScreen {
+        open: false,
+        draft: settings.clone(),
+        preview,
+        preview_stale: true,
+    });
+}
+
+fn toggle_new_world_screen(
+    mut screen: ResMut<NewWorldScreen>,
+    settings: Res<WorldGenSettings>,
+    actions: Res<ActionState>,
+) {
+    if actions.just_pressed(Action::NewWorld) {
+        screen.open = !screen.open;
+        if screen.open {
+            // Start from whatever the current world was made with
+            screen.draft = settings.clone();
+            screen.preview_stale = true;
+        }
+    }
+}
+
+// Any part of the panel that can be clicked
+type PanelFilter = Or<(With<NewWorldRoot>, With<NewWorldButton>, With<ParamSlider>)>;
+
+fn new_world_buttons(
+    mut screen: ResMut<NewWorldScreen>,
+    mut settings: ResMut<WorldGenSettings>,
+    mut recreate: EventWriter<RecreateWorld>,
+    mut capture_state: ResMut<PointerCaptureState>,
+    mut button_query: Query<(&Interaction, &mut BackgroundColor, &NewWorldButton), Changed<Interaction>>,
+    panel_query: Query<&Interaction, PanelFilter>,
+) {
+    if !screen.open {
+        return;
+    }
+
+    // Clicks on the panel shouldn't also paint the world underneath
+    if panel_query.iter().any(|interaction| *interaction == Interaction::Pressed) {
+        capture_state.click_consumed = true;
+    }
+
+    for (interaction, mut color, button) in button_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                match button {
+                    NewWorldButton::CycleGenerator => {
+                        screen.draft.generator = screen.draft.generator.cycle();
+                        screen.preview_stale = true;
+                    }
+                    NewWorldButton::RerollSeed => {
+                        screen.draft.seed = rand::random();
+                        screen.preview_stale = true;
+                    }
+                    NewWorldButton::Create => {
+                        *settings = screen.draft.clone();
+                        recreate.send(RecreateWorld);
+                        screen.open = false;
+                    }
+                    NewWorldButton::Cancel => {
+                        screen.open = false;
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn drag_param_sliders(
+    mut screen: ResMut<NewWorldScreen>,
+    slider_query: Query<(&Interaction, &RelativeCursorPosition, &ParamSlider)>,
+) {
+    if !screen.open {
+        return;
+    }
+
+    for (interaction, cursor, slider) in slider_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        // Cursor may be dragged past either end while held
+        if let Some(normalized) = cursor.normalized {
+            let (min, max) = slider.0.range();
+            let value = min + normalized.x.clamp(0., 1.) as f64 * (max - min);
+            if screen.draft.get(slider.0) != value {
+                screen.draft.set(slider.0, value);
+                screen.preview_stale = true;
+            }
+        }
+    }
+}
+
+type RootFilter = (With<NewWorldRoot>, Without<ParamRow>, Without<ParamSliderFill>);
+
+#[allow(clippy::too_many_arguments)]
+fn refresh_new_world_screen(
+    screen: Res<NewWorldScreen>,
+    mut root_query: Query<&mut Style, RootFilter>,
+    mut row_query: Query<(&mut Style, &ParamRow), Without<ParamSliderFill>>,
+    mut fill_query: Query<(&mut Style, &ParamSliderFill)>,
+    mut label_query: Query<(&mut Text, &ParamLabel)>,
+    generator_query: Query<(&NewWorldButton, &Children)>,
+    mut seed_query: Query<&mut Text, (With<SeedLabel>, Without<ParamLabel>)>,
+    mut text_query: Query<&mut Text, (Without<SeedLabel>, Without<ParamLabel>)>,
+) {
+    if !screen.is_changed() {
+        return;
+    }
+
+    let draft = &screen.draft;
+    root_query.single_mut().display = if screen.open { Display::Flex } else { Display::None };
+
+    let params = draft.generator.params();
+    for (mut style, row) in row_query.iter_mut() {
+        style.display = if params.contains(&row.0) { Display::Flex } else { Display::None };
+    }
+
+    for (mut style, fill) in fill_query.iter_mut() {
+        let (min, max) = fill.0.range();
+        style.width = Val::Percent(((draft.get(fill.0) - min) / (max - min)).clamp(0., 1.) as f32 * 100.);
+    }
+
+    for (mut text, label) in label_query.iter_mut() {
+        text.sections[0].value = format!("{}: {:.2}", label.0.label(), draft.get(label.0));
+    }
+
+    for (button, children) in generator_query.iter() {
+        if *button == NewWorldButton::CycleGenerator {
+            if let Ok(mut text) = text_query.get_mut(children[0]) {
+                text.sections[0].value = format!("Generator: {}", draft.generator.name());
+            }
+        }
+    }
+
+    seed_query.single_mut().sections[0].value = format!("Seed: {}", draft.seed);
+}
+
+// Resample the preview from a throwaway generator whenever the draft settings change
+fn update_preview(
+    mut screen: ResMut<NewWorldScreen>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !screen.open || !screen.preview_stale {
+        return;
+    }
+    screen.preview_stale = false;
+
+    let generator = screen.draft.build_generator();
+    let Some(image) = images.get_mut(&screen.preview) else {
+        return;
+    };
+
+    // Centered on where the camera starts, image rows run top down
+    let center = GridVec::new(sandworld::WORLD_WIDTH / 2, 0);
+    image.data.par_chunks_mut(PREVIEW_WIDTH * 4).enumerate().for_each(|(row, pixels)| {
+        let y = center.y + (PREVIEW_HEIGHT as i32 / 2 - row as i32) * PREVIEW_SCALE;
+        for (column, pixel) in pixels.chunks_mut(4).enumerate() {
+            let x = center.x + (column as i32 - PREVIEW_WIDTH as i32 / 2) * PREVIEW_SCALE;
+            let particle = generator.get_particle(GridVec::new(x, y));
+            pixel.copy_from_slice(&sandworld::get_color_for_type(particle.particle_type));
+        }
+    });
+}
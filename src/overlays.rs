@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+
+use bevy::{prelude::*, render::{render_asset::RenderAssetUsages, render_resource::{Extent3d, TextureDimension, TextureFormat}}};
+use bevy_xpbd_2d::prelude::*;
+use gridmath::{GridBounds, GridVec};
+use sandworld::{RegionState, CHUNK_SIZE, TRUE_REGION_SIZE};
+
+use crate::{
+    camera::cam_bounds,
+    chunk_display::{ChunkDisplay, DrawOptions},
+    sandsim::Sandworld,
+};
+
+pub struct OverlayPlugin;
+
+// Drawn above the world and ejecta, below the player
+const OVERLAY_Z: f32 = 0.2;
+// Temperature tiles rebuilt per frame, the rest catch up over the next few
+const MAX_TEMPERATURE_REDRAWS_PER_FRAME: usize = 32;
+// How quickly the update frequency overlay forgets old updates, per second
+const UPDATE_RATE_DECAY: f32 = 2.;
+// Chunk updates per second drawn at full strength
+const FULL_UPDATE_RATE: f32 = 30.;
+// Region staleness drawn at full strength
+const FULL_STALENESS: f32 = 12.;
+
+const COLLIDER_COLOR: Color = Color::rgb(0.2, 1., 0.4);
+
+/*
+    Debug views drawn over the world, cycled with F11
+    Collider outlines are separate (DrawOptions::show_colliders) so they can be shown over any of these
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugOverlay {
+    None,
+    // Local temperature of each cell, as the simulation sees it
+    Temperature,
+    // How often each chunk has been updated recently
+    UpdateFrequency,
+    // Staleness as color and update priority as strength, for loaded regions
+    RegionPriority,
+    // Loaded, loading, compressed and compressing regions
+    RegionLoadState,
+}
+
+impl DebugOverlay {
+    pub const ALL: [DebugOverlay; 5] = [
+        DebugOverlay::None,
+        DebugOverlay::Temperature,
+        DebugOverlay::UpdateFrequency,
+        DebugOverlay::RegionPriority,
+        DebugOverlay::RegionLoadState,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugOverlay::None => "None",
+            DebugOverlay::Temperature => "Temperature",
+            DebugOverlay::UpdateFrequency => "Chunk update frequency",
+            DebugOverlay::RegionPriority => "Region staleness and priority",
+            DebugOverlay::RegionLoadState => "Region load state",
+        }
+    }
+
+    // The next overlay in ALL, wrapping around
+    pub fn cycle(&self) -> DebugOverlay {
+        let index = DebugOverlay::ALL.iter().position(|overlay| overlay == self).unwrap_or(0);
+        DebugOverlay::ALL[(index + 1) % DebugOverlay::ALL.len()]
+    }
+}
+
+// Recent updates per second for each chunk, decaying so idle chunks fade out
+#[derive(Resource, Default)]
+struct ChunkUpdateRates {
+    rates: HashMap<GridVec, f32>,
+}
+
+// Plain colored quads for the chunk and region overlays, reused from frame to frame
+#[derive(Resource, Default)]
+struct OverlaySprites {
+    entities: Vec<Entity>,
+}
+
+// A heatmap sprite for each visible chunk while the temperature overlay is on
+#[derive(Resource, Default)]
+struct TemperatureTiles {
+    tiles: HashMap<GridVec, (Entity, Handle<Image>)>,
+}
+
+#[derive(Component)]
+struct OverlayLabel;
+
+impl Plugin for OverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkUpdateRates>()
+            .init_resource::<OverlaySprites>()
+            .init_resource::<TemperatureTiles>()
+            .add_systems(Startup, spawn_overlay_label)
+            .add_systems(Update, track_update_rates.in_set(crate::UpdateStages::WorldUpdate).after(crate::sandsim::sand_update))
+            .add_systems(
+                Update,
+                (draw_area_overlays, draw_temperature_overlay, draw_collider_outlines, update_overlay_label)
+                    .in_set(crate::UpdateStages::WorldDraw),
+            );
+    }
+}
+
+fn get_visible_chunks(camera: &Camera, camera_transform: &GlobalTransform) -> GridBounds {
+    let bounds = cam_bounds(camera, camera_transform);
+    GridBounds::new_from_extents(
+        sandworld::World::get_chunkpos(&bounds.bottom_left()),
+        sandworld::World::get_chunkpos(&bounds.top_right()),
+    )
+}
+
+fn get_chunk_center(chunk_pos: GridVec) -> Vec2 {
+    (Vec2::new(chunk_pos.x as f32, chunk_pos.y as f32) + 0.5) * CHUNK_SIZE as f32
+}
+
+fn track_update_rates(
+    world: Res<Sandworld>,
+    mut rates: ResMut<ChunkUpdateRates>,
+    time: Res<Time>,
+) {
+    // Each update adds the decay rate, so a chunk updated every frame settles at its updates per second
+    let keep = (-UPDATE_RATE_DECAY * time.delta_seconds()).exp();
+    rates.rates.retain(|_, rate| {
+        *rate *= keep;
+        *rate > 0.01
+    });
+    for chunk_pos in world.world.get_updated_chunks() {
+        *rates.rates.entry(chunk_pos).or_insert(0.) += UPDATE_RATE_DECAY;
+    }
+}
+
+pub(crate) fn get_region_state_color(state: RegionState) -> Color {
+    match state {
+        RegionState::Loaded => Color::rgba(0.2, 0.8, 0.3, 0.25),
+        RegionState::Loading => Color::rgba(0.9, 0.8, 0.2, 0.4),
+        RegionState::Compressed => Color::rgba(0.3, 0.4, 0.9, 0.4),
+        RegionState::Compressing => Color::rgba(0.8, 0.3, 0.9, 0.4),
+    }
+}
+
+// Chunk and region sized overlays are each a flat colored quad per area
+fn draw_area_overlays(
+    world: Res<Sandworld>,
+    draw_options: Res<DrawOptions>,
+    rates: Res<ChunkUpdateRates>,
+    cam_query: Query<(&Camera, &GlobalTransform)>,
+    mut sprites: ResMut<OverlaySprites>,
+    mut sprite_query: Query<(&mut Transform, &mut Sprite, &mut Visibility)>,
+    mut commands: Commands,
+) {
+    let (camera, camera_transform) = cam_query.single();
+    let visible_chunks = get_visible_chunks(camera, camera_transform);
+    let region_size = Vec2::splat(TRUE_REGION_SIZE as f32);
+
+    let mut quads: Vec<(Vec2, Vec2, Color)> = Vec::new();
+    match draw_options.overlay {
+        DebugOverlay::UpdateFrequency => {
+            for (chunk_pos, rate) in rates.rates.iter() {
+                if visible_chunks.contains(*chunk_pos) {
+                    let strength = (rate / FULL_UPDATE_RATE).min(1.);
+                    quads.push((get_chunk_center(*chunk_pos), Vec2::splat(CHUNK_SIZE as f32), Color::rgba(1., 0.25, 0.1, strength * 0.6)));
+                }
+            }
+        }
+        DebugOverlay::RegionPriority => {
+            let statuses = world.world.get_region_statuses();
+            let max_priority = statuses.iter().map(|status| status.update_priority).max().unwrap_or(0).max(1);
+            for status in statuses.iter().filter(|status| status.state == RegionState::Loaded) {
+                let stale = (status.staleness as f32 / FULL_STALENESS).min(1.);
+                let priority = status.update_priority as f32 / max_priority as f32;
+                let center = (Vec2::new(status.position.x as f32, status.position.y as f32) + 0.5) * region_size;
+                quads.push((center, region_size, Color::rgba(stale, 1. - stale, 0.2, 0.1 + priority * 0.5)));
+            }
+        }
+        DebugOverlay::RegionLoadState => {
+            for status in world.world.get_region_statuses() {
+                let center = (Vec2::new(status.position.x as f32, status.position.y as f32) + 0.5) * region_size;
+                quads.push((center, region_size, get_region_state_color(status.state)));
+            }
+        }
+        DebugOverlay::None | DebugOverlay::Temperature => {}
+    }
+
+    // New sprites show up next frame, like ejecta sprites
+    while sprites.entities.len() < quads.len() {
+        let entity = commands.spawn(SpriteBundle {
+            visibility: Visibility::Hidden,
+            ..default()
+        }).id();
+        sprites.entities.push(entity);
+    }
+
+    for (index, entity) in sprites.entities.iter().enumerate() {
+        let Ok((mut transform, mut sprite, mut visibility)) = sprite_query.get_mut(*entity) else {
+            continue;
+        };
+
+        match quads.get(index) {
+            Some((center, size, color)) => {
+                *transform = Transform::from_translation(center.extend(OVERLAY_Z));
+                sprite.custom_size = Some(*size);
+                sprite.color = *color;
+                *visibility = Visibility::Visible;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+fn create_temperature_image(data: Vec<u8>) -> Image {
+    let mut image = Image::new_fill(
+        Extent3d { width: CHUNK_SIZE as u32, height: CHUNK_SIZE as u32, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.data = data;
+    image
+}
+
+// Tiles are rebuilt when their chunk updates, and dropped once off screen or when the overlay is turned off
+fn draw_temperature_overlay(
+    world: Res<Sandworld>,
+    draw_options: Res<DrawOptions>,
+    cam_query: Query<(&Camera, &GlobalTransform)>,
+    mut tiles: ResMut<TemperatureTiles>,
+    mut images: ResMut<Assets<Image>>,
+    mut commands: Commands,
+) {
+    if draw_options.overlay != DebugOverlay::Temperature {
+        for (_, (entity, _)) in tiles.tiles.drain() {
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+
+    let (camera, camera_transform) = cam_query.single();
+    let visible_chunks = get_visible_chunks(camera, camera_transform);
+
+    tiles.tiles.retain(|chunk_pos, (entity, _)| {
+        let keep = visible_chunks.contains(*chunk_pos);
+        if !keep {
+            commands.entity(*entity).despawn();
+        }
+        keep
+    });
+
+    let updated_chunks = world.world.get_updated_chunks();
+    let mut redraws = 0;
+    for chunk_pos in visible_chunks.iter() {
+        if redraws >= MAX_TEMPERATURE_REDRAWS_PER_FRAME {
+            break;
+        }
+        let Some(chunk) = world.world.get_chunk(&chunk_pos) else {
+            continue;
+        };
+
+        match tiles.tiles.get(&chunk_pos) {
+            Some((_, image)) => {
+                if updated_chunks.contains(&chunk_pos) || draw_options.force_redraw_all {
+                    if let Some(image) = images.get_mut(image) {
+                        image.data = chunk.render_temperature_array();
+                        redraws += 1;
+                    }
+                }
+            }
+            None => {
+                let image = images.add(create_temperature_image(chunk.render_temperature_array()));
+                let entity = commands.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::splat(CHUNK_SIZE as f32)),
+                        ..default()
+                    },
+                    texture: image.clone(),
+                    transform: Transform::from_translation(get_chunk_center(chunk_pos).extend(OVERLAY_Z)),
+                    ..default()
+                }).id();
+                tiles.tiles.insert(chunk_pos, (entity, image));
+                redraws += 1;
+            }
+        }
+    }
+}
+
+fn draw_collider_outlines(
+    draw_options: Res<DrawOptions>,
+    collider_query: Query<(&ChunkDisplay, &Collider, &GlobalTransform)>,
+    mut gizmos: Gizmos,
+) {
+    if !draw_options.show_colliders {
+        return;
+    }
+
+    for (chunk_display, collider, transform) in collider_query.iter() {
+        if chunk_display.chunk_pos.is_none() {
+            continue;
+        }
+        let Some(polyline) = collider.shape().as_polyline() else {
+            continue;
+        };
+
+        for segment in polyline.segments() {
+            let start = transform.transform_point(Vec3::new(segment.a.x, segment.a.y, 0.)).truncate();
+            let end = transform.transform_point(Vec3::new(segment.b.x, segment.b.y, 0.)).truncate();
+            gizmos.line_2d(start, end, COLLIDER_COLOR);
+        }
+    }
+}
+
+fn spawn_overlay_label(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section("", TextStyle {
+            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+            font_size: 20.0,
+            color: Color::rgb(0.9, 0.9, 0.6),
+        })
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            left: Val::Percent(40.),
+            top: Val::Px(40.),
+            ..default()
+        }),
+        OverlayLabel,
+    ));
+}
+
+fn update_overlay_label(
+    draw_options: Res<DrawOptions>,
+    mut text_query: Query<&mut Text, With<OverlayLabel>>,
+) {
+    if !draw_options.is_changed() {
+        return;
+    }
+
+    text_query.single_mut().sections[0].value = match draw_options.overlay {
+        DebugOverlay::None => String::new(),
+        overlay => format!("Overlay: {}", overlay.name()),
+    };
+}
@@ -0,0 +1,106 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{prelude::*, render::{render_asset::RenderAssetUsages, render_resource::{Extent3d, TextureDimension, TextureFormat}}, sprite::Anchor};
+use sandworld::{BodyEvent, BodyId, ParticleBody};
+
+use crate::sandsim::Sandworld;
+
+pub struct ParticleBodyPlugin;
+
+// Sprite showing a rigid body lifted out of the grid, see sandworld::ParticleBody
+#[derive(Component)]
+pub struct BodyDisplay {
+    pub body: BodyId,
+}
+
+#[derive(Resource, Default)]
+struct BodyDisplays {
+    entities: HashMap<BodyId, Entity>,
+}
+
+impl Plugin for ParticleBodyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BodyDisplays>()
+            .add_systems(Update, update_particle_bodies.in_set(crate::UpdateStages::WorldUpdate).after(crate::sandsim::sand_update))
+            .add_systems(Update, sync_body_displays.in_set(crate::UpdateStages::WorldDraw));
+    }
+}
+
+fn update_particle_bodies(
+    mut world: ResMut<Sandworld>,
+    mut displays: ResMut<BodyDisplays>,
+    mut commands: Commands,
+    time: Res<Time>,
+) {
+    world.world.update_bodies(time.delta_seconds());
+
+    // Settled and shattered bodies are back in the grid, so stop drawing them separately
+    for event in world.world.take_body_events() {
+        let (BodyEvent::Settled(id) | BodyEvent::Shattered(id)) = event;
+        if let Some(entity) = displays.entities.remove(&id) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn sync_body_displays(
+    world: Res<Sandworld>,
+    mut displays: ResMut<BodyDisplays>,
+    mut display_query: Query<(Entity, &BodyDisplay, &mut Transform)>,
+    mut images: ResMut<Assets<Image>>,
+    mut commands: Commands,
+) {
+    let live_bodies: HashSet<BodyId> = world.world.get_bodies().iter().map(|body| body.id()).collect();
+
+    for body in world.world.get_bodies() {
+        let transform = body_transform(body);
+
+        match displays.entities.get(&body.id()).and_then(|entity| display_query.get_mut(*entity).ok()) {
+            Some((_, _, mut current)) => *current = transform,
+            None => {
+                let entity = commands.spawn((SpriteBundle {
+                    sprite: Sprite { anchor: body_anchor(body), ..default() },
+                    texture: images.add(create_body_image(body)),
+                    transform,
+                    ..default()
+                }, BodyDisplay { body: body.id() })).id();
+                displays.entities.insert(body.id(), entity);
+            }
+        }
+    }
+
+    // Bodies can also disappear along with their world
+    for (entity, display, _) in display_query.iter() {
+        if !live_bodies.contains(&display.body) {
+            displays.entities.remove(&display.body);
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn body_transform(body: &ParticleBody) -> Transform {
+    Transform::from_xyz(body.position[0], body.position[1], 0.2)
+        .with_rotation(Quat::from_rotation_z(body.rotation))
+}
+
+// Sprites turn about their anchor, which needs to be the body's center of mass
+fn body_anchor(body: &ParticleBody) -> Anchor {
+    let size = body.get_size();
+    let center = body.get_center_of_mass();
+    Anchor::Custom(Vec2::new(center[0] / size.x as f32 - 0.5, center[1] / size.y as f32 - 0.5))
+}
+
+fn create_body_image(body: &ParticleBody) -> Image {
+    let size = body.get_size();
+    Image::new(
+        Extent3d {
+            width: size.x as u32,
+            height: size.y as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        body.render_to_color_array(),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
@@ -0,0 +1,390 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_xpbd_2d::prelude::*;
+use gridmath::{GridBounds, GridVec};
+use sandworld::{particle_set, Particle, ParticleSet, ParticleType};
+
+use crate::{
+    camera::CameraTarget,
+    input_map::{Action, ActionState},
+    chunk_colliders::ColliderLayer,
+    sandsim::Sandworld,
+    tools::to_gridpos,
+    ui::PointerCaptureState,
+};
+
+pub struct PlayerPlugin;
+
+const PLAYER_HEIGHT: f32 = 12.;
+const PLAYER_RADIUS: f32 = 3.;
+const PLAYER_COLOR: Color = Color::rgb(0.95, 0.45, 0.3);
+
+const WALK_SPEED: f32 = 60.;
+const WALK_ACCELERATION: f32 = 600.;
+const AIR_CONTROL: f32 = 0.4;
+const JUMP_SPEED: f32 = 90.;
+const SWIM_SPEED: f32 = 40.;
+// Fraction of the player's cells that have to be Water before they swim rather than walk
+const SWIM_THRESHOLD: f32 = 0.3;
+const SWIM_GRAVITY_SCALE: f32 = 0.2;
+const WATER_DAMPING: f32 = 3.;
+
+const MAX_HEALTH: f32 = 100.;
+// Damage per second when fully submerged in Lava, scaled by how much of the player is in it
+const LAVA_DAMAGE: f32 = 120.;
+const HEAT_DAMAGE_TYPES: ParticleSet = particle_set![ParticleType::Lava, ParticleType::MoltenGlass];
+
+const DIG_RADIUS: i32 = 4;
+// How far from the player the tool reaches, in cells
+const TOOL_REACH: f32 = 48.;
+// Seconds between each dig or place while the mouse is held
+const TOOL_INTERVAL: f32 = 0.08;
+// Everything the tool can pick up
+const DIGGABLE: ParticleSet = ParticleSet::all().difference(particle_set![
+    ParticleType::Air,
+    ParticleType::LaserBeam,
+    ParticleType::Boundary,
+    ParticleType::RegionBoundary,
+    ParticleType::Dirty
+]);
+
+/*
+    Particles collected by digging, counted per type
+    Indexed by particle type like BehaviorRegistry
+*/
+#[derive(Debug, Clone)]
+pub struct Inventory {
+    counts: [u32; ParticleType::ALL.len()],
+    pub selected: ParticleType,
+}
+
+#[derive(Component)]
+pub struct Player {
+    pub health: f32,
+    pub inventory: Inventory,
+    pub spawn_point: Vec2,
+    swimming: bool,
+    tool_cooldown: f32,
+    // Set when the world is recreated, the player is held in place until the new world loads under them
+    respawn_pending: bool,
+}
+
+#[derive(Component)]
+struct PlayerReadout;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_player_readout)
+            .add_systems(Update, toggle_player.in_set(crate::UpdateStages::Input))
+            .add_systems(
+                Update,
+                (player_environment, player_movement, player_tool)
+                    .chain()
+                    .in_set(crate::UpdateStages::Input)
+                    .after(toggle_player)
+                    .before(crate::tools::world_interact),
+            )
+            .add_systems(Update, update_player_readout.in_set(crate::UpdateStages::UI).after(crate::UpdateStages::WorldUpdate));
+    }
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Inventory {
+            counts: [0; ParticleType::ALL.len()],
+            selected: ParticleType::Sand,
+        }
+    }
+
+    pub fn count(&self, particle_type: ParticleType) -> u32 {
+        self.counts[particle_type as usize]
+    }
+
+    pub fn add(&mut self, particle_type: ParticleType) {
+        self.counts[particle_type as usize] += 1;
+    }
+
+    // Returns false if there are none left
+    pub fn take(&mut self, particle_type: ParticleType) -> bool {
+        let count = &mut self.counts[particle_type as usize];
+        if *count == 0 {
+            return false;
+        }
+        *count -= 1;
+        true
+    }
+
+    // Step through the types that are held, wrapping around
+    pub fn cycle_selected(&mut self, forward: bool) {
+        let held: Vec<ParticleType> = ParticleType::ALL.into_iter().filter(|t| self.count(*t) > 0).collect();
+        if held.is_empty() {
+            return;
+        }
+
+        let current = held.iter().position(|t| *t == self.selected);
+        let next = match (current, forward) {
+            (Some(index), true) => (index + 1) % held.len(),
+            (Some(index), false) => (index + held.len() - 1) % held.len(),
+            (None, _) => 0,
+        };
+        self.selected = held[next];
+    }
+}
+
+impl Player {
+    // Move to open space in a new world once it has loaded, see player_environment
+    pub fn respawn_in_new_world(&mut self) {
+        self.health = MAX_HEALTH;
+        self.respawn_pending = true;
+    }
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn player_bounds(position: Vec2) -> GridBounds {
+    GridBounds::new_from_extents(
+        to_gridpos(position - Vec2::new(PLAYER_RADIUS, PLAYER_HEIGHT / 2.)),
+        to_gridpos(position + Vec2::new(PLAYER_RADIUS, PLAYER_HEIGHT / 2.)),
+    )
+}
+
+// Walk up from a point until the player would fit
+fn find_open_space(world: &sandworld::World, start: Vec2) -> Vec2 {
+    let mut position = start;
+    for _ in 0..512 {
+        if world.count_matches_in_bounds(player_bounds(position), crate::chunk_colliders::COLLIDES) == 0 {
+            break;
+        }
+        position.y += PLAYER_HEIGHT / 2.;
+    }
+    position
+}
+
+// P spawns a player at the middle of the screen, or removes it if there already is one
+fn toggle_player(
+    actions: Res<ActionState>,
+    world: Res<Sandworld>,
+    player_query: Query<Entity, With<Player>>,
+    cam_query: Query<&Transform, With<Camera>>,
+    mut commands: Commands,
+) {
+    if !actions.just_pressed(Action::TogglePlayer) {
+        return;
+    }
+
+    if let Ok(entity) = player_query.get_single() {
+        commands.entity(entity).despawn_recursive();
+        return;
+    }
+
+    let spawn_point = find_open_space(&world.world, cam_query.single().translation.truncate());
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: PLAYER_COLOR,
+                custom_size: Some(Vec2::new(PLAYER_RADIUS * 2., PLAYER_HEIGHT)),
+                ..default()
+            },
+            transform: Transform::from_translation(spawn_point.extend(0.3)),
+            ..default()
+        },
+        Player {
+            health: MAX_HEALTH,
+            inventory: Inventory::new(),
+            spawn_point,
+            swimming: false,
+            tool_cooldown: 0.,
+            respawn_pending: false,
+        },
+        CameraTarget,
+        RigidBody::Dynamic,
+        Collider::capsule(PLAYER_HEIGHT - PLAYER_RADIUS * 2., PLAYER_RADIUS),
+        LockedAxes::ROTATION_LOCKED,
+        Friction::new(0.).with_combine_rule(CoefficientCombine::Min),
+        GravityScale(1.),
+        LinearDamping(0.),
+        CollisionLayers::new(ColliderLayer::Player, [ColliderLayer::Terrain, ColliderLayer::Projectile]),
+        ShapeCaster::new(Collider::circle(PLAYER_RADIUS * 0.9), Vec2::ZERO, 0., Direction2d::NEG_Y)
+            .with_max_time_of_impact(PLAYER_HEIGHT / 2. - PLAYER_RADIUS + 1.)
+            .with_query_filter(SpatialQueryFilter::from_mask(ColliderLayer::Terrain)),
+    ));
+}
+
+// Swimming, lava damage and respawning, from the particles the player overlaps
+fn player_environment(
+    world: Res<Sandworld>,
+    mut player_query: Query<(&mut Player, &mut Transform, &mut LinearVelocity, &mut GravityScale, &mut LinearDamping)>,
+    time: Res<Time>,
+) {
+    let Ok((mut player, mut transform, mut velocity, mut gravity, mut damping)) = player_query.get_single_mut() else {
+        return;
+    };
+
+    if player.respawn_pending {
+        velocity.0 = Vec2::ZERO;
+        gravity.0 = 0.;
+        let position = transform.translation.truncate();
+        if !world.world.contains(to_gridpos(position)) {
+            return;
+        }
+
+        let respawn = find_open_space(&world.world, position);
+        transform.translation = respawn.extend(transform.translation.z);
+        player.spawn_point = respawn;
+        player.respawn_pending = false;
+    }
+
+    let bounds = player_bounds(transform.translation.truncate());
+    let area = bounds.area() as f32;
+    let water = world.world.count_matches_in_bounds(bounds, particle_set![ParticleType::Water]) as f32 / area;
+    let heat = world.world.count_matches_in_bounds(bounds, HEAT_DAMAGE_TYPES) as f32 / area;
+
+    player.swimming = water > SWIM_THRESHOLD;
+    gravity.0 = if player.swimming { SWIM_GRAVITY_SCALE } else { 1. };
+    damping.0 = if player.swimming { WATER_DAMPING } else { 0. };
+
+    player.health -= heat * LAVA_DAMAGE * time.delta_seconds();
+    if player.health <= 0. {
+        // Back to where they started, dropping everything they were carrying
+        player.health = MAX_HEALTH;
+        player.inventory = Inventory::new();
+        let respawn = find_open_space(&world.world, player.spawn_point);
+        transform.translation = respawn.extend(transform.translation.z);
+        velocity.0 = Vec2::ZERO;
+    }
+}
+
+fn player_movement(
+    mut player_query: Query<(&Player, &mut LinearVelocity, &ShapeHits)>,
+    actions: Res<ActionState>,
+    time: Res<Time>,
+) {
+    let Ok((player, mut velocity, ground_hits)) = player_query.get_single_mut() else {
+        return;
+    };
+
+    let grounded = !ground_hits.is_empty();
+    let direction = actions.value(Action::PlayerRight) - actions.value(Action::PlayerLeft);
+
+    let control = if grounded || player.swimming { 1. } else { AIR_CONTROL };
+    let max_change = WALK_ACCELERATION * control * time.delta_seconds();
+    velocity.x += (direction * WALK_SPEED - velocity.x).clamp(-max_change, max_change);
+
+    let jump = actions.pressed(Action::PlayerJump);
+    if player.swimming {
+        if jump {
+            velocity.y = velocity.y.max(SWIM_SPEED);
+        }
+        else if actions.pressed(Action::PlayerDown) {
+            velocity.y = velocity.y.min(-SWIM_SPEED);
+        }
+    }
+    else if jump && grounded && velocity.y <= 0. {
+        velocity.y = JUMP_SPEED;
+    }
+}
+
+/*
+    Left mouse digs particles into the inventory, right mouse places the selected type back
+    Q and E pick which held type to place
+*/
+#[allow(clippy::too_many_arguments)]
+fn player_tool(
+    mut world: ResMut<Sandworld>,
+    mut player_query: Query<(&mut Player, &Transform)>,
+    mut capture_state: ResMut<PointerCaptureState>,
+    wnds: Query<&Window, With<PrimaryWindow>>,
+    q_cam: Query<(&Camera, &GlobalTransform)>,
+    actions: Res<ActionState>,
+    time: Res<Time>,
+) {
+    let Ok((mut player, transform)) = player_query.get_single_mut() else {
+        return;
+    };
+
+    if actions.just_pressed(Action::PrevItem) {
+        player.inventory.cycle_selected(false);
+    }
+    if actions.just_pressed(Action::NextItem) {
+        player.inventory.cycle_selected(true);
+    }
+
+    player.tool_cooldown -= time.delta_seconds();
+    if capture_state.click_consumed || !(actions.pressed(Action::PlayerDig) || actions.pressed(Action::PlayerPlace)) {
+        return;
+    }
+    // The player has the mouse now, keep the world brush out of it
+    capture_state.click_consumed = true;
+
+    let (camera, camera_transform) = q_cam.single();
+    let Some(cursor) = wnds.get_single().ok().and_then(|wnd| actions.cursor_position(wnd)) else {
+        return;
+    };
+    let Some(target) = camera.viewport_to_world_2d(camera_transform, cursor) else {
+        return;
+    };
+
+    if player.tool_cooldown > 0. || target.distance(transform.translation.truncate()) > TOOL_REACH {
+        return;
+    }
+    player.tool_cooldown = TOOL_INTERVAL;
+
+    let gridpos = to_gridpos(target);
+    if actions.pressed(Action::PlayerDig) {
+        for (particle_type, _) in world.world.extract_circle(gridpos, DIG_RADIUS, DIGGABLE) {
+            player.inventory.add(particle_type);
+        }
+    }
+    else {
+        let selected = player.inventory.selected;
+        let player_cells = player_bounds(transform.translation.truncate());
+        for pos in GridBounds::new(gridpos, GridVec::new(DIG_RADIUS, DIG_RADIUS)).iter() {
+            if gridpos.sq_distance(pos) >= DIG_RADIUS.pow(2) || player_cells.contains(pos) {
+                continue;
+            }
+            if world.world.get_particle(pos).particle_type == ParticleType::Air && player.inventory.take(selected) {
+                world.world.add_particle(pos, Particle::new(selected));
+            }
+        }
+    }
+}
+
+fn spawn_player_readout(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section("", TextStyle {
+            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+            font_size: 20.0,
+            color: Color::rgb(0.9, 0.9, 0.9),
+        })
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            left: Val::Percent(40.),
+            top: Val::Px(10.),
+            ..default()
+        }),
+        PlayerReadout,
+    ));
+}
+
+fn update_player_readout(
+    player_query: Query<&Player>,
+    mut text_query: Query<&mut Text, With<PlayerReadout>>,
+) {
+    let mut text = text_query.single_mut();
+    let Ok(player) = player_query.get_single() else {
+        text.sections[0].value.clear();
+        return;
+    };
+
+    let mut readout = format!("Health: {:.0}  Placing: {:?} ({})", player.health.max(0.), player.inventory.selected, player.inventory.count(player.inventory.selected));
+    for particle_type in ParticleType::ALL {
+        let count = player.inventory.count(particle_type);
+        if count > 0 {
+            readout += &format!("\n{:?}: {}", particle_type, count);
+        }
+    }
+    text.sections[0].value = readout;
+}
@@ -0,0 +1,406 @@
+use std::{
+    io::BufWriter,
+    path::PathBuf,
+    sync::mpsc::{sync_channel, SyncSender, TrySendError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use gridmath::{GridBounds, GridVec};
+use image::{codecs::gif::{GifEncoder, Repeat}, imageops::{self, FilterType}, Delay, Frame, RgbaImage};
+
+use crate::{
+    camera::cam_bounds,
+    chunk_display::DrawOptions,
+    input_map::{Action, ActionState},
+    sandsim::Sandworld,
+    worldgen_settings::SettingsError,
+};
+
+pub struct RecorderPlugin;
+
+pub const RECORDER_SETTINGS_PATH: &str = "assets/recorder.cfg";
+
+// Same as the clear color, so unloaded areas look the way they do in game
+const BACKGROUND: [u8; 3] = [10, 10, 10];
+
+// Frames waiting for the writer, beyond this capturing waits for it rather than piling frames up in memory
+const FRAME_QUEUE_LENGTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingFormat {
+    // Numbered PNG files in a folder
+    Png,
+    // A single looping animated GIF
+    Gif,
+}
+
+/*
+    Timelapse recorder settings, loaded from a `key = value` file like the world generation settings, eg
+
+        interval = 4
+        scale = 0.5
+        format = gif
+
+    Missing keys keep their defaults
+*/
+#[derive(Resource, Debug, Clone)]
+pub struct RecorderSettings {
+    // World updates between captured frames
+    pub interval: u32,
+    // Output pixels per cell, 0.5 halves the resolution
+    pub scale: f32,
+    pub format: RecordingFormat,
+    // Time each frame is shown for when a GIF plays back, in milliseconds
+    pub frame_delay: u32,
+    pub output_dir: String,
+}
+
+impl Default for RecorderSettings {
+    fn default() -> Self {
+        RecorderSettings {
+            interval: 4,
+            scale: 1.,
+            format: RecordingFormat::Gif,
+            frame_delay: 40,
+            output_dir: "recordings".to_string(),
+        }
+    }
+}
+
+impl RecorderSettings {
+    // Falls back to the defaults if the file is missing, other problems are reported
+    pub fn load(path: &str) -> Result<Self, SettingsError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(RecorderSettings::default()),
+            Err(err) => Err(SettingsError::Read(err)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, SettingsError> {
+        let mut settings = RecorderSettings::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(SettingsError::BadLine(line.to_string()));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let bad_value = || SettingsError::BadValue(line.to_string());
+
+            match key {
+                "interval" => settings.interval = value.parse::<u32>().map_err(|_| bad_value())?.max(1),
+                "scale" => {
+                    settings.scale = value.parse().map_err(|_| bad_value())?;
+                    if settings.scale <= 0. {
+                        return Err(bad_value());
+                    }
+                }
+                "format" => {
+                    settings.format = match value {
+                        "png" => RecordingFormat::Png,
+                        "gif" => RecordingFormat::Gif,
+                        _ => return Err(bad_value()),
+                    };
+                }
+                "frame_delay" => settings.frame_delay = value.parse().map_err(|_| bad_value())?,
+                "output" => settings.output_dir = value.to_string(),
+                _ => return Err(SettingsError::UnknownKey(key.to_string())),
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+/*
+    Captures the area around the camera every few world updates, toggled with R
+    Frames are composed on the CPU from the chunks' colors rather than read back from the GPU, so they show the
+    simulation without lighting, overlays or anything else drawn over it. Encoding runs on its own thread
+*/
+#[derive(Resource, Default)]
+struct Recorder {
+    active: Option<Recording>,
+}
+
+struct Recording {
+    // Cells captured each frame, fixed at the start so every frame is the same size
+    size: GridVec,
+    // World updates between captures, starting from the setting and doubled whenever the writer falls behind
+    interval: u32,
+    updates_since_capture: u64,
+    // The world's update count when it was last checked, the recorder is drawn once a frame however many updates ran
+    last_update_count: u64,
+    frames: u32,
+    path: PathBuf,
+    frame_sender: SyncSender<RgbaImage>,
+}
+
+#[derive(Component)]
+struct RecordingLabel;
+
+impl Plugin for RecorderPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = RecorderSettings::load(RECORDER_SETTINGS_PATH).unwrap_or_else(|err| {
+            println!("Error in {}: {}", RECORDER_SETTINGS_PATH, err);
+            RecorderSettings::default()
+        });
+
+        app.insert_resource(settings)
+            .init_resource::<Recorder>()
+            .add_systems(Startup, spawn_recording_label)
+            .add_systems(Update, toggle_recording.in_set(crate::UpdateStages::Input))
+            .add_systems(Update, (capture_frame, update_recording_label).chain().in_set(crate::UpdateStages::WorldDraw));
+    }
+}
+
+fn spawn_recording_label(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((TextBundle::from_section("", TextStyle {
+        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+        font_size: 20.0,
+        color: Color::rgb(1., 0.3, 0.3),
+    }).with_style(Style {
+        display: Display::None,
+        position_type: PositionType::Absolute,
+        right: Val::Px(10.),
+        bottom: Val::Px(10.),
+        ..default()
+    }), RecordingLabel));
+}
+
+// Start the thread that writes frames out as they arrive, it finishes once the sender is dropped
+fn start_writer(settings: &RecorderSettings) -> std::io::Result<(PathBuf, SyncSender<RgbaImage>)> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    let name = format!("timelapse-{}", timestamp);
+    let output_dir = PathBuf::from(&settings.output_dir);
+    std::fs::create_dir_all(&output_dir)?;
+
+    let (sender, receiver) = sync_channel::<RgbaImage>(FRAME_QUEUE_LENGTH);
+    let delay = Delay::from_numer_denom_ms(settings.frame_delay, 1);
+
+    let path = match settings.format {
+        RecordingFormat::Png => {
+            let folder = output_dir.join(name);
+            std::fs::create_dir_all(&folder)?;
+
+            let frames_folder = folder.clone();
+            std::thread::spawn(move || {
+                for (index, frame) in receiver.into_iter().enumerate() {
+                    if let Err(err) = frame.save(frames_folder.join(format!("frame_{:05}.png", index))) {
+                        println!("Failed to write timelapse frame: {}", err);
+                        return;
+                    }
+                }
+            });
+            folder
+        }
+        RecordingFormat::Gif => {
+            let path = output_dir.join(format!("{}.gif", name));
+            let file = BufWriter::new(std::fs::File::create(&path)?);
+
+            std::thread::spawn(move || {
+                // Speed trades color quality for encoding time, 10 keeps up with most recordings
+                let mut encoder = GifEncoder::new_with_speed(file, 10);
+                let result = encoder.set_repeat(Repeat::Infinite).and_then(|_| {
+                    receiver.into_iter().try_for_each(|frame| encoder.encode_frame(Frame::from_parts(frame, 0, 0, delay)))
+                });
+                if let Err(err) = result {
+                    println!("Failed to write timelapse: {}", err);
+                }
+            });
+            path
+        }
+    };
+
+    Ok((path, sender))
+}
+
+fn toggle_recording(
+    mut recorder: ResMut<Recorder>,
+    settings: Res<RecorderSettings>,
+    world: Res<Sandworld>,
+    actions: Res<ActionState>,
+    cam_query: Query<(&Camera, &GlobalTransform)>,
+) {
+    if !actions.just_pressed(Action::ToggleRecording) {
+        return;
+    }
+
+    // Dropping the recording closes its channel, which lets the writer finish the file
+    if let Some(recording) = recorder.active.take() {
+        println!("Saved {} timelapse frames to {} (every {} updates)", recording.frames, recording.path.display(), recording.interval);
+        return;
+    }
+
+    let (camera, cam_transform) = cam_query.single();
+    let view = cam_bounds(camera, cam_transform);
+
+    match start_writer(&settings) {
+        Ok((path, frame_sender)) => {
+            println!("Recording timelapse to {}", path.display());
+            recorder.active = Some(Recording {
+                size: GridVec::new(view.width() as i32, view.height() as i32),
+                interval: settings.interval,
+                // Capture straight away
+                updates_since_capture: settings.interval as u64,
+                last_update_count: world.world.update_count(),
+                frames: 0,
+                path,
+                frame_sender,
+            });
+        }
+        Err(err) => println!("Could not start recording: {}", err),
+    }
+}
+
+// Blend over the background, then resize to the configured scale
+fn finish_frame(mut colors: Vec<u8>, width: u32, height: u32, scale: f32) -> RgbaImage {
+    for pixel in colors.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u32;
+        for (channel, background) in pixel[..3].iter_mut().zip(BACKGROUND) {
+            *channel = ((*channel as u32 * alpha + background as u32 * (255 - alpha)) / 255) as u8;
+        }
+        pixel[3] = 255;
+    }
+
+    let image = RgbaImage::from_raw(width, height, colors).unwrap();
+    if scale == 1. {
+        return image;
+    }
+
+    let scaled_width = ((width as f32 * scale).round() as u32).max(1);
+    let scaled_height = ((height as f32 * scale).round() as u32).max(1);
+    // Averaging when shrinking, but keep cells as crisp squares when enlarging
+    let filter = if scale < 1. { FilterType::Triangle } else { FilterType::Nearest };
+    imageops::resize(&image, scaled_width, scaled_height, filter)
+}
+
+fn capture_frame(
+    mut recorder: ResMut<Recorder>,
+    settings: Res<RecorderSettings>,
+    world: Res<Sandworld>,
+    draw_options: Res<DrawOptions>,
+    cam_query: Query<(&Camera, &GlobalTransform)>,
+) {
+    let Some(recording) = recorder.active.as_mut() else {
+        return;
+    };
+
+    // A recreated world starts counting again from zero
+    let update_count = world.world.update_count();
+    let new_updates = update_count.saturating_sub(recording.last_update_count);
+    recording.last_update_count = update_count;
+
+    recording.updates_since_capture += new_updates;
+    if recording.updates_since_capture < recording.interval as u64 {
+        return;
+    }
+    recording.updates_since_capture = 0;
+
+    // Same size as when recording started, but following the camera
+    let (camera, cam_transform) = cam_query.single();
+    let center = cam_bounds(camera, cam_transform).center();
+    let bottom_left = center - recording.size / 2;
+    let bounds = GridBounds::new_from_extents(bottom_left, bottom_left + recording.size - GridVec::new(1, 1));
+
+    let colors = world.world.render_to_color_array(bounds, &sandworld::ChunkRenderOptions {
+        draw_dirty: false,
+        draw_borders: false,
+        color_variation: draw_options.color_variation,
+        edge_shading: draw_options.edge_shading,
+        heat_glow: draw_options.heat_glow,
+    });
+    let frame = finish_frame(colors, bounds.width(), bounds.height(), settings.scale);
+
+    let sent = match recording.frame_sender.try_send(frame) {
+        Ok(()) => true,
+        // Dropping the frame would leave a jump in the timelapse, so wait for the writer instead
+        // and capture half as often from here on, so it doesn't keep holding up the game
+        Err(TrySendError::Full(frame)) => {
+            recording.interval *= 2;
+            recording.frame_sender.send(frame).is_ok()
+        }
+        Err(TrySendError::Disconnected(_)) => false,
+    };
+
+    if sent {
+        recording.frames += 1;
+    }
+    else {
+        // The writer has already reported why it stopped
+        recorder.active = None;
+    }
+}
+
+fn update_recording_label(
+    recorder: Res<Recorder>,
+    settings: Res<RecorderSettings>,
+    mut label_query: Query<(&mut Text, &mut Style), With<RecordingLabel>>,
+) {
+    if !recorder.is_changed() {
+        return;
+    }
+
+    let (mut text, mut style) = label_query.single_mut();
+    match &recorder.active {
+        Some(recording) => {
+            style.display = Display::Flex;
+            text.sections[0].value = if recording.interval == settings.interval {
+                format!("REC {} frames", recording.frames)
+            }
+            else {
+                format!("REC {} frames (every {} updates)", recording.frames, recording.interval)
+            };
+        }
+        None => style.display = Display::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::recorder::*;
+
+    #[test]
+    fn parse_reads_keys_and_skips_comments() {
+        let settings = RecorderSettings::parse("
+            # Small and slow
+            interval = 10
+            scale = 0.25
+
+            format = png
+            frame_delay = 100
+            output = captures
+        ").unwrap();
+        assert_eq!(settings.interval, 10);
+        assert_eq!(settings.scale, 0.25);
+        assert_eq!(settings.format, RecordingFormat::Png);
+        assert_eq!(settings.frame_delay, 100);
+        assert_eq!(settings.output_dir, "captures");
+    }
+
+    #[test]
+    fn parse_keeps_defaults_for_missing_keys() {
+        let settings = RecorderSettings::parse("format = gif").unwrap();
+        let defaults = RecorderSettings::default();
+        assert_eq!(settings.interval, defaults.interval);
+        assert_eq!(settings.scale, defaults.scale);
+        assert_eq!(settings.output_dir, defaults.output_dir);
+        // Every update is as often as it can capture
+        assert_eq!(RecorderSettings::parse("interval = 0").unwrap().interval, 1);
+    }
+
+    #[test]
+    fn parse_rejects_bad_lines() {
+        assert!(matches!(RecorderSettings::parse("interval 4"), Err(SettingsError::BadLine(_))));
+        assert!(matches!(RecorderSettings::parse("speed = 4"), Err(SettingsError::UnknownKey(_))));
+        assert!(matches!(RecorderSettings::parse("interval = often"), Err(SettingsError::BadValue(_))));
+        assert!(matches!(RecorderSettings::parse("scale = 0"), Err(SettingsError::BadValue(_))));
+        assert!(matches!(RecorderSettings::parse("scale = -1"), Err(SettingsError::BadValue(_))));
+        assert!(matches!(RecorderSettings::parse("format = mp4"), Err(SettingsError::BadValue(_))));
+    }
+}
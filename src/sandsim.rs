@@ -1,117 +1,69 @@
-use std::{collections::VecDeque, sync::Arc};
+use std::collections::VecDeque;
 
-use bevy::{
-    prelude::*, window::PrimaryWindow
-};
+use bevy::prelude::*;
 use bevy_xpbd_2d::{parry::na::partial_ge, prelude::*};
 use gridmath::*;
-use rand::Rng;
 use sandworld::*;
 
-use crate::{camera::cam_bounds, chunk_colliders::{self, ColliderLayer, SandworldColliderPlugin}, chunk_display::{DrawOptions, SandworldDisplayPlugin}};
+use crate::{camera::cam_bounds, input_map::{Action, ActionState}, chunk_colliders::{self, SandworldColliderPlugin}, chunk_display::{DrawOptions, SandworldDisplayPlugin}, player::Player, worldgen_settings::{WorldGenSettings, WORLDGEN_SETTINGS_PATH}};
 
 
 pub struct SandSimulationPlugin;
 
+// Visible chunks relit per frame, light spreads across chunks over several frames either way
+const MAX_LIGHT_UPDATES_PER_FRAME: usize = 64;
+// Fraction of each frame the simulation aims to use, and the least it's cut back to when the frame runs long
+const SIMULATION_FRAME_SHARE: f64 = 0.5;
+const MIN_SIMULATION_FRAME_SHARE: f64 = 0.1;
+
+const PARTICLE_SCRIPT_DIR: &str = "assets/scripts";
+const STRUCTURE_DIR: &str = "assets/structures";
+
 #[derive(Component)]
 struct BombComp {
     start_time: f32,
     timer_length: f32,
     blast_radius: i32,
-    throw_power: f32,
-}
-
-#[derive(Component)]
-struct SandParticle {
-    particle: ParticleType,
-    last_good: Option<GridVec>,
-}
-
-#[derive(Bundle)]
-struct SandParticleBundle {
-    sprite_bundle: SpriteBundle,
-    sand_particle: SandParticle,
-    collider: Collider,
-    rigidbody: RigidBody,
-    velocity: LinearVelocity,
-    layers: CollisionLayers,
-    friction: Friction,
-}
-
-impl SandParticleBundle {
-    fn new(particle_type: ParticleType, position: Vec3, velocity: Vec3, last_good: Option<GridVec>) -> Self {
-        let color = get_color_for_type(particle_type);
-        SandParticleBundle {
-            sprite_bundle: SpriteBundle {
-                sprite: Sprite {
-                    color: Color::rgb_u8(color[0], color[1], color[2]),
-                    ..Default::default()
-                },
-                transform: Transform::from_translation(position),
-                ..Default::default()
-            },
-            sand_particle: SandParticle { particle: particle_type, last_good },
-            collider: Collider::circle(0.5),
-            rigidbody: RigidBody::Dynamic,
-            velocity: LinearVelocity(velocity.truncate()),
-            layers: CollisionLayers::new(ColliderLayer::Particle, [ColliderLayer::Terrain]),
-            friction: Friction::new(0.5),
-        }
-    }
+    power: f32,
+    throw_speed: f32,
 }
 
 impl Plugin for SandSimulationPlugin {
     fn build(&self, app: &mut App) {
-        let mut rng = rand::thread_rng();
-        let seed: u32 = rng.gen();
+        let mut settings = WorldGenSettings::load(WORLDGEN_SETTINGS_PATH).unwrap_or_else(|err| {
+            println!("Error in {}: {}", WORLDGEN_SETTINGS_PATH, err);
+            WorldGenSettings::default()
+        });
+        if let Err(err) = settings.apply_args(std::env::args().skip(1)) {
+            println!("Ignoring command line: {}", err);
+        }
 
-        println!("Seed: {}", seed);
+        let world = create_world(&settings);
 
-        app.insert_resource(Sandworld {
-            world: sandworld::World::new(Arc::new(crate::worldgen::WorldBuilder::new(
-                seed, 5000., 1500., 500., 500., 400.,
-            ))),
-        })
+        app.insert_resource(Sandworld { world })
+        .insert_resource(settings)
         .add_plugins(SandworldDisplayPlugin)
         .add_plugins(SandworldColliderPlugin)
-        .insert_resource(BrushOptions {
-            brush_mode: BrushMode::Place(ParticleType::Sand, 0),
-            radius: 10,
-            click_start: None,
-        })
         .insert_resource(WorldStats {
             update_stats: None,
             sand_update_time: VecDeque::new(),
             chunk_texture_update_time: VecDeque::new(),
             chunk_cull_time: VecDeque::new(),
-            target_chunk_updates: 0,
             mouse_grid_pos: GridVec::new(0, 0),
             mouse_chunk_pos: GridVec::new(0, 0),
             mouse_region: GridVec::new(0, 0),
         })
         .add_systems(Update, sand_update.in_set(crate::UpdateStages::WorldUpdate))
-        .add_systems(Update, (world_interact, bomb_timer, sand_particle_settle).in_set(crate::UpdateStages::Input))
-        .add_systems(Update, draw_mode_controls.in_set(crate::UpdateStages::Input))
+        .add_systems(Update, bomb_timer.in_set(crate::UpdateStages::Input))
+        .add_systems(Update, (draw_mode_controls, script_reload_controls, recreate_world).chain().in_set(crate::UpdateStages::Input))
+        .add_event::<RecreateWorld>()
         ;
     }
 }
 
-#[derive(PartialEq, Eq, Clone)]
-pub enum BrushMode {
-    Place(sandworld::ParticleType, u8),
-    Melt,
-    Break,
-    Chill,
-    Beam,
-    Ball,
-}
-
-#[derive(Resource)]
-pub struct BrushOptions {
-    pub brush_mode: BrushMode,
-    pub radius: i32,
-    pub click_start: Option<GridVec>,
-}
+// Replace the world with a new one built from the current WorldGenSettings
+#[derive(Event)]
+pub struct RecreateWorld;
 
 #[derive(Resource)]
 pub struct Sandworld {
@@ -124,7 +76,6 @@ pub struct WorldStats {
     pub sand_update_time: VecDeque<(f64, u64)>, // Pairs of update time and updated chunk counts
     pub chunk_texture_update_time: VecDeque<(f64, u64)>, // Pairs of update time and updated chunk counts
     pub chunk_cull_time: VecDeque<(f64, u64)>, // Pairs of culling time and culled chunk counts
-    pub target_chunk_updates: u64,
     pub mouse_grid_pos: GridVec,
     pub mouse_chunk_pos: GridVec,
     pub mouse_region: GridVec,
@@ -132,56 +83,174 @@ pub struct WorldStats {
 
 fn draw_mode_controls(
     mut draw_options: ResMut<DrawOptions>, 
-    keys: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
 ){
     draw_options.force_redraw_all = false;
 
-    if keys.just_pressed(KeyCode::F2) {
+    if actions.just_pressed(Action::ToggleChunkBounds) {
         draw_options.chunk_bounds = !draw_options.chunk_bounds;
         draw_options.force_redraw_all = true;
     }
-    if keys.just_pressed(KeyCode::F3) {
+    if actions.just_pressed(Action::ToggleUpdateBounds) {
         draw_options.update_bounds = !draw_options.update_bounds;
         draw_options.force_redraw_all = true;
     }
-    if keys.just_pressed(KeyCode::F4) {
+    if actions.just_pressed(Action::ToggleWorldStats) {
         draw_options.world_stats = !draw_options.world_stats;
     }
+    if actions.just_pressed(Action::ToggleColorVariation) {
+        draw_options.color_variation = if draw_options.color_variation > 0. { 0. } else { 1. };
+    }
+    if actions.just_pressed(Action::ToggleEdgeShading) {
+        draw_options.edge_shading = if draw_options.edge_shading > 0. { 0. } else { 0.25 };
+    }
+    if actions.just_pressed(Action::ToggleHeatGlow) {
+        draw_options.heat_glow = if draw_options.heat_glow > 0. { 0. } else { 0.6 };
+    }
+    if actions.just_pressed(Action::ToggleLighting) {
+        draw_options.lighting = !draw_options.lighting;
+        draw_options.force_redraw_all = true;
+    }
+    if actions.just_pressed(Action::CycleOverlay) {
+        draw_options.overlay = draw_options.overlay.cycle();
+    }
+    if actions.just_pressed(Action::ToggleColliders) {
+        draw_options.show_colliders = !draw_options.show_colliders;
+    }
+}
+
+// A fresh world from the settings, with the particle scripts and structures from assets loaded into it
+pub fn create_world(settings: &WorldGenSettings) -> sandworld::World {
+    println!("Seed: {} ({} generator)", settings.seed, settings.generator.name());
+
+    let mut world = sandworld::World::new(settings.build_generator());
+    load_particle_scripts(&mut world);
+    load_structures(&mut world, settings.seed as u64);
+    world
+}
+
+// Scripts are named after the material they drive, eg assets/scripts/source.rhai replaces the Source update
+fn load_particle_scripts(world: &mut sandworld::World) {
+    world.clear_particle_scripts();
+
+    let Ok(entries) = std::fs::read_dir(PARTICLE_SCRIPT_DIR) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension() != Some("rhai".as_ref()) {
+            continue;
+        }
+
+        let Some(particle_type) = path.file_stem().and_then(|stem| stem.to_str()).and_then(ParticleType::from_name) else {
+            println!("Skipping script {}, name does not match a particle type", path.display());
+            continue;
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(source) => match world.set_particle_script(particle_type, &source) {
+                Ok(()) => println!("Loaded {:?} script from {}", particle_type, path.display()),
+                Err(err) => println!("Error in {}: {}", path.display(), err),
+            },
+            Err(err) => println!("Could not read {}: {}", path.display(), err),
+        }
+    }
+}
+
+// Each .stamp file is one structure, see sandworld::StructureType::parse for the format
+fn load_structures(world: &mut sandworld::World, seed: u64) {
+    let mut structures = StructureSet::new(seed);
+
+    let Ok(entries) = std::fs::read_dir(STRUCTURE_DIR) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension() != Some("stamp".as_ref()) {
+            continue;
+        }
+
+        let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default().to_string();
+        match std::fs::read_to_string(&path) {
+            Ok(source) => match StructureType::parse(&name, &source) {
+                Ok(structure) => {
+                    println!("Loaded structure {} from {}", structure.name, path.display());
+                    structures.add(structure);
+                }
+                Err(err) => println!("Error in {}: {}", path.display(), err),
+            },
+            Err(err) => println!("Could not read {}: {}", path.display(), err),
+        }
+    }
+
+    world.set_structures(structures);
 }
 
-fn sand_update(
+fn recreate_world(
+    mut events: EventReader<RecreateWorld>,
+    mut world: ResMut<Sandworld>,
+    settings: Res<WorldGenSettings>,
+    mut draw_options: ResMut<DrawOptions>,
+    bomb_query: Query<Entity, With<BombComp>>,
+    mut player_query: Query<&mut Player>,
+    mut commands: Commands,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+
+    world.world = create_world(&settings);
+
+    // Bombs in flight belonged to the old world, ejecta went with it
+    for entity in bomb_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    // The player stays where they are, but is moved to open space once the new world loads there
+    for mut player in player_query.iter_mut() {
+        player.respawn_in_new_world();
+    }
+    draw_options.force_redraw_all = true;
+}
+
+fn script_reload_controls(
+    mut world: ResMut<Sandworld>,
+    actions: Res<ActionState>,
+) {
+    if actions.just_pressed(Action::ReloadScripts) {
+        load_particle_scripts(&mut world.world);
+    }
+}
+
+pub(crate) fn sand_update(
     mut world: ResMut<Sandworld>,
     mut world_stats: ResMut<WorldStats>,
     perf_settings: Res<crate::perf::PerfSettings>,
+    frame_times: Res<crate::perf::FrameTimes>,
     cam_query: Query<(&Camera, &GlobalTransform)>,
-    debug_buttons: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
+    draw_options: Res<DrawOptions>,
 ) {
     world.world.reset_updated_chunks();
 
-    let mut target_chunk_updates = 128;
-
-    if let Some(_stats) = &world_stats.update_stats {
-        // Aim to use half of the available frame time for sand updates
-        let target_update_seconds: f64 = (1. / (perf_settings.target_frame_rate as f64)) * 0.5;
-        let mut chunk_updates_per_second_avg = 0.;
-        for (time, count) in &world_stats.sand_update_time {
-            chunk_updates_per_second_avg += (*count + 1) as f64 / time;
-        }
-        chunk_updates_per_second_avg =
-            chunk_updates_per_second_avg / (world_stats.sand_update_time.len() as f64);
-        target_chunk_updates = (target_update_seconds * chunk_updates_per_second_avg) as u64;
-        world_stats.target_chunk_updates = target_chunk_updates;
-    }
+    // The world measures what a chunk update costs, so it only needs to be told how long it has
+    let frame_seconds = 1. / perf_settings.target_frame_rate as f64;
+    // Time the rest of the frame has been overrunning by comes out of the simulation's share
+    let overrun = (frame_times.current_avg - frame_seconds).max(0.);
+    let budget_seconds = (frame_seconds * SIMULATION_FRAME_SHARE - overrun).max(frame_seconds * MIN_SIMULATION_FRAME_SHARE);
 
     let (camera, cam_transform) = cam_query.single();
     let bounds = cam_bounds(camera, cam_transform);
 
     let update_options = sandworld::WorldUpdateOptions {
-        force_compress_decompress_all: debug_buttons.just_pressed(KeyCode::F10),
+        budget: sandworld::UpdateBudget::Time(std::time::Duration::from_secs_f64(budget_seconds)),
+        force_compress_decompress_all: actions.just_pressed(Action::ForceCompress),
+        ..default()
     };
 
     let update_start = std::time::Instant::now();
-    let stats = world.world.update(bounds, target_chunk_updates, update_options);
+    let stats = world.world.update(bounds, update_options);
     let update_end = std::time::Instant::now();
     let update_time = update_end - update_start;
     world_stats
@@ -191,6 +260,10 @@ fn sand_update(
         world_stats.sand_update_time.pop_front();
     }
     world_stats.update_stats = Some(stats);
+
+    if draw_options.lighting {
+        world.world.update_lighting(bounds, MAX_LIGHT_UPDATES_PER_FRAME);
+    }
 }
 
 fn bomb_timer(
@@ -204,170 +277,34 @@ fn bomb_timer(
         if timer > bomb.timer_length {
             let pos = transform.translation;
             let gridpos = GridVec::new(pos.x as i32, pos.y as i32);
-            sand.world.break_circle(gridpos, bomb.blast_radius, 1.2);
-            sand.world.temp_change_circle(gridpos, 8, 0.75, 1000);
+            let explosion = Explosion::new(bomb.blast_radius, bomb.power)
+                .with_heat(1000)
+                .with_throw_speed(bomb.throw_speed);
+            sand.world.explode(gridpos, explosion);
             commands.entity(entity).despawn();
-
-            let throwable_parts = particle_set![ParticleType::Gravel, ParticleType::Sand];
-
-            let throw_radius = bomb.blast_radius / 2;
-            let to_throw = sand.world.extract_circle(gridpos, throw_radius, throwable_parts);
-
-            for (part_type, position) in to_throw {
-                let world_pos = Vec3::new(position.x as f32, position.y as f32, 0.1);
-                let power = (world_pos - pos).length_squared() / throw_radius.pow(2) as f32;
-                let throw_velocity = (world_pos - pos).normalize_or_zero() * power * bomb.throw_power;
-                commands.spawn(SandParticleBundle::new(part_type, world_pos, throw_velocity, Some(position)));
-            }
         }
     }
 }
 
-fn sand_particle_settle(
-    mut sand: ResMut<Sandworld>,
-    mut particle_query: Query<(Entity, &Transform, &LinearVelocity, &mut SandParticle)>,
-    mut commands: Commands,
-) {
-    let min_vel = 0.1;
-
-    for (entity, transform, velocity, mut particle) in particle_query.iter_mut() {
-        let fpos = transform.translation;
-        let gridpos = GridVec::new((fpos.x + 0.5) as i32, (fpos.y + 0.5) as i32);
-
-        if crate::chunk_colliders::COLLIDES.test(sand.world.get_particle(gridpos).particle_type) {
-            commands.entity(entity).despawn_recursive();
-
-            // If we're in a bad spot, attempt to place in the last good spot
-            if let Some(last_pos) = particle.last_good {
-                sand.world.replace_particle_filtered(last_pos, Particle::new(particle.particle), particle_set![ParticleType::Air]);
-            }
-        }
-        else if velocity.length_squared() < min_vel {
-            if let Some(replaced_type) = sand.world.replace_particle_filtered(gridpos, Particle::new(particle.particle), particle_set![ParticleType::Air, ParticleType::Water]) {
-                // if we're replacing something, attempt to preserve it by displacing it to the last air that this particle passed thru
-                if let Some(last_pos) = particle.last_good {
-                    sand.world.replace_particle_filtered(last_pos, Particle::new(replaced_type), particle_set![ParticleType::Air]);
-                }
-            }
-            commands.entity(entity).despawn_recursive();
-        }
-        else {
-            if sand.world.get_particle(gridpos).particle_type == ParticleType::Air {
-                particle.last_good = Some(gridpos);
-            }
-        }
-    }
-}
-
-fn world_interact(
-    wnds: Query<&Window, With<PrimaryWindow>>,
-    capture_state: Res<crate::ui::PointerCaptureState>,
-    q_cam: Query<(&Camera, &GlobalTransform)>,
-    mut sand: ResMut<Sandworld>,
-    buttons: Res<ButtonInput<MouseButton>>,
-    mut brush_options: ResMut<BrushOptions>,
-    mut world_stats: ResMut<WorldStats>,
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    time: Res<Time>,
-    spatial_query: SpatialQuery,
-) {
-    // get the camera info and transform
-    // assuming there is exactly one main camera entity, so query::single() is OK
-    let (camera, camera_transform) = q_cam.single();
-
-    // get the window that the camera is displaying to (or the primary window)
-    let Ok(wnd) = wnds.get_single() else {
-        eprintln!("no window!!!");
-        return;
-    };
-
-    // check if the cursor is inside the window and get its position
-    if let Some(screen_pos) = wnd.cursor_position() {
-        let world_pos = camera.viewport_to_world_2d(camera_transform, screen_pos).unwrap();
-        let gridpos = GridVec::new(world_pos.x as i32, world_pos.y as i32);
-
-        world_stats.mouse_grid_pos = gridpos;
-        world_stats.mouse_chunk_pos = gridpos / CHUNK_SIZE as i32;
-        world_stats.mouse_region = sandworld::World::get_regionpos_for_chunkpos(&(world_stats.mouse_chunk_pos));
-
-        
-        if !capture_state.click_consumed && buttons.any_pressed([MouseButton::Left, MouseButton::Right])
-        {
-            if buttons.just_pressed(MouseButton::Left) {
-                brush_options.click_start = Some(gridpos);
-
-                match brush_options.brush_mode {
-                    BrushMode::Ball => {
-                        commands.spawn(SpriteBundle {
-                                texture: asset_server.load("sprites/bomb1.png"),
-                                transform: Transform::from_xyz(world_pos.x, world_pos.y, 0.1),
-                                ..default()
-                            })
-                            .insert(BombComp {
-                                start_time: time.elapsed_seconds(),
-                                timer_length: 5.0,
-                                blast_radius: 64,
-                                throw_power: 1024.
-                            })
-                            .insert(Collider::circle(5.))
-                            .insert(RigidBody::Dynamic)
-                            .insert(CollisionLayers::new(
-                                chunk_colliders::ColliderLayer::Projectile,
-                                chunk_colliders::DEFAULT_COLLISION_LAYERS
-                            ))
-                            ;
-                    },
-                    _ => ()
-                }
-            }
-
-            if buttons.pressed(MouseButton::Left) {
-                match brush_options.brush_mode {
-                    BrushMode::Place(part_type, data) => sand.world.place_circle(
-                        gridpos,
-                        brush_options.radius,
-                        sandworld::Particle::new_with_data(part_type, data),
-                        false,
-                    ),
-                    BrushMode::Melt => {
-                        sand.world
-                            .temp_change_circle(gridpos, brush_options.radius, 0.01, 1800)
-                    }
-                    BrushMode::Break => sand.world.break_circle(gridpos, brush_options.radius, 0.1),
-                    BrushMode::Chill => {
-                        sand.world
-                            .temp_change_circle(gridpos, brush_options.radius, 0.01, -100)
-                    },
-                    BrushMode::Beam => {
-                        if let Some(click_pos) = brush_options.click_start {
-                            let clickposf = Vec2::new(click_pos.x as f32, click_pos.y as f32);
-                            let direction = (world_pos - clickposf).normalize();
-
-                            if let Some(hit) = spatial_query.cast_ray(
-                                clickposf, 
-                                Direction2d::new(direction).unwrap(), 
-                                512., 
-                                true, 
-                                SpatialQueryFilter::default()
-                            ) {
-                                let hit_pos = clickposf + hit.time_of_impact * direction;
-                                let grid_pos = GridVec::new(hit_pos.x as i32, hit_pos.y as i32);
-
-                                sand.world.temp_change_circle(grid_pos, brush_options.radius, 0.01, 1800);
-                            }
-                        }     
-                    },
-                    BrushMode::Ball => () // only act on down
-                }
-            } else if buttons.pressed(MouseButton::Right) {
-                sand.world.place_circle(
-                    gridpos,
-                    10,
-                    sandworld::Particle::new(sandworld::ParticleType::Air),
-                    true,
-                );
-            }
-        }
-    }
+// Drop a bomb at a world position, which goes off after a few seconds, see bomb_timer
+pub(crate) fn spawn_bomb(commands: &mut Commands, asset_server: &AssetServer, pos: Vec2, time: &Time) {
+    commands.spawn(SpriteBundle {
+            texture: asset_server.load("sprites/bomb1.png"),
+            transform: Transform::from_xyz(pos.x, pos.y, 0.1),
+            ..default()
+        })
+        .insert(BombComp {
+            start_time: time.elapsed_seconds(),
+            timer_length: 5.0,
+            blast_radius: 64,
+            power: 12.,
+            throw_speed: 240.,
+        })
+        .insert(Collider::circle(5.))
+        .insert(RigidBody::Dynamic)
+        .insert(CollisionLayers::new(
+            chunk_colliders::ColliderLayer::Projectile,
+            chunk_colliders::DEFAULT_COLLISION_LAYERS
+        ))
+        ;
 }
@@ -0,0 +1,325 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use gridmath::*;
+use sandworld::{Particle, ParticleType, World, CHUNK_SIZE};
+
+use crate::{
+    input_map::{Action, ActionState},
+    sandsim::{Sandworld, WorldStats},
+};
+
+pub struct ToolsPlugin;
+
+// Radius right click erases with, whatever the tool
+const ERASE_RADIUS: i32 = 10;
+const PREVIEW_COLOR: Color = Color::rgba(0.9, 0.9, 0.9, 0.5);
+
+/*
+    Settings a tool keeps while it's selected, starting from Tool::default_options
+    Radius can be changed in game with the RadiusUp and RadiusDown actions, - and = by default
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct ToolOptions {
+    pub radius: i32,
+    pub min_radius: i32,
+    pub max_radius: i32,
+}
+
+impl Default for ToolOptions {
+    fn default() -> Self {
+        ToolOptions {
+            radius: 10,
+            min_radius: 1,
+            max_radius: 64,
+        }
+    }
+}
+
+impl ToolOptions {
+    pub fn with_radius(mut self, radius: i32) -> Self {
+        self.radius = radius;
+        self
+    }
+}
+
+/*
+    Everything a tool can act on while handling the mouse
+    Positions are in world space, and click_start is where the current press began
+*/
+pub struct ToolContext<'a, 'w, 's> {
+    pub world: &'a mut World,
+    pub commands: &'a mut Commands<'w, 's>,
+    pub asset_server: &'a AssetServer,
+    pub time: &'a Time,
+    pub cursor: Vec2,
+    pub gridpos: GridVec,
+    pub click_start: Option<GridVec>,
+    pub options: ToolOptions,
+    // Last material picked with a tool that has one, for tools that place whatever is selected
+    pub material: Particle,
+}
+
+// What a tool can see when drawing its preview under the cursor
+pub struct PreviewContext<'a> {
+    pub world: &'a World,
+    pub cursor: Vec2,
+    pub gridpos: GridVec,
+    pub click_start: Option<GridVec>,
+    pub options: ToolOptions,
+}
+
+/*
+    Something the player can do to the world with the mouse, registered with App::add_tool
+    Every registered tool gets a toolbar button. The UseTool action (left click) presses, holding it drags
+    every frame after, and letting go releases. Erase (right click) always erases
+*/
+pub trait Tool: Send + Sync + 'static {
+    fn label(&self) -> &str;
+
+    fn default_options(&self) -> ToolOptions {
+        ToolOptions::default()
+    }
+
+    // Material this tool puts down, which becomes the one other tools use when it is selected
+    fn material(&self) -> Option<Particle> {
+        None
+    }
+
+    fn press(&mut self, _ctx: &mut ToolContext) {}
+
+    fn drag(&mut self, _ctx: &mut ToolContext) {}
+
+    fn release(&mut self, _ctx: &mut ToolContext) {}
+
+    // Defaults to the outline of the brush
+    fn draw_preview(&self, gizmos: &mut Gizmos, ctx: &PreviewContext) {
+        gizmos.circle_2d(ctx.cursor, ctx.options.radius as f32, PREVIEW_COLOR);
+    }
+}
+
+struct RegisteredTool {
+    tool: Box<dyn Tool>,
+    options: ToolOptions,
+}
+
+#[derive(Resource)]
+pub struct ToolRegistry {
+    tools: Vec<RegisteredTool>,
+    selected: usize,
+    material: Particle,
+    click_start: Option<GridVec>,
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        ToolRegistry {
+            tools: Vec::new(),
+            selected: 0,
+            material: Particle::new(ParticleType::Sand),
+            click_start: None,
+        }
+    }
+}
+
+impl ToolRegistry {
+    pub fn add(&mut self, tool: impl Tool) {
+        let options = tool.default_options();
+        self.tools.push(RegisteredTool { tool: Box::new(tool), options });
+    }
+
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    pub fn get_label(&self, index: usize) -> &str {
+        self.tools[index].tool.label()
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select(&mut self, index: usize) {
+        if index >= self.tools.len() {
+            return;
+        }
+
+        self.selected = index;
+        if let Some(material) = self.tools[index].tool.material() {
+            self.material = material;
+        }
+    }
+
+    // Pick a tool by its toolbar label
+    pub fn select_by_label(&mut self, label: &str) {
+        if let Some(index) = self.tools.iter().position(|registered| registered.tool.label() == label) {
+            self.select(index);
+        }
+    }
+}
+
+pub trait AppToolExt {
+    // Register a tool, giving it a toolbar button after those already added
+    fn add_tool(&mut self, tool: impl Tool) -> &mut Self;
+}
+
+impl AppToolExt for App {
+    fn add_tool(&mut self, tool: impl Tool) -> &mut Self {
+        self.init_resource::<ToolRegistry>();
+        self.world.resource_mut::<ToolRegistry>().add(tool);
+        self
+    }
+}
+
+impl Plugin for ToolsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ToolRegistry>();
+        crate::builtin_tools::add_builtin_tools(app);
+        app.world.resource_mut::<ToolRegistry>().select_by_label("Sand");
+
+        app.add_systems(Update, (tool_radius_controls, tool_select_controls, world_interact).chain().in_set(crate::UpdateStages::Input))
+            .add_systems(Update, draw_tool_preview.in_set(crate::UpdateStages::WorldDraw));
+    }
+}
+
+fn get_cursor_world_pos(actions: &ActionState, window: &Window, camera: &Camera, camera_transform: &GlobalTransform) -> Option<Vec2> {
+    actions.cursor_position(window).and_then(|screen_pos| camera.viewport_to_world_2d(camera_transform, screen_pos))
+}
+
+pub(crate) fn to_gridpos(pos: Vec2) -> GridVec {
+    GridVec::new(pos.x.floor() as i32, pos.y.floor() as i32)
+}
+
+// Hand the cursor to the selected tool, unless something else took the click this frame
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn world_interact(
+    wnds: Query<&Window, With<PrimaryWindow>>,
+    capture_state: Res<crate::ui::PointerCaptureState>,
+    q_cam: Query<(&Camera, &GlobalTransform)>,
+    mut sand: ResMut<Sandworld>,
+    actions: Res<ActionState>,
+    mut registry: ResMut<ToolRegistry>,
+    mut world_stats: ResMut<WorldStats>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+) {
+    let (camera, camera_transform) = q_cam.single();
+    let Ok(wnd) = wnds.get_single() else {
+        return;
+    };
+    let Some(cursor) = get_cursor_world_pos(&actions, wnd, camera, camera_transform) else {
+        return;
+    };
+    let gridpos = to_gridpos(cursor);
+
+    world_stats.mouse_grid_pos = gridpos;
+    world_stats.mouse_chunk_pos = gridpos / CHUNK_SIZE as i32;
+    world_stats.mouse_region = World::get_regionpos_for_chunkpos(&(world_stats.mouse_chunk_pos));
+
+    if registry.tools.is_empty() {
+        return;
+    }
+
+    let registry = &mut *registry;
+    let selected = &mut registry.tools[registry.selected];
+    let mut ctx = ToolContext {
+        world: &mut sand.world,
+        commands: &mut commands,
+        asset_server: &asset_server,
+        time: &time,
+        cursor,
+        gridpos,
+        click_start: registry.click_start,
+        options: selected.options,
+        material: registry.material,
+    };
+
+    if actions.just_released(Action::UseTool) && registry.click_start.is_some() {
+        selected.tool.release(&mut ctx);
+        registry.click_start = None;
+    }
+
+    if capture_state.click_consumed {
+        return;
+    }
+
+    if actions.just_pressed(Action::UseTool) {
+        ctx.click_start = Some(gridpos);
+        registry.click_start = Some(gridpos);
+        selected.tool.press(&mut ctx);
+    }
+    else if actions.pressed(Action::UseTool) && registry.click_start.is_some() {
+        selected.tool.drag(&mut ctx);
+    }
+    else if actions.pressed(Action::Erase) {
+        ctx.world.place_circle(gridpos, ERASE_RADIUS, Particle::new(ParticleType::Air), true);
+    }
+}
+
+fn tool_radius_controls(
+    mut registry: ResMut<ToolRegistry>,
+    actions: Res<ActionState>,
+) {
+    let change = actions.just_pressed(Action::RadiusUp) as i32 - actions.just_pressed(Action::RadiusDown) as i32;
+    if change == 0 || registry.tools.is_empty() {
+        return;
+    }
+
+    let selected = registry.selected;
+    let options = &mut registry.tools[selected].options;
+    let step = (options.radius / 4).max(1);
+    options.radius = (options.radius + change * step).clamp(options.min_radius, options.max_radius);
+}
+
+// Step through the toolbar without the mouse, for gamepads
+fn tool_select_controls(
+    mut registry: ResMut<ToolRegistry>,
+    actions: Res<ActionState>,
+) {
+    if registry.is_empty() || registry.click_start.is_some() {
+        return;
+    }
+    let len = registry.len();
+
+    if actions.just_pressed(Action::NextTool) {
+        let next = (registry.selected + 1) % len;
+        registry.select(next);
+    }
+    if actions.just_pressed(Action::PrevTool) {
+        let prev = (registry.selected + len - 1) % len;
+        registry.select(prev);
+    }
+}
+
+fn draw_tool_preview(
+    wnds: Query<&Window, With<PrimaryWindow>>,
+    actions: Res<ActionState>,
+    q_cam: Query<(&Camera, &GlobalTransform)>,
+    sand: Res<Sandworld>,
+    registry: Res<ToolRegistry>,
+    mut gizmos: Gizmos,
+) {
+    let (camera, camera_transform) = q_cam.single();
+    let Ok(wnd) = wnds.get_single() else {
+        return;
+    };
+    let Some(cursor) = get_cursor_world_pos(&actions, wnd, camera, camera_transform) else {
+        return;
+    };
+    let Some(selected) = registry.tools.get(registry.selected) else {
+        return;
+    };
+
+    let ctx = PreviewContext {
+        world: &sand.world,
+        cursor,
+        gridpos: to_gridpos(cursor),
+        click_start: registry.click_start,
+        options: selected.options,
+    };
+    selected.tool.draw_preview(&mut gizmos, &ctx);
+}
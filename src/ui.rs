@@ -1,7 +1,6 @@
-use crate::sandsim::{BrushMode, BrushOptions};
+use crate::tools::ToolRegistry;
 use crate::chunk_display::DrawOptions;
 use bevy::prelude::*;
-use sandworld::ParticleType;
 
 pub struct UiPlugin;
 
@@ -10,9 +9,11 @@ pub struct PointerCaptureState {
     pub click_consumed: bool,
 }
 
-const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
-const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
-const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+pub(crate) const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+pub(crate) const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
+pub(crate) const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+// Background for panels and screens drawn over the world
+pub(crate) const PANEL_COLOR: Color = Color::rgba(0.08, 0.08, 0.08, 0.9);
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
@@ -36,6 +37,31 @@ impl Plugin for UiPlugin {
     }
 }
 
+pub(crate) fn text_style(asset_server: &AssetServer, font_size: f32) -> TextStyle {
+    TextStyle {
+        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+        font_size,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    }
+}
+
+// Text button for a screen, tagged with the component its screen reacts to when it's pressed
+pub(crate) fn spawn_button<T: Component>(parent: &mut ChildBuilder, asset_server: &AssetServer, label: &str, action: T) {
+    parent.spawn(ButtonBundle {
+        style: Style {
+            padding: UiRect::axes(Val::Px(12.), Val::Px(6.)),
+            margin: UiRect::all(Val::Px(4.)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: NORMAL_BUTTON.into(),
+        ..default()
+    }).insert(action).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(label, text_style(asset_server, 20.)));
+    });
+}
+
 #[derive(Component)]
 struct PerformanceReadout;
 
@@ -142,8 +168,8 @@ fn update_performance_text(
             text.sections[2].value = format!("\nMouse position: {0} (Chunk: {1} Region: {2})", stats.mouse_grid_pos, stats.mouse_chunk_pos, stats.mouse_region);
             text.sections[3].value = format!("\nRegion Updates: {}", world_stats.region_updates);
             text.sections[4].value = format!(
-                "\nChunk Updates [Target]: {} [{}]",
-                world_stats.chunk_updates, stats.target_chunk_updates
+                "\nChunk Updates [Target]: {} [{}] ({:.1}us each)",
+                world_stats.chunk_updates, world_stats.target_chunk_updates, world_stats.chunk_update_cost * 1_000_000.
             );
 
             if stats.chunk_texture_update_time.len() > 0 {
@@ -207,18 +233,17 @@ fn update_performance_text(
     }
 }
 
+// Toolbar button selecting the tool at this index in the ToolRegistry
 #[derive(Component)]
-struct ToolSelector {
-    brush_mode: BrushMode,
-    radius: i32,
+pub(crate) struct ToolSelector {
+    tool: usize,
 }
 
 fn spawn_tool_selector_button(
     parent: &mut ChildBuilder,
     asset_server: &Res<AssetServer>,
     label: &str,
-    brush_mode: BrushMode,
-    radius: i32,
+    tool: usize,
 ) {
     parent
         .spawn(ButtonBundle {
@@ -241,7 +266,7 @@ fn spawn_tool_selector_button(
             background_color: NORMAL_BUTTON.into(),
             ..default()
         })
-        .insert(ToolSelector { brush_mode, radius })
+        .insert(ToolSelector { tool })
         .with_children(|parent| {
             parent.spawn(TextBundle::from_section(
                 label,
@@ -254,7 +279,7 @@ fn spawn_tool_selector_button(
         });
 }
 
-fn setup_buttons(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup_buttons(mut commands: Commands, asset_server: Res<AssetServer>, registry: Res<ToolRegistry>) {
     commands.spawn(NodeBundle {
         style: Style {
             flex_direction: FlexDirection::Row,
@@ -265,103 +290,17 @@ fn setup_buttons(mut commands: Commands, asset_server: Res<AssetServer>) {
         },
         ..Default::default()
     }).with_children(|parent| {
-        spawn_tool_selector_button(parent, &asset_server, "BOMB", BrushMode::Ball, 10);
-        spawn_tool_selector_button(parent, &asset_server, "BEAM", BrushMode::Beam, 10);
-        spawn_tool_selector_button(parent, &asset_server, "MELT", BrushMode::Melt, 10);
-        spawn_tool_selector_button(parent, &asset_server, "BREAK", BrushMode::Break, 10);
-        spawn_tool_selector_button(parent, &asset_server, "CHILL", BrushMode::Chill, 20);
-        spawn_tool_selector_button(
-             parent,
-            &asset_server,
-            "Stone",
-            BrushMode::Place(ParticleType::Stone, 0),
-            20,
-        );
-        spawn_tool_selector_button(
-            parent,
-            &asset_server,
-            "Gravel",
-            BrushMode::Place(ParticleType::Gravel, 0),
-            10,
-        );
-        spawn_tool_selector_button(
-            parent,
-            &asset_server,
-            "Sand",
-            BrushMode::Place(ParticleType::Sand, 0),
-            10,
-        );
-        spawn_tool_selector_button(
-            parent,
-            &asset_server,
-            "Ice",
-            BrushMode::Place(ParticleType::Ice, 0),
-            10,
-        );
-        spawn_tool_selector_button(
-            parent,
-            &asset_server,
-            "Water",
-            BrushMode::Place(ParticleType::Water, 0),
-            10,
-        );
-        spawn_tool_selector_button(
-            parent,
-            &asset_server,
-            "Steam",
-            BrushMode::Place(ParticleType::Steam, 0),
-            10,
-        );
-        spawn_tool_selector_button(
-            parent,
-            &asset_server,
-            "Lava",
-            BrushMode::Place(ParticleType::Lava, 0),
-            10,
-        );
-        spawn_tool_selector_button(
-            parent,
-            &asset_server,
-            "Emit",
-            BrushMode::Place(ParticleType::Source, 0),
-            1,
-        );
-        spawn_tool_selector_button(
-            parent,
-            &asset_server,
-            "LaserR",
-            BrushMode::Place(ParticleType::LaserEmitter, 1),
-            1,
-        );
-        spawn_tool_selector_button(
-            parent,
-            &asset_server,
-            "LaserL",
-            BrushMode::Place(ParticleType::LaserEmitter, 3),
-            1,
-        );
-        spawn_tool_selector_button(
-            parent,
-            &asset_server,
-            "LaserU",
-            BrushMode::Place(ParticleType::LaserEmitter, 0),
-            1,
-        );
-        spawn_tool_selector_button(
-            parent,
-            &asset_server,
-            "LaserD",
-            BrushMode::Place(ParticleType::LaserEmitter, 2),
-            1,
-        );
+        for tool in 0..registry.len() {
+            spawn_tool_selector_button(parent, &asset_server, registry.get_label(tool), tool);
+        }
     });
     
 }
 
-fn button_system(
+pub(crate) fn button_system(
     mut capture_state: ResMut<PointerCaptureState>,
     mut interaction_query: Query<(&Interaction, &mut BackgroundColor, &ToolSelector), With<Button>>,
-    mut brush_options: ResMut<BrushOptions>,
+    mut registry: ResMut<ToolRegistry>,
 ) {
     capture_state.click_consumed = false;
 
@@ -369,8 +308,7 @@ fn button_system(
         match *interaction {
             Interaction::Pressed => {
                 *color = PRESSED_BUTTON.into();
-                brush_options.brush_mode = selector.brush_mode.clone();
-                brush_options.radius = selector.radius;
+                registry.select(selector.tool);
                 capture_state.click_consumed = true;
             }
             Interaction::Hovered => {
@@ -381,7 +319,7 @@ fn button_system(
             }
         }
 
-        if selector.brush_mode == brush_options.brush_mode {
+        if selector.tool == registry.selected() {
             *color = PRESSED_BUTTON.into();
         }
     }
@@ -0,0 +1,380 @@
+use std::{fmt, sync::Arc};
+
+use bevy::prelude::Resource;
+use sandworld::{assembled_world, Biome, BiomeWorld, Blankworld, FlatPlain, WorldBuilder, WorldGenerator};
+
+pub const WORLDGEN_SETTINGS_PATH: &str = "assets/worldgen.cfg";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorKind {
+    Builder,
+    Assembled,
+    Biomes,
+    Flat,
+    Blank,
+}
+
+// Numeric generator parameters, each exposed as a slider on the new world screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorldGenParam {
+    TerrainScale,
+    TerrainHeight,
+    CaveScale,
+    CaveOpenness,
+    LavaDepth,
+    LavaScale,
+    BiomeScale,
+    SeaLevel,
+}
+
+/*
+    Everything needed to recreate a world's terrain
+    Loaded from a `key = value` file, eg
+
+        generator = biomes
+        seed = 1234
+        terrain_height = 800
+
+    Missing keys keep their defaults, and a seed of `random` (or none at all) picks a new one each run
+*/
+#[derive(Resource, Debug, Clone)]
+pub struct WorldGenSettings {
+    pub seed: u32,
+    pub generator: GeneratorKind,
+    pub terrain_scale: f64,
+    pub terrain_height: f64,
+    pub cave_scale: f64,
+    pub cave_openness: f64,
+    pub lava_depth: f64,
+    pub lava_scale: f64,
+    pub biome_scale: f64,
+    pub sea_level: f64,
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Read(std::io::Error),
+    BadLine(String),
+    UnknownKey(String),
+    BadValue(String),
+    UnknownGenerator(String),
+    MissingArgValue(String),
+    UnknownArg(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Read(err) => write!(f, "could not read settings: {}", err),
+            SettingsError::BadLine(line) => write!(f, "expected `key = value`, found `{}`", line),
+            SettingsError::UnknownKey(key) => write!(f, "unknown setting `{}`", key),
+            SettingsError::BadValue(line) => write!(f, "bad value in `{}`", line),
+            SettingsError::UnknownGenerator(name) => write!(f, "unknown generator `{}`, expected one of {}", name, GeneratorKind::names()),
+            SettingsError::MissingArgValue(arg) => write!(f, "`{}` needs a value", arg),
+            SettingsError::UnknownArg(arg) => write!(f, "unknown argument `{}`", arg),
+        }
+    }
+}
+
+impl GeneratorKind {
+    pub const ALL: [GeneratorKind; 5] = [
+        GeneratorKind::Builder,
+        GeneratorKind::Assembled,
+        GeneratorKind::Biomes,
+        GeneratorKind::Flat,
+        GeneratorKind::Blank,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GeneratorKind::Builder => "builder",
+            GeneratorKind::Assembled => "assembled",
+            GeneratorKind::Biomes => "biomes",
+            GeneratorKind::Flat => "flat",
+            GeneratorKind::Blank => "blank",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<GeneratorKind> {
+        GeneratorKind::ALL.into_iter().find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+
+    fn names() -> String {
+        GeneratorKind::ALL.map(|kind| kind.name()).join(", ")
+    }
+
+    // The next generator in ALL, wrapping around
+    pub fn cycle(&self) -> GeneratorKind {
+        let index = GeneratorKind::ALL.iter().position(|kind| kind == self).unwrap_or(0);
+        GeneratorKind::ALL[(index + 1) % GeneratorKind::ALL.len()]
+    }
+
+    pub fn params(&self) -> &'static [WorldGenParam] {
+        match self {
+            GeneratorKind::Builder => &[
+                WorldGenParam::TerrainScale,
+                WorldGenParam::TerrainHeight,
+                WorldGenParam::CaveScale,
+                WorldGenParam::LavaDepth,
+                WorldGenParam::LavaScale,
+            ],
+            GeneratorKind::Biomes => &[
+                WorldGenParam::BiomeScale,
+                WorldGenParam::TerrainScale,
+                WorldGenParam::TerrainHeight,
+                WorldGenParam::SeaLevel,
+                WorldGenParam::CaveScale,
+                WorldGenParam::CaveOpenness,
+            ],
+            GeneratorKind::Assembled | GeneratorKind::Flat | GeneratorKind::Blank => &[],
+        }
+    }
+}
+
+impl WorldGenParam {
+    pub const ALL: [WorldGenParam; 8] = [
+        WorldGenParam::TerrainScale,
+        WorldGenParam::TerrainHeight,
+        WorldGenParam::CaveScale,
+        WorldGenParam::CaveOpenness,
+        WorldGenParam::LavaDepth,
+        WorldGenParam::LavaScale,
+        WorldGenParam::BiomeScale,
+        WorldGenParam::SeaLevel,
+    ];
+
+    // Name used in settings files
+    pub fn key(&self) -> &'static str {
+        match self {
+            WorldGenParam::TerrainScale => "terrain_scale",
+            WorldGenParam::TerrainHeight => "terrain_height",
+            WorldGenParam::CaveScale => "cave_scale",
+            WorldGenParam::CaveOpenness => "cave_openness",
+            WorldGenParam::LavaDepth => "lava_depth",
+            WorldGenParam::LavaScale => "lava_scale",
+            WorldGenParam::BiomeScale => "biome_scale",
+            WorldGenParam::SeaLevel => "sea_level",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WorldGenParam::TerrainScale => "Terrain scale",
+            WorldGenParam::TerrainHeight => "Terrain height",
+            WorldGenParam::CaveScale => "Cave scale",
+            WorldGenParam::CaveOpenness => "Cave openness",
+            WorldGenParam::LavaDepth => "Lava depth",
+            WorldGenParam::LavaScale => "Lava scale",
+            WorldGenParam::BiomeScale => "Biome scale",
+            WorldGenParam::SeaLevel => "Sea level",
+        }
+    }
+
+    // Slider limits, settings files may go outside them
+    pub fn range(&self) -> (f64, f64) {
+        match self {
+            WorldGenParam::TerrainScale => (500., 10000.),
+            WorldGenParam::TerrainHeight => (100., 3000.),
+            WorldGenParam::CaveScale => (100., 1500.),
+            WorldGenParam::CaveOpenness => (0., 0.3),
+            WorldGenParam::LavaDepth => (100., 2000.),
+            WorldGenParam::LavaScale => (100., 1500.),
+            WorldGenParam::BiomeScale => (1000., 20000.),
+            WorldGenParam::SeaLevel => (-1000., 1000.),
+        }
+    }
+}
+
+impl Default for WorldGenSettings {
+    fn default() -> Self {
+        WorldGenSettings {
+            seed: rand::random(),
+            generator: GeneratorKind::Builder,
+            terrain_scale: 5000.,
+            terrain_height: 1500.,
+            cave_scale: 500.,
+            cave_openness: 0.09,
+            lava_depth: 500.,
+            lava_scale: 400.,
+            biome_scale: 6000.,
+            sea_level: 0.,
+        }
+    }
+}
+
+impl WorldGenSettings {
+    // Falls back to the defaults if the file is missing, other problems are reported
+    pub fn load(path: &str) -> Result<Self, SettingsError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(WorldGenSettings::default()),
+            Err(err) => Err(SettingsError::Read(err)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, SettingsError> {
+        let mut settings = WorldGenSettings::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(SettingsError::BadLine(line.to_string()));
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "seed" => {
+                    if value != "random" {
+                        settings.seed = value.parse().map_err(|_| SettingsError::BadValue(line.to_string()))?;
+                    }
+                }
+                "generator" => {
+                    settings.generator = GeneratorKind::from_name(value).ok_or_else(|| SettingsError::UnknownGenerator(value.to_string()))?;
+                }
+                _ => {
+                    let param = WorldGenParam::ALL.into_iter().find(|param| param.key() == key)
+                        .ok_or_else(|| SettingsError::UnknownKey(key.to_string()))?;
+                    let value = value.parse().map_err(|_| SettingsError::BadValue(line.to_string()))?;
+                    settings.set(param, value);
+                }
+            }
+        }
+
+        Ok(settings)
+    }
+
+    // Apply `--seed <seed>` and `--generator <name>` from the command line
+    // Every argument is checked first, so a bad one leaves the settings untouched
+    pub fn apply_args(&mut self, args: impl IntoIterator<Item = String>) -> Result<(), SettingsError> {
+        let mut seed = None;
+        let mut generator = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let value = args.next().ok_or_else(|| SettingsError::MissingArgValue(arg.clone()))?;
+                    seed = Some(value.parse().map_err(|_| SettingsError::BadValue(format!("{} {}", arg, value)))?);
+                }
+                "--generator" => {
+                    let value = args.next().ok_or_else(|| SettingsError::MissingArgValue(arg.clone()))?;
+                    generator = Some(GeneratorKind::from_name(&value).ok_or(SettingsError::UnknownGenerator(value))?);
+                }
+                _ => return Err(SettingsError::UnknownArg(arg)),
+            }
+        }
+
+        if let Some(seed) = seed {
+            self.seed = seed;
+        }
+        if let Some(generator) = generator {
+            self.generator = generator;
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, param: WorldGenParam) -> f64 {
+        match param {
+            WorldGenParam::TerrainScale => self.terrain_scale,
+            WorldGenParam::TerrainHeight => self.terrain_height,
+            WorldGenParam::CaveScale => self.cave_scale,
+            WorldGenParam::CaveOpenness => self.cave_openness,
+            WorldGenParam::LavaDepth => self.lava_depth,
+            WorldGenParam::LavaScale => self.lava_scale,
+            WorldGenParam::BiomeScale => self.biome_scale,
+            WorldGenParam::SeaLevel => self.sea_level,
+        }
+    }
+
+    pub fn set(&mut self, param: WorldGenParam, value: f64) {
+        match param {
+            WorldGenParam::TerrainScale => self.terrain_scale = value,
+            WorldGenParam::TerrainHeight => self.terrain_height = value,
+            WorldGenParam::CaveScale => self.cave_scale = value,
+            WorldGenParam::CaveOpenness => self.cave_openness = value,
+            WorldGenParam::LavaDepth => self.lava_depth = value,
+            WorldGenParam::LavaScale => self.lava_scale = value,
+            WorldGenParam::BiomeScale => self.biome_scale = value,
+            WorldGenParam::SeaLevel => self.sea_level = value,
+        }
+    }
+
+    // The biomes generator on its own, so the biome under a cell can be looked up as well
+    pub fn build_biome_world(&self) -> BiomeWorld {
+        BiomeWorld::new(self.seed, Biome::default_table())
+            .with_biome_scale(self.biome_scale, 0.08)
+            .with_terrain(self.terrain_scale, self.terrain_height, self.sea_level as i32)
+            .with_caves(self.cave_scale, self.cave_openness)
+    }
+
+    pub fn build_generator(&self) -> Arc<dyn WorldGenerator + Send + Sync> {
+        match self.generator {
+            GeneratorKind::Builder => Arc::new(WorldBuilder::new(
+                self.seed, self.terrain_scale, self.terrain_height, self.cave_scale, self.lava_depth, self.lava_scale,
+            )),
+            GeneratorKind::Assembled => Arc::new(assembled_world(self.seed)),
+            GeneratorKind::Biomes => Arc::new(self.build_biome_world()),
+            GeneratorKind::Flat => Arc::new(FlatPlain { stone_height: 0, sand_height: 64 }),
+            GeneratorKind::Blank => Arc::new(Blankworld {}),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::worldgen_settings::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_reads_keys_and_skips_comments() {
+        let settings = WorldGenSettings::parse("
+            # a comment
+            generator = Biomes
+            seed = 42
+            terrain_height = 800
+        ").unwrap();
+
+        assert_eq!(settings.generator, GeneratorKind::Biomes);
+        assert_eq!(settings.seed, 42);
+        assert_eq!(settings.get(WorldGenParam::TerrainHeight), 800.);
+        assert_eq!(settings.get(WorldGenParam::CaveScale), WorldGenSettings::default().cave_scale);
+    }
+
+    #[test]
+    fn parse_rejects_bad_lines() {
+        assert!(matches!(WorldGenSettings::parse("seed"), Err(SettingsError::BadLine(_))));
+        assert!(matches!(WorldGenSettings::parse("seed = abc"), Err(SettingsError::BadValue(_))));
+        assert!(matches!(WorldGenSettings::parse("mountains = 3"), Err(SettingsError::UnknownKey(_))));
+        assert!(matches!(WorldGenSettings::parse("generator = moon"), Err(SettingsError::UnknownGenerator(_))));
+    }
+
+    #[test]
+    fn apply_args_sets_seed_and_generator() {
+        let mut settings = WorldGenSettings::default();
+        settings.apply_args(args(&["--seed", "7", "--generator", "flat"])).unwrap();
+        assert_eq!(settings.seed, 7);
+        assert_eq!(settings.generator, GeneratorKind::Flat);
+    }
+
+    #[test]
+    fn apply_args_changes_nothing_on_error() {
+        let mut settings = WorldGenSettings::default();
+        let (seed, generator) = (settings.seed, settings.generator);
+
+        let result = settings.apply_args(args(&["--generator", "flat", "--seed", "7", "--fast"]));
+        assert!(matches!(result, Err(SettingsError::UnknownArg(_))));
+        assert!(matches!(settings.apply_args(args(&["--seed", "7", "--generator"])), Err(SettingsError::MissingArgValue(_))));
+        assert!(matches!(settings.apply_args(args(&["--seed", "7", "--generator", "moon"])), Err(SettingsError::UnknownGenerator(_))));
+
+        assert_eq!(settings.seed, seed);
+        assert_eq!(settings.generator, generator);
+    }
+}